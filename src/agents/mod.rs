@@ -1,24 +1,42 @@
+use crate::agents::planner::{PlannerDecision, RouteCategory};
 use crate::agents::profiles::*;
+use crate::api_keys::ApiKeyManager;
 use crate::builtin_commands::BuiltinCommands;
+use crate::cancellation::{is_cancelled, CancellationToken};
 use crate::config::Config;
 use crate::context::ContextGatherer;
-use crate::history::{ContextPriority, ContextWindow, History};
+use crate::error_handling::display_warning;
+use crate::execution::CommandOutcome;
+use crate::history::{ChatTurn, ContextPriority, ContextWindow, History};
 use crate::intent::{IntentAnalysis, IntentClassifier, UserIntent};
-use crate::os_context::OSContext;
+use crate::logging::{get_logger, LogCategory};
+use crate::os_context::{ExecutionShell, OSContext};
 use crate::performance::{OperationType, PerformanceMonitor, SystemPerformanceSummary};
+use crate::provider_health::{BreakerRecord, ProviderHealthStore};
+use crate::providers::is_budget_exceeded;
 use crate::providers::{
-    CircuitBreakerState, CloudProvider, OllamaProvider, ProviderManager, ProviderType,
+    AnthropicProvider, CircuitBreakerState, CloudProvider, CohereProvider, Conversation,
+    GeminiProvider, OllamaProvider, OpenAICompatibleProvider, ProviderManager, ProviderType,
 };
+use crate::replay::{
+    ReplayFixtures, ReplayMode, ReplayProvider, ReplaySettings, REPLAY_FIXTURE_ENV,
+};
+use crate::response_cache::{stable_hash, ResponseCache};
+use crate::usage::{PriceTable, UsageLedger, UsageTracker};
 use crate::validation::{CommandValidator, DefaultCommandValidator, ValidationResult};
 use anyhow::{anyhow, Result};
-use serde_json::json;
 use std::env;
 use std::fs::OpenOptions;
 use std::io::Write;
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
+pub mod planner;
 pub mod profiles;
 
+/// Callback receiving streamed text chunks of an agent answer
+pub type ChunkHandler = Box<dyn FnMut(&str) + Send>;
+
 pub struct Orchestrator {
     config: Config,
     history: History,
@@ -28,11 +46,15 @@ pub struct Orchestrator {
     context_gatherer: ContextGatherer,
     intent_classifier: IntentClassifier,
     provider_manager: ProviderManager,
+    stream_handler: Option<ChunkHandler>,
 }
 
 impl Orchestrator {
     pub fn new(config: Config, history: History) -> Self {
-        let os_context = OSContext::detect();
+        // The ShellExpert writes for, and the validator checks, the shell commands run in
+        let shell = ExecutionShell::resolve(config.shell.as_deref());
+        let mut os_context = OSContext::detect();
+        os_context.shell = shell.kind.clone();
         let context_gatherer = ContextGatherer::new(&config);
 
         // Initialize provider manager with local-first architecture
@@ -41,38 +63,229 @@ impl Orchestrator {
         // Use the configured timeout for providers, with a reasonable minimum of 10s
         let timeout = std::time::Duration::from_millis(std::cmp::max(config.ai_timeout, 10000)); // Min 10 seconds
 
-        // Always add local Ollama provider (offline functionality)
-        let ollama_provider =
-            OllamaProvider::with_timeout(config.ollama_url.clone(), config.model.clone(), timeout);
-        provider_manager.add_provider(Box::new(ollama_provider));
+        // Always add local Ollama provider (offline functionality): the configured
+        // endpoints, or the single `ollama_url`
+        if config.ollama_endpoints.is_empty() {
+            let ollama_provider = OllamaProvider::with_timeout(
+                config.ollama_url.clone(),
+                config.model.clone(),
+                timeout,
+            );
+            provider_manager.add_provider(Box::new(ollama_provider));
+        } else {
+            for endpoint in &config.ollama_endpoints {
+                provider_manager.add_named_provider(
+                    endpoint.name.clone(),
+                    Box::new(OllamaProvider::with_timeout(
+                        endpoint.url.clone(),
+                        config.model.clone(),
+                        timeout,
+                    )),
+                );
+                if !endpoint.models.is_empty() {
+                    provider_manager.set_instance_models(&endpoint.name, endpoint.models.clone());
+                }
+            }
+        }
+        provider_manager.set_routing(config.ollama_routing);
 
         // Add cloud provider if configured
-        if let Some(token) = &config.api_token {
-            let cloud_provider = CloudProvider::new(config.backend_url.clone(), token.clone());
+        let mut fallback_chain = if let Some(token) = config.cloud_token() {
+            let cloud_provider = CloudProvider::new(
+                config.backend_url.clone(),
+                token,
+                config.cloud_model.clone(),
+            );
             provider_manager.add_provider(Box::new(cloud_provider));
 
             // If cloud is enabled, prioritize it
             if config.use_cloud {
-                provider_manager.set_fallback_chain(vec![ProviderType::Cloud, ProviderType::Local]);
+                vec![ProviderType::Cloud, ProviderType::Local]
             } else {
-                provider_manager.set_fallback_chain(vec![ProviderType::Local, ProviderType::Cloud]);
+                vec![ProviderType::Local, ProviderType::Cloud]
             }
         } else {
-            provider_manager.set_fallback_chain(vec![ProviderType::Local]);
+            vec![ProviderType::Local]
+        };
+
+        // Add the direct OpenAI-compatible provider when a model is configured.
+        // The key is optional so keyless local servers (llama.cpp, vLLM, LM Studio) work too.
+        let key_manager = ApiKeyManager::from_config(&config.api_keys);
+        if let Some(model) = &config.openai_model {
+            let api_key = key_manager.get_key("openai").ok();
+            let openai_provider = OpenAICompatibleProvider::with_timeout(
+                config.openai_base_url.clone(),
+                api_key,
+                model.clone(),
+                timeout,
+            );
+            provider_manager.add_provider(Box::new(openai_provider));
+            Self::insert_direct_provider(&mut fallback_chain, ProviderType::OpenAI);
+        }
+
+        // Add the keyed direct providers when a model is configured and a key is stored
+        if let Some(model) = &config.anthropic_model {
+            if let Some(api_key) = Self::stored_key(&key_manager, "anthropic", "anthropic_model") {
+                provider_manager.add_provider(Box::new(AnthropicProvider::with_timeout(
+                    config.anthropic_base_url.clone(),
+                    api_key,
+                    model.clone(),
+                    timeout,
+                )));
+                Self::insert_direct_provider(&mut fallback_chain, ProviderType::Anthropic);
+            }
+        }
+
+        if let Some(model) = &config.gemini_model {
+            if let Some(api_key) = Self::stored_key(&key_manager, "google", "gemini_model") {
+                provider_manager.add_provider(Box::new(GeminiProvider::with_timeout(
+                    config.gemini_base_url.clone(),
+                    api_key,
+                    model.clone(),
+                    timeout,
+                )));
+                Self::insert_direct_provider(&mut fallback_chain, ProviderType::Gemini);
+            }
+        }
+
+        if let Some(model) = &config.cohere_model {
+            if let Some(api_key) = Self::stored_key(&key_manager, "cohere", "cohere_model") {
+                provider_manager.add_provider(Box::new(CohereProvider::with_timeout(
+                    config.cohere_base_url.clone(),
+                    api_key,
+                    model.clone(),
+                    timeout,
+                )));
+                Self::insert_direct_provider(&mut fallback_chain, ProviderType::Cohere);
+            }
+        }
+
+        provider_manager.set_fallback_chain(fallback_chain);
+        provider_manager
+            .set_first_token_timeout(std::time::Duration::from_millis(config.first_token_timeout));
+        provider_manager.set_hedge_delay(config.hedge_delay.map(std::time::Duration::from_millis));
+
+        let replay = Self::replay_settings(&config);
+        if let Some(settings) = &replay {
+            match ReplayFixtures::load(&settings.fixture_path) {
+                Ok(fixtures) => {
+                    let fixtures = Arc::new(Mutex::new(fixtures));
+                    let mode = settings.mode;
+                    provider_manager.wrap_providers(|provider| {
+                        Box::new(ReplayProvider::new(provider, mode, fixtures.clone()))
+                    });
+                }
+                Err(e) => display_warning(&format!("Record/replay disabled: {}", e)),
+            }
+        }
+        let replaying = matches!(&replay, Some(s) if s.mode == ReplayMode::Replay);
+
+        // Unit tests build many orchestrators; keep them away from the user's health
+        // and usage files. Replayed responses are not real traffic either.
+        if !cfg!(test) && !replaying {
+            if let Ok(store) = ProviderHealthStore::new() {
+                provider_manager.enable_health_persistence(store);
+            }
+            if let Ok(ledger) = UsageLedger::new() {
+                provider_manager.enable_usage_tracking(UsageTracker::new(
+                    ledger,
+                    PriceTable::new(&config.price_table),
+                    config.monthly_budget_usd,
+                ));
+            }
+        }
+
+        // Cached answers would bypass recording and replaying
+        if config.cache_enabled && replay.is_none() {
+            provider_manager.enable_cache(ResponseCache::load(
+                config.cache_max_entries,
+                std::time::Duration::from_secs(config.cache_ttl),
+            ));
         }
 
         Self {
             config,
             history,
-            validator: DefaultCommandValidator::new(),
+            validator: DefaultCommandValidator::new().with_shell(shell.kind),
             builtin_commands: BuiltinCommands::new(),
             os_context,
             context_gatherer,
             intent_classifier: IntentClassifier::new(),
             provider_manager,
+            stream_handler: None,
+        }
+    }
+
+    /// Record/replay settings when a mode other than passthrough is selected. An invalid
+    /// `CLIAI_PROVIDER_MODE` is reported and the configured mode is used instead.
+    fn replay_settings(config: &Config) -> Option<ReplaySettings> {
+        let settings = ReplaySettings::from_config(config).or_else(|e| {
+            display_warning(&format!("{}; using provider_mode from the config", e));
+            ReplaySettings::resolve(
+                config.provider_mode,
+                config.replay_fixture.as_deref(),
+                None,
+                env::var(REPLAY_FIXTURE_ENV).ok().as_deref(),
+            )
+        });
+
+        match settings {
+            Ok(settings) if settings.mode != ReplayMode::Passthrough => Some(settings),
+            Ok(_) => None,
+            Err(e) => {
+                display_warning(&format!("Record/replay disabled: {}", e));
+                None
+            }
+        }
+    }
+
+    /// Direct APIs follow local Ollama, in the order they were added
+    fn insert_direct_provider(chain: &mut Vec<ProviderType>, provider_type: ProviderType) {
+        let position = chain
+            .iter()
+            .rposition(|t| *t != ProviderType::Cloud)
+            .map(|i| i + 1)
+            .unwrap_or(chain.len());
+        chain.insert(position, provider_type);
+    }
+
+    /// API key of a direct provider from the key chain. A configured model without a
+    /// key is logged and the provider is left out.
+    fn stored_key(
+        key_manager: &ApiKeyManager,
+        provider: &str,
+        model_setting: &str,
+    ) -> Option<String> {
+        match key_manager.get_key(provider) {
+            Ok(api_key) => Some(api_key),
+            Err(e) => {
+                if let Ok(logger) = get_logger() {
+                    if let Ok(logger_guard) = logger.lock() {
+                        let _ = logger_guard.log_warning(
+                            LogCategory::Authentication,
+                            format!("{} is set but {}", model_setting, e),
+                            None,
+                        );
+                    }
+                }
+                None
+            }
         }
     }
 
+    /// Receive the final agent answer incrementally. The handler is called with each
+    /// text chunk of the first answer attempt; planner calls and format retries are not
+    /// streamed, so callers should compare the streamed text with the returned response.
+    pub fn set_stream_handler(&mut self, handler: ChunkHandler) {
+        self.stream_handler = Some(handler);
+    }
+
+    /// Stop in-flight provider requests when `token` is cancelled (e.g. by Ctrl-C);
+    /// `process` then fails with [`Cancelled`](crate::cancellation::Cancelled)
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.provider_manager.set_cancellation(token);
+    }
+
     fn log_activity(&self, activity: &str) {
         if let Some(mut path) = dirs::config_dir() {
             path.push("cliai");
@@ -106,6 +319,11 @@ impl Orchestrator {
         self.log_activity(&format!("User Prompt: {}", prompt));
         self.show_work(&format!("Processing prompt: '{}'", prompt));
 
+        // Cached answers are only valid for the directory they were generated in
+        let working_dir = env::current_dir().unwrap_or_default().display().to_string();
+        self.provider_manager
+            .set_cache_context(stable_hash(&working_dir));
+
         // 1. Classify user intent FIRST (before any processing)
         let intent_start = Instant::now();
         let intent_analysis = self.intent_classifier.classify_intent(prompt);
@@ -220,50 +438,21 @@ impl Orchestrator {
         let analysis = self.analyze_request(prompt).await?;
         let analysis_duration = analysis_start.elapsed();
 
-        let category = analysis["category"]
-            .as_str()
-            .unwrap_or("GENERAL")
-            .to_uppercase();
-        let commands = analysis["commands"].as_array();
+        let category = analysis.category;
+        let context_commands = analysis.commands;
 
         self.log_activity(&format!(
             "Detected Category: {} - analysis took {}ms",
-            category,
+            category.as_str(),
             analysis_duration.as_millis()
         ));
         self.show_work(&format!(
             "Route category: {} ({}ms)",
-            category,
+            category.as_str(),
             analysis_duration.as_millis()
         ));
 
         // 7. Gather Context using the new safe context gathering system
-        let mut context_commands = Vec::new();
-        if let Some(cmds) = commands {
-            for cmd_val in cmds {
-                if let Some(cmd) = cmd_val.as_str() {
-                    // Map command suggestions to whitelisted context commands
-                    let context_cmd = match cmd {
-                        cmd if cmd.starts_with("ls") => Some("ls-current".to_string()),
-                        cmd if cmd.starts_with("pwd") => Some("pwd".to_string()),
-                        cmd if cmd.starts_with("whoami") => Some("whoami".to_string()),
-                        cmd if cmd.starts_with("hostname") => Some("hostname".to_string()),
-                        cmd if cmd.starts_with("uname") => Some("uname".to_string()),
-                        cmd if cmd.starts_with("cat /etc/os-release") => {
-                            Some("os-release".to_string())
-                        }
-                        cmd if cmd.starts_with("git status") => Some("git-status".to_string()),
-                        cmd if cmd.starts_with("git branch") => Some("git-branch".to_string()),
-                        _ => None,
-                    };
-
-                    if let Some(ctx_cmd) = context_cmd {
-                        context_commands.push(ctx_cmd);
-                    }
-                }
-            }
-        }
-
         if !context_commands.is_empty() {
             self.show_work(&format!("Context commands: {:?}", context_commands));
         }
@@ -300,26 +489,34 @@ impl Orchestrator {
             self.show_work("No system context gathered");
         }
 
+        // Final answers also depend on the gathered context (file listings, git state, ...)
+        self.provider_manager
+            .set_cache_context(stable_hash(&format!("{}\n{}", working_dir, context_str)));
+
         // 8. Select appropriate agent and context window
-        let (agent, context_window) = match category.as_str() {
-            "SHELL" => (&SHELL_EXPERT, ContextWindow::shell_expert()),
-            "CODE" => (&CODE_EXPERT, ContextWindow::specialized_agent()),
-            "LOG" => (&LOG_EXPERT, ContextWindow::specialized_agent()),
-            _ => (&GENERAL_CLIAI, ContextWindow::general_agent()),
+        let (agent, context_window) = match category {
+            RouteCategory::Shell => (&SHELL_EXPERT, ContextWindow::shell_expert()),
+            RouteCategory::Code => (&CODE_EXPERT, ContextWindow::specialized_agent()),
+            RouteCategory::Log => (&LOG_EXPERT, ContextWindow::specialized_agent()),
+            RouteCategory::General => (&GENERAL_CLIAI, ContextWindow::general_agent()),
         };
         self.show_work(&format!("Agent: {}", agent.name));
 
         // Note: provider_manager may still fail over; this is the preferred mode.
-        let preferred_provider = if self.config.use_cloud && self.config.api_token.is_some() {
+        let has_cloud = !self
+            .provider_manager
+            .get_providers_by_type(&ProviderType::Cloud)
+            .is_empty();
+        let preferred_provider = if self.config.use_cloud && has_cloud {
             "Cloud (Pro)"
         } else {
             "Local (Ollama)"
         };
         self.show_work(&format!("Preferred provider: {}", preferred_provider));
 
-        // 9. Build final prompt with appropriate context and intent information
+        // 9. Build final conversation with appropriate context and intent information
         let prompt_build_start = Instant::now();
-        let final_prompt = self.build_agent_prompt_with_intent(
+        let conversation = self.build_agent_conversation_with_intent(
             agent,
             prompt,
             &context_window,
@@ -329,21 +526,23 @@ impl Orchestrator {
         let prompt_build_duration = prompt_build_start.elapsed();
 
         self.log_activity(&format!(
-            "Built final prompt: {} chars - took {}ms",
-            final_prompt.len(),
+            "Built final prompt: {} chars, {} history turns - took {}ms",
+            conversation.system.len() + conversation.user.len(),
+            conversation.history.len(),
             prompt_build_duration.as_millis()
         ));
         self.show_work(&format!(
-            "Final prompt built: {} chars ({}ms)",
-            final_prompt.len(),
+            "Final prompt built: {} chars, {} history turns ({}ms)",
+            conversation.system.len() + conversation.user.len(),
+            conversation.history.len(),
             prompt_build_duration.as_millis()
         ));
 
         // 10. Generate Response with format validation for ShellExpert
         let ai_start = Instant::now();
-        let response = if category == "SHELL" {
+        let response = if category == RouteCategory::Shell {
             self.show_work("Using ShellExpert with validation");
-            let initial_response = self.call_ollama_with_prompt(&final_prompt, agent).await?;
+            let initial_response = self.call_ollama_streaming(&conversation, agent).await?;
             let ai_initial_duration = ai_start.elapsed();
             self.show_work(&format!(
                 "Initial AI response: {} chars ({}ms)",
@@ -385,7 +584,7 @@ impl Orchestrator {
             validated_response
         } else {
             self.show_work(&format!("Using {} agent", agent.name));
-            self.call_ollama_with_prompt(&final_prompt, agent).await?
+            self.call_ollama_streaming(&conversation, agent).await?
         };
 
         let total_ai_duration = ai_start.elapsed();
@@ -409,58 +608,55 @@ impl Orchestrator {
         Ok(response)
     }
 
-    /// Build agent prompt with appropriate context window and system context
-    fn build_agent_prompt(
+    /// Build the agent conversation with appropriate context window and system context
+    fn build_agent_conversation(
         &self,
         agent: &AgentProfile,
         user_prompt: &str,
         context_window: &ContextWindow,
         system_context: &str,
-    ) -> String {
-        let mut full_prompt = agent.system_prompt.to_string();
+    ) -> Conversation {
+        let mut system_prompt = agent.system_prompt.to_string();
+        self.append_system_context(&mut system_prompt, system_context);
+
+        // Conversation history is sent as real chat turns based on the context window
+        Conversation::new(system_prompt, user_prompt)
+            .with_history(self.history.get_context_for_agent(context_window))
+    }
 
+    /// Append OS information and gathered system state to a system message
+    fn append_system_context(&self, system_prompt: &mut String, system_context: &str) {
         // Add OS context information to all agents
-        full_prompt.push_str("\n\nSYSTEM CONTEXT:\n");
-        full_prompt.push_str(&format!(
+        system_prompt.push_str("\n\nSYSTEM CONTEXT:\n");
+        system_prompt.push_str(&format!(
             "Operating System: {} ({})\n",
             self.os_context.version_info, self.os_context.architecture
         ));
-        full_prompt.push_str(&format!(
+        system_prompt.push_str(&format!(
             "Package Manager: {:?}\n",
             self.os_context.package_manager
         ));
-        full_prompt.push_str(&format!("Shell: {:?}\n", self.os_context.shell));
-        full_prompt.push_str(&format!(
+        system_prompt.push_str(&format!("Shell: {:?}\n", self.os_context.shell));
+        system_prompt.push_str(&format!(
             "Working Directory: {}\n",
             std::env::current_dir().unwrap_or_default().display()
         ));
 
         // Add gathered system context if available
         if !system_context.is_empty() {
-            full_prompt.push_str(&format!("\nCURRENT SYSTEM STATE:\n{}\n", system_context));
-        }
-
-        // Add conversation history based on context window
-        if !self.history.is_empty() {
-            let history_context = self.history.format_for_prompt(context_window);
-            if !history_context.is_empty() {
-                full_prompt.push_str(&format!("\n{}", history_context));
-            }
+            system_prompt.push_str(&format!("\nCURRENT SYSTEM STATE:\n{}\n", system_context));
         }
-
-        full_prompt.push_str(&format!("\n\nUser: {}", user_prompt));
-        full_prompt
     }
 
-    /// Build agent prompt with intent analysis information
-    fn build_agent_prompt_with_intent(
+    /// Build the agent conversation with intent analysis information
+    fn build_agent_conversation_with_intent(
         &self,
         agent: &AgentProfile,
         user_prompt: &str,
         context_window: &ContextWindow,
         system_context: &str,
         intent_analysis: &IntentAnalysis,
-    ) -> String {
+    ) -> Conversation {
         let mut full_prompt = agent.system_prompt.to_string();
 
         // Add intent analysis information for ShellExpert
@@ -498,46 +694,41 @@ impl Orchestrator {
             }
         }
 
-        // Add OS context information to all agents
-        full_prompt.push_str("\n\nSYSTEM CONTEXT:\n");
-        full_prompt.push_str(&format!(
-            "Operating System: {} ({})\n",
-            self.os_context.version_info, self.os_context.architecture
-        ));
-        full_prompt.push_str(&format!(
-            "Package Manager: {:?}\n",
-            self.os_context.package_manager
-        ));
-        full_prompt.push_str(&format!("Shell: {:?}\n", self.os_context.shell));
-        full_prompt.push_str(&format!(
-            "Working Directory: {}\n",
-            std::env::current_dir().unwrap_or_default().display()
-        ));
+        self.append_system_context(&mut full_prompt, system_context);
 
-        // Add gathered system context if available
-        if !system_context.is_empty() {
-            full_prompt.push_str(&format!("\nCURRENT SYSTEM STATE:\n{}\n", system_context));
-        }
-
-        // Add conversation history based on context window
-        if !self.history.is_empty() {
-            let history_context = self.history.format_for_prompt(context_window);
-            if !history_context.is_empty() {
-                full_prompt.push_str(&format!("\n{}", history_context));
-            }
-        }
-
-        full_prompt.push_str(&format!("\n\nUser: {}", user_prompt));
-        full_prompt
+        // Conversation history is sent as real chat turns based on the context window
+        Conversation::new(full_prompt, user_prompt)
+            .with_history(self.history.get_context_for_agent(context_window))
     }
 
-    /// Execute Ollama call with pre-built prompt
+    /// Execute Ollama call with a pre-built conversation
     async fn call_ollama_with_prompt(
         &mut self,
-        full_prompt: &str,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+    ) -> Result<String> {
+        self.execute_ollama_call(conversation, agent).await
+    }
+
+    /// Execute the final agent call, forwarding chunks to the stream handler if one is set
+    async fn call_ollama_streaming(
+        &mut self,
+        conversation: &Conversation,
         agent: &AgentProfile,
     ) -> Result<String> {
-        self.execute_ollama_call(full_prompt, agent).await
+        let Some(mut handler) = self.stream_handler.take() else {
+            return self.execute_ollama_call(conversation, agent).await;
+        };
+
+        let agent = self.configured_agent(agent);
+        let timeout = std::time::Duration::from_millis(self.config.ai_timeout);
+        let result = self
+            .provider_manager
+            .get_response_streaming(conversation, &agent, timeout, &mut *handler)
+            .await;
+        self.stream_handler = Some(handler);
+
+        result.map_err(|e| self.provider_unavailable_error(e))
     }
 
     /// Validate ShellExpert response format and retry if needed
@@ -577,11 +768,11 @@ Original request: {}",
 
             // Build full prompt with context for retry
             let context_window = ContextWindow::shell_expert();
-            let full_retry_prompt =
-                self.build_agent_prompt(&SHELL_EXPERT, &retry_prompt, &context_window, "");
+            let retry_conversation =
+                self.build_agent_conversation(&SHELL_EXPERT, &retry_prompt, &context_window, "");
 
             let retry_response = self
-                .call_ollama_with_prompt(&full_retry_prompt, &SHELL_EXPERT)
+                .call_ollama_with_prompt(&retry_conversation, &SHELL_EXPERT)
                 .await?;
 
             // Use Box::pin to handle async recursion
@@ -598,6 +789,95 @@ Original request: {}",
         Ok(response.to_string())
     }
 
+    /// Ask the ShellExpert for a corrected command after `command` failed. The failure
+    /// report is sent as a follow-up turn after the current history.
+    pub async fn propose_fix(
+        &mut self,
+        original_prompt: &str,
+        command: &str,
+        outcome: &CommandOutcome,
+    ) -> Result<String> {
+        self.log_activity(&format!(
+            "Command failed with {}, asking ShellExpert for a fix",
+            outcome.status_text()
+        ));
+        let fix_prompt = outcome.fix_request(original_prompt, command);
+
+        let conversation = self.build_agent_conversation(
+            &SHELL_EXPERT,
+            &fix_prompt,
+            &ContextWindow::shell_expert(),
+            "",
+        );
+        // The same failure should always get a fresh answer
+        self.provider_manager
+            .forget_cached_response(&conversation, &SHELL_EXPERT);
+        // Not streamed: the renderer has already finished the first answer
+        let response = self
+            .execute_ollama_call(&conversation, &SHELL_EXPERT)
+            .await?;
+        self.validate_and_retry_shell_response(&fix_prompt, &response, 1)
+            .await
+    }
+
+    /// Ask for a different command than `command` for the same request. Repeated asks
+    /// bypass the cache so each one can come back with something new.
+    pub async fn propose_alternative(
+        &mut self,
+        original_prompt: &str,
+        command: &str,
+    ) -> Result<String> {
+        self.log_activity("Asking ShellExpert for an alternative command");
+        let alternative_prompt = format!(
+            "Suggest a different way to do this.
+
+Original request: {}
+Command to avoid: {}
+
+Reply in the required format with a different command. If there is no other reasonable way, reply with \"Command: (none)\" and explain why.",
+            original_prompt, command
+        );
+
+        let conversation = self.build_agent_conversation(
+            &SHELL_EXPERT,
+            &alternative_prompt,
+            &ContextWindow::shell_expert(),
+            "",
+        );
+        self.provider_manager
+            .forget_cached_response(&conversation, &SHELL_EXPERT);
+        let response = self
+            .execute_ollama_call(&conversation, &SHELL_EXPERT)
+            .await?;
+        self.validate_and_retry_shell_response(&alternative_prompt, &response, 1)
+            .await
+    }
+
+    /// Explain what each part of a shell command does, one line per part
+    pub async fn explain_command(&mut self, command: &str) -> Result<String> {
+        self.log_activity("Explaining command before execution");
+        let explain_prompt = format!(
+            "Explain this shell command part by part before I run it. Give one short line per \
+             program, flag, argument, redirection or operator, in the order they appear, as \
+             \"`part` - what it does\". Point out anything destructive. Do not suggest other \
+             commands.\n\n{}",
+            command
+        );
+        let conversation = self.build_agent_conversation(
+            &GENERAL_CLIAI,
+            &explain_prompt,
+            &ContextWindow::general_agent(),
+            "",
+        );
+        self.execute_ollama_call(&conversation, &GENERAL_CLIAI)
+            .await
+    }
+
+    /// Replace the conversation history used for follow-up requests
+    pub fn set_history(&mut self, history: History) {
+        self.history = history;
+    }
+
     /// Check if a ShellExpert response follows the required format
     pub fn is_valid_shell_response(&self, response: &str) -> bool {
         let trimmed = response.trim();
@@ -664,27 +944,44 @@ Original request: {}",
         self.provider_manager.is_any_provider_available().await
     }
 
-    /// Check if local provider (Ollama) is available
+    /// Check if local provider (Ollama) is available on any endpoint
     pub async fn is_local_provider_available(&self) -> bool {
-        if let Some(provider) = self
+        for provider in self
             .provider_manager
-            .get_provider_by_type(&ProviderType::Local)
+            .get_providers_by_type(&ProviderType::Local)
         {
-            provider.is_available().await
-        } else {
-            false
+            if provider.is_available().await {
+                return true;
+            }
         }
+        false
     }
 
-    /// Try to list ONLY local models to debug availability issues
+    /// Try to list ONLY local models (from every Ollama endpoint) to debug availability issues
     pub async fn list_local_models(&self) -> Result<Vec<String>> {
-        if let Some(provider) = self
+        let providers = self
             .provider_manager
-            .get_provider_by_type(&ProviderType::Local)
-        {
-            provider.list_models().await
-        } else {
-            Err(anyhow!("Local provider not initialized"))
+            .get_providers_by_type(&ProviderType::Local);
+        if providers.is_empty() {
+            return Err(anyhow!("Local provider not initialized"));
+        }
+
+        let mut models = Vec::new();
+        let mut last_error = None;
+        for provider in providers {
+            match provider.list_models().await {
+                Ok(mut found) => models.append(&mut found),
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        match last_error {
+            Some(e) if models.is_empty() => Err(e),
+            _ => {
+                models.sort();
+                models.dedup();
+                Ok(models)
+            }
         }
     }
 
@@ -705,6 +1002,16 @@ Original request: {}",
         self.provider_manager.get_provider_status()
     }
 
+    /// Get the persisted health history of each provider instance
+    pub fn get_provider_health(&self) -> Vec<(String, ProviderType, BreakerRecord)> {
+        self.provider_manager.get_provider_health()
+    }
+
+    /// Time left until an open circuit breaker retries the provider instance
+    pub fn get_retry_after(&self, name: &str) -> Option<std::time::Duration> {
+        self.provider_manager.get_retry_after(name)
+    }
+
     /// Validate a command using the command validator with performance monitoring
     pub fn validate_command(&mut self, command: &str) -> ValidationResult {
         let validation_start = Instant::now();
@@ -776,66 +1083,133 @@ Original request: {}",
         self.provider_manager.list_models().await
     }
 
-    async fn analyze_request(&mut self, prompt: &str) -> Result<serde_json::Value> {
-        let response = self.call_ollama_no_history(&PLANNER_AGENT, prompt).await?;
+    /// Route the request with the Planner. The reply is requested as schema-constrained
+    /// JSON; a reply that still violates the schema gets one repair attempt before
+    /// falling back to GENERAL.
+    async fn analyze_request(&mut self, prompt: &str) -> Result<PlannerDecision> {
+        let mut command_ids = self.context_gatherer.get_available_commands();
+        command_ids.sort();
+
+        let conversation = self
+            .build_agent_conversation(&PLANNER_AGENT, prompt, &Self::planner_window(), "")
+            .with_response_schema(PlannerDecision::json_schema(&command_ids));
+        let reply = self
+            .execute_ollama_call(&conversation, &PLANNER_AGENT)
+            .await?;
+
+        let violation = match PlannerDecision::parse(&reply, &command_ids) {
+            Ok(decision) => return Ok(decision),
+            Err(e) => e,
+        };
 
-        if let Ok(json) = serde_json::from_str(&response) {
-            return Ok(json);
-        }
+        // Never replay an invalid routing decision from the cache
+        self.provider_manager
+            .forget_cached_response(&conversation, &PLANNER_AGENT);
+        self.log_activity(&format!(
+            "Planner reply violated schema ({}), requesting repair",
+            violation
+        ));
+        self.show_work(&format!("Planner schema violation: {}", violation));
+
+        let repair_request = format!(
+            "Your previous reply did not match the required JSON schema: {}\n\n\
+            Reply again with ONLY a JSON object of the form \
+            {{\"category\": \"SHELL\" | \"CODE\" | \"LOG\" | \"GENERAL\", \"commands\": [...]}} \
+            where commands are chosen from: {}",
+            violation,
+            command_ids.join(", ")
+        );
+        let repair = Conversation {
+            history: vec![
+                ChatTurn {
+                    role: "user".to_string(),
+                    content: prompt.to_string(),
+                },
+                ChatTurn {
+                    role: "assistant".to_string(),
+                    content: reply,
+                },
+            ],
+            user: repair_request,
+            ..conversation
+        };
+        let repaired = self.execute_ollama_call(&repair, &PLANNER_AGENT).await?;
 
-        // Fallback for messy JSON
-        if let (Some(start), Some(end)) = (response.find('{'), response.rfind('}')) {
-            if let Ok(json) = serde_json::from_str(&response[start..=end]) {
-                return Ok(json);
+        match PlannerDecision::parse(&repaired, &command_ids) {
+            Ok(decision) => Ok(decision),
+            Err(e) => {
+                self.provider_manager
+                    .forget_cached_response(&repair, &PLANNER_AGENT);
+                self.log_activity(&format!(
+                    "Planner repair failed ({}), routing to GENERAL",
+                    e
+                ));
+                Ok(PlannerDecision::default())
             }
         }
-
-        Ok(json!({"category": "GENERAL", "commands": []}))
     }
 
-    async fn call_ollama_no_history(
-        &mut self,
-        agent: &AgentProfile,
-        prompt: &str,
-    ) -> Result<String> {
-        let context_window = ContextWindow {
+    /// Context window for single-shot agents that see no conversation history
+    fn planner_window() -> ContextWindow {
+        ContextWindow {
             max_turns: 0,
             include_system_context: true,
             prioritize_recent: false,
             include_working_directory: false,
             context_priority: ContextPriority::Balanced,
-        };
-        let full_prompt = self.build_agent_prompt(agent, prompt, &context_window, "");
-        self.execute_ollama_call(&full_prompt, agent).await
+        }
     }
 
     async fn execute_ollama_call(
         &mut self,
-        full_prompt: &str,
+        conversation: &Conversation,
         agent: &AgentProfile,
     ) -> Result<String> {
+        let agent = self.configured_agent(agent);
+
         // Convert configured timeout (ms) to Duration
         let timeout = std::time::Duration::from_millis(self.config.ai_timeout);
 
         // Use provider manager for offline-first functionality
         match self
             .provider_manager
-            .get_response(full_prompt, agent, timeout)
+            .get_response_streaming(conversation, &agent, timeout, &mut |_| {})
             .await
         {
             Ok(response) => Ok(response),
-            Err(_e) => {
-                // Log the error for debugging
-                self.log_activity(&format!("Provider error: {}", _e));
-
-                // Check if any provider is available
-                Err(anyhow!(
-                    "Local AI provider unavailable. Please ensure:\n\
-                    1. Ollama is running at {} (for local mode)\n\
-                    2. The configured model is pulled",
-                    self.config.ollama_url
-                ))
-            }
+            Err(e) => Err(self.provider_unavailable_error(e)),
+        }
+    }
+
+    /// Apply the per-agent generation overrides from the config
+    fn configured_agent(&self, agent: &AgentProfile) -> AgentProfile {
+        match self.config.agent_params.get(agent.name) {
+            Some(overrides) => agent.with_overrides(overrides),
+            None => agent.clone(),
+        }
+    }
+
+    /// Log a provider failure and add the user-facing unavailability context, keeping the
+    /// original error as its cause. Cancellations and budget stops already say what
+    /// happened and pass through.
+    fn provider_unavailable_error(&self, error: anyhow::Error) -> anyhow::Error {
+        if is_cancelled(&error) || is_budget_exceeded(&error) {
+            return error;
+        }
+
+        // Log the error for debugging
+        self.log_activity(&format!("Provider error: {:#}", error));
+
+        // The Ollama hint only helps when Ollama is the provider that failed
+        if self.provider_manager.get_fallback_chain() == [ProviderType::Local] {
+            error.context(format!(
+                "Local AI provider unavailable. Please ensure:\n\
+                1. Ollama is running at {}\n\
+                2. The configured model is pulled",
+                self.config.ollama_url
+            ))
+        } else {
+            error.context("AI provider unavailable")
         }
     }
 }
@@ -925,3 +1299,80 @@ fn is_shell_command(cmd: &str) -> bool {
     let first_word = cmd.split_whitespace().next().unwrap_or("");
     common_commands.contains(&first_word)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_budget_stop_reaches_the_caller() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut orchestrator = Orchestrator::new(Config::default(), History { turns: vec![] });
+        // Nothing listens on this port; the budget stops the request before it is sent
+        orchestrator
+            .provider_manager
+            .add_provider(Box::new(OpenAICompatibleProvider::new(
+                "http://127.0.0.1:9/v1".to_string(),
+                None,
+                "gpt-test".to_string(),
+            )));
+        orchestrator
+            .provider_manager
+            .set_fallback_chain(vec![ProviderType::OpenAI]);
+        orchestrator
+            .provider_manager
+            .enable_usage_tracking(UsageTracker::new(
+                UsageLedger::at(dir.path().join("usage.json")),
+                PriceTable::new(&HashMap::new()),
+                Some(0.0),
+            ));
+
+        let error = orchestrator
+            .execute_ollama_call(&Conversation::new("", "hi"), &GENERAL_CLIAI)
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("monthly budget"),
+            "unexpected error: {}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn test_provider_failure_keeps_the_original_error() {
+        // Nothing listens on this port
+        let config = Config {
+            ollama_url: "http://127.0.0.1:9".to_string(),
+            ..Config::default()
+        };
+        let mut orchestrator = Orchestrator::new(config, History { turns: vec![] });
+
+        let error = orchestrator
+            .execute_ollama_call(&Conversation::new("", "hi"), &GENERAL_CLIAI)
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Ollama is running at http://127.0.0.1:9"));
+        assert!(error.chain().count() > 1, "cause dropped: {:#}", error);
+
+        // A failing cloud provider gets no Ollama hint
+        orchestrator
+            .provider_manager
+            .add_provider(Box::new(OpenAICompatibleProvider::new(
+                "http://127.0.0.1:9/v1".to_string(),
+                None,
+                "gpt-test".to_string(),
+            )));
+        orchestrator
+            .provider_manager
+            .set_fallback_chain(vec![ProviderType::OpenAI]);
+        let error = orchestrator
+            .execute_ollama_call(&Conversation::new("", "hi"), &GENERAL_CLIAI)
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "AI provider unavailable");
+        assert!(error.chain().count() > 1, "cause dropped: {:#}", error);
+    }
+}
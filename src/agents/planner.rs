@@ -0,0 +1,188 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Maximum number of context commands the planner may request
+pub const MAX_CONTEXT_COMMANDS: usize = 3;
+
+/// Route chosen by the planner for a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum RouteCategory {
+    Shell,
+    Code,
+    Log,
+    General,
+}
+
+impl RouteCategory {
+    pub const ALL: [RouteCategory; 4] = [
+        RouteCategory::Shell,
+        RouteCategory::Code,
+        RouteCategory::Log,
+        RouteCategory::General,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RouteCategory::Shell => "SHELL",
+            RouteCategory::Code => "CODE",
+            RouteCategory::Log => "LOG",
+            RouteCategory::General => "GENERAL",
+        }
+    }
+}
+
+/// Typed planner reply: the route category and the whitelisted context
+/// command ids to gather before answering
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlannerDecision {
+    pub category: RouteCategory,
+    pub commands: Vec<String>,
+}
+
+impl Default for PlannerDecision {
+    fn default() -> Self {
+        Self {
+            category: RouteCategory::General,
+            commands: Vec::new(),
+        }
+    }
+}
+
+impl PlannerDecision {
+    /// JSON schema for the planner reply, restricting `commands` to the given context command ids.
+    /// The limit of [`MAX_CONTEXT_COMMANDS`] is left to `parse`: strict structured outputs
+    /// reject `maxItems`.
+    pub fn json_schema(command_ids: &[String]) -> serde_json::Value {
+        let categories: Vec<&str> = RouteCategory::ALL.iter().map(|c| c.as_str()).collect();
+
+        json!({
+            "type": "object",
+            "properties": {
+                "category": {
+                    "type": "string",
+                    "enum": categories
+                },
+                "commands": {
+                    "type": "array",
+                    "items": { "type": "string", "enum": command_ids }
+                }
+            },
+            "required": ["category", "commands"],
+            "additionalProperties": false
+        })
+    }
+
+    /// Parse a planner reply and check it against the schema. The error describes the
+    /// violation so it can be fed back to the model in a repair request.
+    pub fn parse(reply: &str, command_ids: &[String]) -> Result<Self> {
+        let json = strip_code_fence(reply);
+
+        let decision: PlannerDecision = serde_json::from_str(json)
+            .map_err(|e| anyhow!("reply is not a valid planner decision: {}", e))?;
+
+        if decision.commands.len() > MAX_CONTEXT_COMMANDS {
+            return Err(anyhow!(
+                "at most {} context commands are allowed, got {}",
+                MAX_CONTEXT_COMMANDS,
+                decision.commands.len()
+            ));
+        }
+
+        if let Some(unknown) = decision
+            .commands
+            .iter()
+            .find(|cmd| !command_ids.contains(cmd))
+        {
+            return Err(anyhow!(
+                "unknown context command '{}' (allowed: {})",
+                unknown,
+                command_ids.join(", ")
+            ));
+        }
+
+        Ok(decision)
+    }
+}
+
+/// Remove a surrounding Markdown code fence (```json ... ```) if present
+fn strip_code_fence(reply: &str) -> &str {
+    let trimmed = reply.trim();
+    let Some(inner) = trimmed.strip_prefix("```") else {
+        return trimmed;
+    };
+    let inner = inner.strip_prefix("json").unwrap_or(inner);
+    inner.strip_suffix("```").unwrap_or(inner).trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids() -> Vec<String> {
+        ["ls-current", "pwd", "git-status"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_valid_decision() {
+        let decision = PlannerDecision::parse(
+            r#"{"category": "SHELL", "commands": ["ls-current"]}"#,
+            &ids(),
+        )
+        .unwrap();
+        assert_eq!(decision.category, RouteCategory::Shell);
+        assert_eq!(decision.commands, vec!["ls-current"]);
+    }
+
+    #[test]
+    fn test_parse_strips_code_fence() {
+        let reply = "```json\n{\"category\": \"CODE\", \"commands\": []}\n```";
+        let decision = PlannerDecision::parse(reply, &ids()).unwrap();
+        assert_eq!(decision.category, RouteCategory::Code);
+    }
+
+    #[test]
+    fn test_parse_rejects_schema_violations() {
+        // Unknown category
+        assert!(
+            PlannerDecision::parse(r#"{"category": "FILES", "commands": []}"#, &ids()).is_err()
+        );
+        // Raw shell command instead of a context command id
+        let err =
+            PlannerDecision::parse(r#"{"category": "SHELL", "commands": ["ls -la"]}"#, &ids())
+                .unwrap_err();
+        assert!(err.to_string().contains("ls -la"));
+        // Missing field
+        assert!(PlannerDecision::parse(r#"{"category": "SHELL"}"#, &ids()).is_err());
+        // Too many commands
+        assert!(PlannerDecision::parse(
+            r#"{"category": "SHELL", "commands": ["pwd", "pwd", "pwd", "pwd"]}"#,
+            &ids()
+        )
+        .is_err());
+        // Prose around the JSON
+        assert!(
+            PlannerDecision::parse(r#"Sure! {"category": "SHELL", "commands": []}"#, &ids())
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_json_schema_lists_categories_and_ids() {
+        let schema = PlannerDecision::json_schema(&ids());
+        assert_eq!(
+            schema["properties"]["category"]["enum"],
+            json!(["SHELL", "CODE", "LOG", "GENERAL"])
+        );
+        assert_eq!(
+            schema["properties"]["commands"]["items"]["enum"],
+            json!(["ls-current", "pwd", "git-status"])
+        );
+        assert_eq!(schema["additionalProperties"], false);
+    }
+}
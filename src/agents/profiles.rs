@@ -1,9 +1,69 @@
+use serde::{Deserialize, Serialize};
+
+/// Sampling and length settings an agent generates with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenerationParams {
+    pub temperature: f32,
+    pub max_tokens: u32,
+    pub top_p: Option<f32>,
+    pub stop: Vec<String>,
+    /// Context window size (Ollama only)
+    pub num_ctx: Option<u32>,
+    pub seed: Option<u64>,
+}
+
+/// User overrides for an agent's generation settings; unset fields keep the agent default
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GenerationOverrides {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl GenerationParams {
+    /// Apply user overrides on top of these settings
+    pub fn with_overrides(&self, overrides: &GenerationOverrides) -> Self {
+        Self {
+            temperature: overrides.temperature.unwrap_or(self.temperature),
+            max_tokens: overrides.max_tokens.unwrap_or(self.max_tokens),
+            top_p: overrides.top_p.or(self.top_p),
+            stop: overrides.stop.clone().unwrap_or_else(|| self.stop.clone()),
+            num_ctx: overrides.num_ctx.or(self.num_ctx),
+            seed: overrides.seed.or(self.seed),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct AgentProfile {
     pub name: &'static str,
     pub system_prompt: &'static str,
+    pub params: GenerationParams,
+}
+
+impl AgentProfile {
+    /// Copy of this profile with user overrides applied to its generation settings
+    pub fn with_overrides(&self, overrides: &GenerationOverrides) -> Self {
+        Self {
+            params: self.params.with_overrides(overrides),
+            ..self.clone()
+        }
+    }
 }
 
-pub const PLANNER_AGENT: AgentProfile = AgentProfile {
+/// Names of the built-in agents, as used for per-agent config overrides
+pub const AGENT_NAMES: [&str; 5] = ["Planner", "ShellExpert", "CodeExpert", "LogExpert", "CLIAI"];
+
+pub static PLANNER_AGENT: AgentProfile = AgentProfile {
     name: "Planner",
     system_prompt: r#"You are the CLIAI Planner. Analyze the user request using system context for OS-aware routing.
 
@@ -17,7 +77,7 @@ CONTEXT WINDOW: No conversation history - focus only on the current request.
 Respond ONLY with a JSON object:
 {
   "category": "SHELL" | "CODE" | "LOG" | "GENERAL",
-  "commands": ["cmd1", "cmd2"] (up to 3 read-only commands if needed for context, else [])
+  "commands": ["id1", "id2"] (up to 3 context command ids if needed for context, else [])
 }
 
 Categories:
@@ -40,20 +100,22 @@ Categories:
 - If the user asks about system status, processes, or resources → SHELL
 - If the user wants to EXECUTE any command → SHELL
 
-Context Commands (SAFE READ-ONLY ONLY):
-- Use OS-appropriate commands based on system context
-- Examples: "ls", "pwd", "whoami", "uname", "git status", "cat /etc/os-release"
-- NEVER suggest write operations or dangerous commands for context
+Context Commands (read-only, run safely by CLIAI - use ONLY these ids):
+- "ls-current": current directory contents
+- "pwd", "whoami", "hostname": environment details
+- "uname": kernel and architecture
+- "os-release": distribution information
+- "git-status", "git-branch": repository state
 
 Examples:
-- "what files are in this directory?" → {"category": "SHELL", "commands": ["ls"]}
-- "how big is this directory?" → {"category": "SHELL", "commands": ["ls"]}
-- "show me all rust files" → {"category": "SHELL", "commands": ["ls"]}
-- "what's the largest file?" → {"category": "SHELL", "commands": ["ls"]}
+- "what files are in this directory?" → {"category": "SHELL", "commands": ["ls-current"]}
+- "how big is this directory?" → {"category": "SHELL", "commands": ["ls-current"]}
+- "show me all rust files" → {"category": "SHELL", "commands": ["ls-current"]}
+- "what's the largest file?" → {"category": "SHELL", "commands": ["ls-current"]}
 - "how much disk space is left?" → {"category": "SHELL", "commands": []}
 - "what version of rust?" → {"category": "SHELL", "commands": []}
 - "check if docker is running" → {"category": "SHELL", "commands": []}
-- "Create a file x" → {"category": "SHELL", "commands": ["ls"]}
+- "Create a file x" → {"category": "SHELL", "commands": ["ls-current"]}
 - "How do I use a for loop in Python?" → {"category": "CODE", "commands": []}
 - "Who are you?" → {"category": "GENERAL", "commands": []}
 - "Why is my build failing? [error log provided]" → {"category": "LOG", "commands": []}
@@ -63,9 +125,18 @@ ROUTING SAFETY:
 - Route destructive operations to SHELL for proper safety checking
 - Route system modifications to SHELL for validation
 - Keep explanatory requests in appropriate categories"#,
+    // Routing output is parsed as JSON, so keep it deterministic and short
+    params: GenerationParams {
+        temperature: 0.0,
+        max_tokens: 256,
+        top_p: None,
+        stop: Vec::new(),
+        num_ctx: None,
+        seed: Some(0),
+    },
 };
 
-pub const SHELL_EXPERT: AgentProfile = AgentProfile {
+pub static SHELL_EXPERT: AgentProfile = AgentProfile {
     name: "ShellExpert",
     system_prompt: r#"You are the CLIAI Shell Expert. Your task is to provide a SINGLE, CORRECT terminal command that accomplishes the user's request.
 
@@ -143,9 +214,17 @@ CONTEXT WINDOW LIMITS:
 - Use provided system context for OS-aware responses
 
 Provide ONLY the command in the specified format, tailored to the detected operating system and following all safety constraints."#,
+    params: GenerationParams {
+        temperature: 0.1,
+        max_tokens: 512,
+        top_p: None,
+        stop: Vec::new(),
+        num_ctx: None,
+        seed: None,
+    },
 };
 
-pub const CODE_EXPERT: AgentProfile = AgentProfile {
+pub static CODE_EXPERT: AgentProfile = AgentProfile {
     name: "CodeExpert",
     system_prompt: r#"You are the CLIAI Code Expert. 
 Help the user with programming tasks using the provided system context for OS-aware responses.
@@ -187,9 +266,17 @@ INTEGRATION WITH SHELL:
 - "find all TODO comments in the codebase" → Command: grep -r 'TODO' .
 - "how do I use map in JavaScript?" → [Provide detailed explanation with code examples]
 - "explain async/await in Rust" → [Provide detailed explanation with code examples]"#,
+    params: GenerationParams {
+        temperature: 0.3,
+        max_tokens: 2048,
+        top_p: None,
+        stop: Vec::new(),
+        num_ctx: None,
+        seed: None,
+    },
 };
 
-pub const LOG_EXPERT: AgentProfile = AgentProfile {
+pub static LOG_EXPERT: AgentProfile = AgentProfile {
     name: "LogExpert",
     system_prompt: r#"You are the CLIAI Troubleshooting Expert.
 Analyze the provided logs or error messages using system context for OS-aware solutions.
@@ -219,9 +306,17 @@ DIAGNOSTIC APPROACH:
 2. Suggest safe diagnostic commands first
 3. Provide fix commands only if they are safe and appropriate
 4. Explain the reasoning behind suggested solutions"#,
+    params: GenerationParams {
+        temperature: 0.2,
+        max_tokens: 1024,
+        top_p: None,
+        stop: Vec::new(),
+        num_ctx: None,
+        seed: None,
+    },
 };
 
-pub const GENERAL_CLIAI: AgentProfile = AgentProfile {
+pub static GENERAL_CLIAI: AgentProfile = AgentProfile {
     name: "CLIAI",
     system_prompt: r#"You are CLIAI, a friendly CLI assistant. Give short, helpful answers using the provided system context.
 
@@ -248,4 +343,12 @@ INTEGRATION AWARENESS:
 - You work alongside specialized agents (ShellExpert, CodeExpert, LogExpert)
 - Direct users to appropriate specialized help when needed
 - Maintain consistency with the overall CLIAI experience"#,
+    params: GenerationParams {
+        temperature: 0.4,
+        max_tokens: 2048,
+        top_p: None,
+        stop: Vec::new(),
+        num_ctx: None,
+        seed: None,
+    },
 };
@@ -1,12 +1,48 @@
-use anyhow::{anyhow, Result};
+use crate::config::ApiKeySources;
+use crate::network::client_builder;
+use anyhow::{anyhow, Context, Result};
 use keyring::Entry;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
 
+/// Providers whose keys can be stored; `cloud` is the cliai backend token
+const KEYED_PROVIDERS: &[&str] = &["openai", "anthropic", "google", "cohere", "cloud"];
+
+/// Resolves API keys from, in order: `CLIAI_<PROVIDER>_API_KEY`, a key file, a helper
+/// command and the system keyring. A source that is missing or broken falls through to
+/// the next one, so headless machines without a keyring still work.
 #[allow(dead_code)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiKeyManager {
     service_name: String,
+    /// Directory with one key file per provider
+    key_dir: Option<PathBuf>,
+    /// Commands printing a provider's key on stdout
+    key_commands: HashMap<String, String>,
+}
+
+/// Where a resolved API key came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySource {
+    Env(String),
+    File(PathBuf),
+    Command,
+    Keyring,
+}
+
+impl fmt::Display for KeySource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeySource::Env(var) => write!(f, "env {}", var),
+            KeySource::File(path) => write!(f, "file {}", path.display()),
+            KeySource::Command => write!(f, "command"),
+            KeySource::Keyring => write!(f, "keyring"),
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -20,37 +56,170 @@ pub struct ProviderConfig {
 
 #[allow(dead_code)]
 impl ApiKeyManager {
+    /// Manager using the default key directory and no helper commands
     pub fn new() -> Self {
+        Self::from_config(&ApiKeySources::default())
+    }
+
+    /// Manager for the `api_keys` section of the configuration
+    pub fn from_config(sources: &ApiKeySources) -> Self {
         Self {
             service_name: "cliai".to_string(),
+            key_dir: sources
+                .key_dir
+                .as_ref()
+                .map(PathBuf::from)
+                .or_else(Self::default_key_dir),
+            key_commands: sources
+                .key_commands
+                .iter()
+                .map(|(provider, command)| (provider.clone(), command.clone()))
+                .collect(),
         }
     }
 
-    /// Set an API key for a provider
-    pub fn set_key(&self, provider: &str, api_key: &str) -> Result<()> {
-        let entry = Entry::new(&self.service_name, provider)?;
-        entry.set_password(api_key)?;
-        println!("✅ API key set for {}", provider);
-        Ok(())
+    /// ~/.config/cliai/keys
+    fn default_key_dir() -> Option<PathBuf> {
+        dirs::config_dir().map(|mut path| {
+            path.push("cliai");
+            path.push("keys");
+            path
+        })
+    }
+
+    /// Environment variable checked first for a provider's key, e.g. CLIAI_OPENAI_API_KEY
+    pub fn env_var_name(provider: &str) -> String {
+        format!(
+            "CLIAI_{}_API_KEY",
+            provider.to_uppercase().replace(['-', '.'], "_")
+        )
+    }
+
+    /// Key file of a provider, e.g. ~/.config/cliai/keys/openai
+    pub fn key_file_path(&self, provider: &str) -> Option<PathBuf> {
+        self.key_dir.as_ref().map(|dir| dir.join(provider))
+    }
+
+    /// Store an API key in the system keyring, or in a 0600 key file when no keyring is
+    /// available. Returns where the key was stored.
+    pub fn set_key(&self, provider: &str, api_key: &str) -> Result<KeySource> {
+        let keyring_error = match Entry::new(&self.service_name, provider)
+            .and_then(|entry| entry.set_password(api_key))
+        {
+            Ok(()) => return Ok(KeySource::Keyring),
+            Err(e) => e,
+        };
+
+        let path = self.key_file_path(provider).ok_or_else(|| {
+            anyhow!(
+                "Keyring unavailable ({}) and no key directory could be determined",
+                keyring_error
+            )
+        })?;
+        write_key_file(&path, api_key)?;
+        Ok(KeySource::File(path))
     }
 
-    /// Get an API key for a provider
+    /// Get an API key for a provider from the first source that has one
     pub fn get_key(&self, provider: &str) -> Result<String> {
-        let entry = Entry::new(&self.service_name, provider)?;
-        match entry.get_password() {
-            Ok(key) => Ok(key),
-            Err(_) => Err(anyhow!("No API key found for provider: {}", provider)),
+        self.resolve_key(provider).map(|(key, _)| key)
+    }
+
+    /// Get an API key together with the source it came from
+    pub fn resolve_key(&self, provider: &str) -> Result<(String, KeySource)> {
+        self.resolve_key_with_env(provider, |var| std::env::var(var).ok())
+    }
+
+    /// `resolve_key` reading environment variables through `env`
+    fn resolve_key_with_env(
+        &self,
+        provider: &str,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<(String, KeySource)> {
+        // Broken sources are reported only when no later source has a key
+        let mut problems = Vec::new();
+
+        let var = Self::env_var_name(provider);
+        if let Some(key) = env(&var) {
+            if !key.trim().is_empty() {
+                return Ok((key.trim().to_string(), KeySource::Env(var)));
+            }
+        }
+
+        if let Some(path) = self.key_file_path(provider).filter(|path| path.exists()) {
+            match read_key_file(&path) {
+                Ok(key) => return Ok((key, KeySource::File(path))),
+                Err(e) => problems.push(e.to_string()),
+            }
+        }
+
+        if let Some(command) = self.key_commands.get(provider) {
+            match run_key_command(command) {
+                Ok(key) => return Ok((key, KeySource::Command)),
+                Err(e) => problems.push(e.to_string()),
+            }
+        }
+
+        match Entry::new(&self.service_name, provider).and_then(|entry| entry.get_password()) {
+            Ok(key) => return Ok((key, KeySource::Keyring)),
+            Err(keyring::Error::NoEntry) => {}
+            Err(e) => problems.push(format!("keyring: {}", e)),
+        }
+
+        if problems.is_empty() {
+            Err(anyhow!("No API key found for provider: {}", provider))
+        } else {
+            Err(anyhow!(
+                "No API key found for provider: {} ({})",
+                provider,
+                problems.join("; ")
+            ))
         }
     }
 
-    /// Remove an API key for a provider
+    /// Where the key of a provider would be read from, if it has one
+    pub fn key_source(&self, provider: &str) -> Option<KeySource> {
+        self.resolve_key(provider).ok().map(|(_, source)| source)
+    }
+
+    /// Remove an API key for a provider from the keyring and its key file. Keys from
+    /// environment variables and commands are managed outside cliai.
     pub fn remove_key(&self, provider: &str) -> Result<()> {
-        let entry = Entry::new(&self.service_name, provider)?;
-        entry.delete_password()?;
+        let mut removed = false;
+
+        if let Ok(entry) = Entry::new(&self.service_name, provider) {
+            removed |= entry.delete_password().is_ok();
+        }
+        if let Some(path) = self.key_file_path(provider).filter(|path| path.exists()) {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove key file {}", path.display()))?;
+            removed = true;
+        }
+
+        if !removed {
+            return match self.key_source(provider) {
+                Some(source) => Err(anyhow!(
+                    "The key for {} comes from {}; remove it there",
+                    provider,
+                    source
+                )),
+                None => Err(anyhow!("No API key found for provider: {}", provider)),
+            };
+        }
         println!("✅ API key removed for {}", provider);
         Ok(())
     }
 
+    /// Check that the system keyring can be reached. A missing entry is fine; any
+    /// other error means API keys cannot be stored or read.
+    pub fn check_backend(&self) -> Result<()> {
+        let entry = Entry::new(&self.service_name, "cliai-doctor")?;
+        match entry.get_password() {
+            Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(anyhow!("Keyring backend unavailable: {}", e)),
+        }
+    }
+
     /// Test if an API key exists for a provider
     pub fn has_key(&self, provider: &str) -> bool {
         self.get_key(provider).is_ok()
@@ -58,9 +227,8 @@ impl ApiKeyManager {
 
     /// List all providers that have API keys set
     pub fn list_configured_providers(&self) -> Vec<String> {
-        let providers = vec!["openai", "anthropic", "google", "cohere"];
-        providers
-            .into_iter()
+        KEYED_PROVIDERS
+            .iter()
             .filter(|provider| self.has_key(provider))
             .map(|s| s.to_string())
             .collect()
@@ -115,6 +283,44 @@ impl ApiKeyManager {
             },
         );
 
+        providers.insert(
+            "google".to_string(),
+            ProviderConfig {
+                name: "Google Gemini".to_string(),
+                models: vec![
+                    "gemini-2.0-flash".to_string(),
+                    "gemini-1.5-flash".to_string(),
+                    "gemini-1.5-pro".to_string(),
+                ],
+                url: "https://generativelanguage.googleapis.com/v1beta".to_string(),
+                requires_key: true,
+            },
+        );
+
+        providers.insert(
+            "cloud".to_string(),
+            ProviderConfig {
+                name: "CLIAI Cloud".to_string(),
+                models: vec!["gpt-4o-mini".to_string(), "gpt-4o".to_string()],
+                url: "http://localhost:5000".to_string(),
+                requires_key: true,
+            },
+        );
+
+        providers.insert(
+            "cohere".to_string(),
+            ProviderConfig {
+                name: "Cohere".to_string(),
+                models: vec![
+                    "command-r".to_string(),
+                    "command-r-plus".to_string(),
+                    "command-r7b-12-2024".to_string(),
+                ],
+                url: "https://api.cohere.com".to_string(),
+                requires_key: true,
+            },
+        );
+
         providers
     }
 
@@ -130,6 +336,8 @@ impl ApiKeyManager {
         match provider {
             "openai" => self.test_openai_key(&api_key).await,
             "anthropic" => self.test_anthropic_key(&api_key).await,
+            "google" => self.test_google_key(&api_key).await,
+            "cohere" => self.test_cohere_key(&api_key).await,
             _ => Err(anyhow!(
                 "Testing not implemented for provider: {}",
                 provider
@@ -138,7 +346,7 @@ impl ApiKeyManager {
     }
 
     async fn test_openai_key(&self, api_key: &str) -> Result<bool> {
-        let client = reqwest::Client::new();
+        let client = client_builder("openai").build()?;
         let response = client
             .get("https://api.openai.com/v1/models")
             .header("Authorization", format!("Bearer {}", api_key))
@@ -149,7 +357,7 @@ impl ApiKeyManager {
     }
 
     async fn test_anthropic_key(&self, api_key: &str) -> Result<bool> {
-        let client = reqwest::Client::new();
+        let client = client_builder("anthropic").build()?;
         let response = client
             .post("https://api.anthropic.com/v1/messages")
             .header("x-api-key", api_key)
@@ -166,6 +374,28 @@ impl ApiKeyManager {
         // Anthropic returns 400 for invalid requests but 401 for invalid keys
         Ok(response.status() != reqwest::StatusCode::UNAUTHORIZED)
     }
+
+    async fn test_google_key(&self, api_key: &str) -> Result<bool> {
+        let client = client_builder("gemini").build()?;
+        let response = client
+            .get("https://generativelanguage.googleapis.com/v1beta/models?pageSize=1")
+            .header("x-goog-api-key", api_key)
+            .send()
+            .await?;
+
+        Ok(response.status().is_success())
+    }
+
+    async fn test_cohere_key(&self, api_key: &str) -> Result<bool> {
+        let client = client_builder("cohere").build()?;
+        let response = client
+            .get("https://api.cohere.com/v1/models?page_size=1")
+            .header("Authorization", format!("Bearer {}", api_key))
+            .send()
+            .await?;
+
+        Ok(response.status().is_success())
+    }
 }
 
 impl Default for ApiKeyManager {
@@ -173,3 +403,187 @@ impl Default for ApiKeyManager {
         Self::new()
     }
 }
+
+/// Read a key file, refusing files other users can read
+fn read_key_file(path: &Path) -> Result<String> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path)
+            .with_context(|| format!("Failed to read key file {}", path.display()))?
+            .permissions()
+            .mode();
+        if mode & 0o077 != 0 {
+            return Err(anyhow!(
+                "key file {} is accessible by other users (mode {:o}); restrict it with chmod 600",
+                path.display(),
+                mode & 0o777
+            ));
+        }
+    }
+
+    let key = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read key file {}", path.display()))?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(anyhow!("key file {} is empty", path.display()));
+    }
+    Ok(key.to_string())
+}
+
+/// Write a key file readable only by the current user
+fn write_key_file(path: &Path, api_key: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create key directory {}", parent.display()))?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        // An existing file keeps its mode, so tighten it as well
+        if path.exists() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+
+    use std::io::Write;
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to write key file {}", path.display()))?;
+    writeln!(file, "{}", api_key)?;
+    Ok(())
+}
+
+/// Run a `pass`/`op`-style helper and take the first line of its output as the key
+fn run_key_command(command: &str) -> Result<String> {
+    let (shell, flag) = if cfg!(target_os = "windows") {
+        ("cmd", "/C")
+    } else {
+        ("sh", "-c")
+    };
+    let output = Command::new(shell)
+        .args([flag, command])
+        .output()
+        .with_context(|| format!("Failed to run key command '{}'", command))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "key command '{}' failed ({}): {}",
+            command,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout.lines().next().map(str::trim) {
+        Some(key) if !key.is_empty() => Ok(key.to_string()),
+        _ => Err(anyhow!("key command '{}' printed no key", command)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn test_manager(dir: &TempDir, commands: &[(&str, &str)]) -> ApiKeyManager {
+        ApiKeyManager {
+            service_name: "cliai-test".to_string(),
+            key_dir: Some(dir.path().to_path_buf()),
+            key_commands: commands
+                .iter()
+                .map(|(provider, command)| (provider.to_string(), command.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_env_var_name() {
+        assert_eq!(
+            ApiKeyManager::env_var_name("openai"),
+            "CLIAI_OPENAI_API_KEY"
+        );
+        assert_eq!(
+            ApiKeyManager::env_var_name("my-proxy"),
+            "CLIAI_MY_PROXY_API_KEY"
+        );
+    }
+
+    #[test]
+    fn test_resolution_order() {
+        let dir = TempDir::new().unwrap();
+        let provider = "resolution-order-test";
+        let manager = test_manager(&dir, &[(provider, "echo from-command")]);
+
+        assert_eq!(
+            manager.resolve_key(provider).unwrap(),
+            ("from-command".to_string(), KeySource::Command)
+        );
+
+        write_key_file(&manager.key_file_path(provider).unwrap(), "from-file").unwrap();
+        let (key, source) = manager.resolve_key(provider).unwrap();
+        assert_eq!(key, "from-file");
+        assert!(matches!(source, KeySource::File(_)));
+
+        let var = ApiKeyManager::env_var_name(provider);
+        let env = HashMap::from([(var.clone(), "from-env\n".to_string())]);
+        assert_eq!(
+            manager
+                .resolve_key_with_env(provider, |name| env.get(name).cloned())
+                .unwrap(),
+            ("from-env".to_string(), KeySource::Env(var))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_key_file_permissions_enforced() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let provider = "permissions-test";
+        let manager = test_manager(&dir, &[(provider, "exit 3")]);
+        let path = manager.key_file_path(provider).unwrap();
+
+        write_key_file(&path, "secret").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let error = manager.resolve_key(provider).unwrap_err().to_string();
+        assert!(error.contains("chmod 600"));
+        // The failing command is reported too
+        assert!(error.contains("exit 3"));
+
+        // Rewriting tightens the existing file
+        write_key_file(&path, "secret").unwrap();
+        assert_eq!(manager.get_key(provider).unwrap(), "secret");
+    }
+
+    // The helper commands below are POSIX shell
+    #[cfg(unix)]
+    #[test]
+    fn test_key_command_without_output() {
+        assert!(run_key_command("true").is_err());
+        assert_eq!(
+            run_key_command("printf 'sk-1\\nsecond line'").unwrap(),
+            "sk-1"
+        );
+    }
+
+    #[test]
+    fn test_remove_key_file() {
+        let dir = TempDir::new().unwrap();
+        let provider = "remove-test";
+        let manager = test_manager(&dir, &[]);
+        write_key_file(&manager.key_file_path(provider).unwrap(), "secret").unwrap();
+
+        manager.remove_key(provider).unwrap();
+        assert!(!manager.key_file_path(provider).unwrap().exists());
+        assert!(manager.remove_key(provider).is_err());
+    }
+}
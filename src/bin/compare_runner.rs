@@ -1,6 +1,6 @@
 use anyhow::{anyhow, Result};
 use clap::Parser;
-use cliai::{Config, History, Orchestrator};
+use cliai::{ApiKeyManager, Config, History, Orchestrator};
 use serde::Deserialize;
 use std::fs;
 use std::path::PathBuf;
@@ -28,7 +28,7 @@ struct Cli {
     #[arg(long)]
     backend_url: Option<String>,
 
-    /// Cloud token override (optional). If not provided, uses config.json api_token.
+    /// Cloud token override (optional). If not provided, the stored cloud key is used.
     #[arg(long)]
     cloud_token: Option<String>,
 
@@ -111,7 +111,8 @@ async fn main() -> Result<()> {
         base_config.backend_url = url;
     }
     if let Some(token) = cli.cloud_token.clone() {
-        base_config.api_token = Some(token);
+        // The environment variable outranks stored keys in the key resolution chain
+        std::env::set_var(ApiKeyManager::env_var_name("cloud"), token);
     }
 
     let out_path = cli.out.unwrap_or_else(|| {
@@ -130,7 +131,7 @@ async fn main() -> Result<()> {
     let mut cloud_config = base_config.clone();
     cloud_config.use_cloud = true;
     cloud_config.ai_timeout = cli.timeout_ms;
-    if cloud_config.api_token.is_none() {
+    if cloud_config.cloud_token().is_none() {
         if cli.local_only {
             // OK: user explicitly requested local-only.
         } else {
@@ -206,11 +207,12 @@ async fn main() -> Result<()> {
     let mut md = String::new();
     md.push_str("# CLIAI Local vs Cloud Comparison Report\n\n");
     md.push_str(&format!(
-        "- Generated: {}\n- Scenarios: {}\n- Local model: `{}`\n- Cloud backend: `{}`\n\n",
+        "- Generated: {}\n- Scenarios: {}\n- Local model: `{}`\n- Cloud backend: `{}` (model `{}`)\n\n",
         chrono::Utc::now().format("%Y-%m-%d %H:%M:%S UTC"),
         scenarios_path.display(),
         local_config.model,
-        cloud_config.backend_url
+        cloud_config.backend_url,
+        cloud_config.cloud_model
     ));
 
     md.push_str("## Results (side-by-side)\n\n");
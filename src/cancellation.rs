@@ -0,0 +1,145 @@
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::Notify;
+
+/// Exit code for a run stopped with Ctrl-C (128 + SIGINT, as shells report it)
+pub const CANCELLED_EXIT_CODE: i32 = 130;
+
+/// Error returned by work that was stopped through a [`CancellationToken`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cancelled by user")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Whether an error, or any error it wraps, is a cancellation rather than a failure
+pub fn is_cancelled(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.is::<Cancelled>())
+}
+
+#[derive(Default)]
+struct TokenState {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+/// Shared flag that lets in-flight work stop cooperatively. Clones observe the same
+/// state; once cancelled a token stays cancelled.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    state: Arc<TokenState>,
+}
+
+#[allow(dead_code)]
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a token that is cancelled by the first Ctrl-C. A second Ctrl-C exits
+    /// right away, for work that does not reach a cancellation point.
+    pub fn on_ctrl_c() -> Self {
+        let token = Self::new();
+        let handler_token = token.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            handler_token.cancel();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(CANCELLED_EXIT_CODE);
+            }
+        });
+        token
+    }
+
+    pub fn cancel(&self) {
+        self.state.cancelled.store(true, Ordering::SeqCst);
+        self.state.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.state.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Resolve once the token is cancelled
+    pub async fn cancelled(&self) {
+        loop {
+            // Register before checking the flag so a concurrent cancel is not missed
+            let notified = self.state.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Drive `future` to completion unless the token is cancelled first, in which case
+    /// the future is dropped and [`Cancelled`] is returned. A result that arrives after
+    /// cancellation was requested is discarded as well.
+    pub async fn run<T, F>(&self, future: F) -> Result<T>
+    where
+        F: Future<Output = Result<T>>,
+    {
+        let result = tokio::select! {
+            biased;
+            _ = self.cancelled() => return Err(Cancelled.into()),
+            result = future => result,
+        };
+        if self.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_cancel_stops_pending_future() {
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            canceller.cancel();
+        });
+
+        let result = token
+            .run(async {
+                tokio::time::sleep(Duration::from_secs(30)).await;
+                Ok("finished")
+            })
+            .await;
+
+        let error = result.unwrap_err();
+        assert!(is_cancelled(&error));
+        assert!(token.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_uncancelled_token_passes_result_through() {
+        let token = CancellationToken::new();
+        assert_eq!(token.run(async { Ok(42) }).await.unwrap(), 42);
+
+        let error = token
+            .run(async { Err::<(), _>(anyhow::anyhow!("provider down")) })
+            .await
+            .unwrap_err();
+        assert!(!is_cancelled(&error));
+
+        let wrapped = anyhow::Error::new(Cancelled).context("while generating");
+        assert!(is_cancelled(&wrapped));
+    }
+}
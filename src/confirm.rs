@@ -0,0 +1,151 @@
+use anyhow::{bail, Result};
+use base64::Engine;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+/// What the user chose at a confirmation prompt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfirmChoice {
+    Run,
+    Edit,
+    Explain,
+    Copy,
+    Alternative,
+    No,
+}
+
+impl ConfirmChoice {
+    /// Parse a prompt answer. `y`/`yes` still run the command, as the old prompt did.
+    pub fn parse(input: &str) -> Option<Self> {
+        match input.trim().to_lowercase().as_str() {
+            "r" | "run" | "y" | "yes" => Some(ConfirmChoice::Run),
+            "e" | "edit" => Some(ConfirmChoice::Edit),
+            "x" | "explain" => Some(ConfirmChoice::Explain),
+            "c" | "copy" => Some(ConfirmChoice::Copy),
+            "a" | "alternative" => Some(ConfirmChoice::Alternative),
+            "n" | "no" => Some(ConfirmChoice::No),
+            _ => None,
+        }
+    }
+
+    /// The choices offered; running is left out when the command may not be run
+    pub fn prompt(can_run: bool) -> String {
+        let choices = "[e]dit, e[x]plain, [c]opy, [a]lternative, [n]o";
+        if can_run {
+            format!("[r]un, {}:", choices)
+        } else {
+            format!("{}:", choices)
+        }
+    }
+
+    /// Note recorded against the answer that suggested `command`, so later requests can
+    /// see what the user did with it
+    pub fn history_note(&self, command: &str) -> String {
+        let action = match self {
+            ConfirmChoice::Run => "chose to run",
+            ConfirmChoice::Edit => "edited",
+            ConfirmChoice::Explain => "asked for an explanation of",
+            ConfirmChoice::Copy => "copied",
+            ConfirmChoice::Alternative => "asked for an alternative to",
+            ConfirmChoice::No => "declined",
+        };
+        format!("(User {} `{}`)", action, command)
+    }
+}
+
+/// Let the user edit `command` in a line editor pre-filled with it. Returns `None` when
+/// the edit is abandoned with Ctrl-C or Ctrl-D, or leaves the line empty.
+pub fn edit_command(command: &str) -> Result<Option<String>> {
+    let mut editor = DefaultEditor::new()?;
+    match editor.readline_with_initial("edit> ", (command, "")) {
+        Ok(line) if line.trim().is_empty() => Ok(None),
+        Ok(line) => Ok(Some(line.trim().to_string())),
+        Err(ReadlineError::Interrupted | ReadlineError::Eof) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Clipboard programs tried in order, with the environment variable that must be set
+/// for each to be usable
+const CLIPBOARD_PROGRAMS: &[(&str, &[&str], Option<&str>)] = &[
+    ("pbcopy", &[], None),
+    ("wl-copy", &[], Some("WAYLAND_DISPLAY")),
+    ("xclip", &["-selection", "clipboard"], Some("DISPLAY")),
+    ("xsel", &["--clipboard", "--input"], Some("DISPLAY")),
+    ("clip.exe", &[], None),
+    ("clip", &[], None),
+];
+
+/// Copy `text` to the system clipboard. Falls back to the OSC 52 escape sequence, which
+/// most terminals (including over SSH) turn into a clipboard write. Returns what was used.
+pub fn copy_to_clipboard(text: &str) -> Result<String> {
+    for (program, args, required_env) in CLIPBOARD_PROGRAMS {
+        if required_env.is_some_and(|var| env::var_os(var).is_none()) {
+            continue;
+        }
+        let Ok(mut child) = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        else {
+            continue;
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            let _ = stdin.write_all(text.as_bytes());
+        }
+        if child.wait()?.success() {
+            return Ok(program.to_string());
+        }
+    }
+
+    let mut stdout = io::stdout();
+    if !stdout.is_terminal() {
+        bail!("No clipboard program found and the output is not a terminal");
+    }
+    write!(stdout, "{}", osc52_sequence(text))?;
+    stdout.flush()?;
+    Ok("terminal (OSC 52)".to_string())
+}
+
+/// Escape sequence asking the terminal to put `text` on the clipboard
+fn osc52_sequence(text: &str) -> String {
+    format!(
+        "\x1b]52;c;{}\x07",
+        base64::engine::general_purpose::STANDARD.encode(text)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_confirm_choice_parsing() {
+        assert_eq!(ConfirmChoice::parse("r"), Some(ConfirmChoice::Run));
+        assert_eq!(ConfirmChoice::parse(" Y\n"), Some(ConfirmChoice::Run));
+        assert_eq!(ConfirmChoice::parse("e"), Some(ConfirmChoice::Edit));
+        assert_eq!(ConfirmChoice::parse("x"), Some(ConfirmChoice::Explain));
+        assert_eq!(ConfirmChoice::parse("copy"), Some(ConfirmChoice::Copy));
+        assert_eq!(ConfirmChoice::parse("A"), Some(ConfirmChoice::Alternative));
+        assert_eq!(ConfirmChoice::parse("n"), Some(ConfirmChoice::No));
+        assert_eq!(ConfirmChoice::parse(""), None);
+        assert_eq!(ConfirmChoice::parse("maybe"), None);
+
+        assert!(ConfirmChoice::prompt(true).starts_with("[r]un"));
+        assert!(!ConfirmChoice::prompt(false).contains("[r]un"));
+        assert_eq!(
+            ConfirmChoice::No.history_note("rm -r build"),
+            "(User declined `rm -r build`)"
+        );
+    }
+
+    #[test]
+    fn test_osc52_sequence() {
+        assert_eq!(osc52_sequence("ls -la"), "\x1b]52;c;bHMgLWxh\x07");
+    }
+}
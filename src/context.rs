@@ -344,6 +344,8 @@ mod tests {
             api_token: None,
             use_cloud: false,
             backend_url: "https://api.cliai.com".to_string(),
+            cache_enabled: false,
+            ..Config::default()
         }
     }
 
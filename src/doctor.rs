@@ -0,0 +1,614 @@
+use crate::api_keys::ApiKeyManager;
+use crate::config::{Config, NetworkConfig};
+use crate::error_handling::enhance_error;
+use crate::history::History;
+use crate::logging::PrivacyLogger;
+use crate::network::HttpClientFactory;
+use crate::os_context::{OSContext, Shell};
+use crate::providers::{AIProvider, CloudProvider, OllamaProvider};
+use anyhow::anyhow;
+use colored::*;
+use serde::Serialize;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// How long to wait for each network check
+const NETWORK_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Age after which the cached OS detection is reported as stale
+const OS_CACHE_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Outcome of a single diagnostic check
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+/// Result of one diagnostic check with remediation hints
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorCheck {
+    pub name: String,
+    pub status: CheckStatus,
+    pub message: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hints: Vec<String>,
+}
+
+impl DoctorCheck {
+    fn new(name: &str, status: CheckStatus, message: String) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            message,
+            hints: Vec::new(),
+        }
+    }
+
+    fn pass(name: &str, message: String) -> Self {
+        Self::new(name, CheckStatus::Pass, message)
+    }
+
+    fn warn(name: &str, message: String) -> Self {
+        Self::new(name, CheckStatus::Warn, message)
+    }
+
+    fn fail(name: &str, message: String) -> Self {
+        Self::new(name, CheckStatus::Fail, message)
+    }
+
+    fn with_hints(mut self, hints: Vec<String>) -> Self {
+        self.hints.extend(hints);
+        self
+    }
+
+    /// Attach the remediation hints `error_handling` gives for this error
+    fn with_error_hints(self, error: &anyhow::Error) -> Self {
+        self.with_hints(enhance_error(error).suggestions)
+    }
+}
+
+/// Full diagnostics report, printable or serializable for bug reports
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorReport {
+    pub version: String,
+    pub os: String,
+    pub checks: Vec<DoctorCheck>,
+}
+
+#[allow(dead_code)]
+impl DoctorReport {
+    fn new(checks: Vec<DoctorCheck>) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            os: format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+            checks,
+        }
+    }
+
+    /// Number of checks with the given status
+    pub fn count(&self, status: CheckStatus) -> usize {
+        self.checks.iter().filter(|c| c.status == status).count()
+    }
+
+    /// Whether any check failed
+    pub fn has_failures(&self) -> bool {
+        self.count(CheckStatus::Fail) > 0
+    }
+
+    /// Print the report with status icons and hints
+    pub fn display(&self) {
+        println!("{}", "🩺 CLIAI Doctor".bold().cyan());
+        println!(
+            "{}",
+            format!("cliai {} on {}", self.version, self.os).dimmed()
+        );
+        println!();
+
+        for check in &self.checks {
+            let label = match check.status {
+                CheckStatus::Pass => "✅ PASS".green(),
+                CheckStatus::Warn => "⚠️  WARN".yellow(),
+                CheckStatus::Fail => "❌ FAIL".red(),
+            };
+            println!("{} {:<16} {}", label, check.name.bold(), check.message);
+            for hint in &check.hints {
+                println!("          {} {}", "→".dimmed(), hint.dimmed());
+            }
+        }
+
+        println!(
+            "\n{} {} passed, {} warnings, {} failed",
+            "Summary:".bold(),
+            self.count(CheckStatus::Pass),
+            self.count(CheckStatus::Warn),
+            self.count(CheckStatus::Fail)
+        );
+    }
+}
+
+/// Walk the whole pipeline: configuration, keyring, providers, OS detection,
+/// log and history files, and shell detection
+pub async fn run_diagnostics(config: &Config) -> DoctorReport {
+    let mut checks = vec![check_config_file(Config::get_config_path().as_deref())];
+    checks.push(check_keyring(&ApiKeyManager::new()));
+    checks.push(check_network(&config.network));
+    checks.push(check_ollama(config).await);
+    checks.push(check_cloud(config).await);
+    checks.push(check_os_cache(
+        OSContext::get_cache_path().ok().as_deref(),
+        OS_CACHE_MAX_AGE,
+    ));
+    checks.push(check_file_access(
+        "Log file",
+        PrivacyLogger::get_log_file_path().ok().as_deref(),
+        false,
+    ));
+    checks.push(check_file_access(
+        "History file",
+        History::get_history_path().as_deref(),
+        true,
+    ));
+    checks.push(check_shell(
+        std::env::var("SHELL").ok().as_deref(),
+        OSContext::detect_shell(),
+    ));
+
+    DoctorReport::new(checks)
+}
+
+/// Check that the config file parses and passes `Config::validate_config`
+fn check_config_file(path: Option<&Path>) -> DoctorCheck {
+    const NAME: &str = "Config";
+
+    let Some(path) = path else {
+        return DoctorCheck::warn(NAME, "Could not determine config directory".to_string());
+    };
+    if !path.exists() {
+        return DoctorCheck::warn(
+            NAME,
+            format!("{} not found, using defaults", path.display()),
+        )
+        .with_hints(vec!["Run any cliai command to create it".to_string()]);
+    }
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            let error = anyhow!("Failed to read {}: {}", path.display(), e);
+            return DoctorCheck::fail(NAME, error.to_string()).with_error_hints(&error);
+        }
+    };
+
+    let config: Config = match serde_json::from_str(&content) {
+        Ok(config) => config,
+        Err(e) => {
+            let error = anyhow!("Failed to parse configuration: {}", e);
+            return DoctorCheck::fail(NAME, error.to_string()).with_error_hints(&error);
+        }
+    };
+
+    match Config::validate_config(&config) {
+        Ok(()) => DoctorCheck::pass(NAME, format!("{} is valid", path.display())),
+        Err(e) => {
+            let error = anyhow!("Invalid configuration: {}", e);
+            DoctorCheck::fail(NAME, error.to_string()).with_error_hints(&error)
+        }
+    }
+}
+
+/// Check that API keys can be stored in the system keyring
+fn check_keyring(manager: &ApiKeyManager) -> DoctorCheck {
+    const NAME: &str = "Keyring";
+
+    match manager.check_backend() {
+        Ok(()) => DoctorCheck::pass(NAME, "System keyring is available".to_string()),
+        // Only cloud API keys live in the keyring, so local use still works
+        Err(e) => DoctorCheck::warn(NAME, e.to_string()).with_hints(vec![
+            "Start a Secret Service provider (e.g. gnome-keyring) to store API keys".to_string(),
+            "Or supply keys via CLIAI_<PROVIDER>_API_KEY or ~/.config/cliai/keys/<provider> (mode 600)".to_string(),
+            "Local Ollama models work without a keyring".to_string(),
+        ]),
+    }
+}
+
+/// Check that the proxy, CA bundles and client certificate load
+fn check_network(network: &NetworkConfig) -> DoctorCheck {
+    const NAME: &str = "Network";
+
+    if network.proxy.is_none() && network.ca_bundles.is_empty() && network.client_cert.is_none() {
+        return DoctorCheck::pass(NAME, "Direct connections, system CA store".to_string());
+    }
+
+    match HttpClientFactory::from_config(network) {
+        Ok(_) => {
+            let mut parts = Vec::new();
+            if network.proxy.is_some() {
+                parts.push("proxy".to_string());
+            }
+            if !network.ca_bundles.is_empty() {
+                parts.push(format!("{} extra CA bundle(s)", network.ca_bundles.len()));
+            }
+            if network.client_cert.is_some() {
+                parts.push("client certificate".to_string());
+            }
+            DoctorCheck::pass(NAME, format!("Loaded {}", parts.join(", ")))
+        }
+        Err(e) => DoctorCheck::fail(NAME, e.to_string()).with_hints(vec![
+            "Fix the paths under network in ~/.config/cliai/config.json".to_string(),
+            "Certificates must be PEM; client keys must be PKCS#8 PEM".to_string(),
+        ]),
+    }
+}
+
+/// Check Ollama reachability and that the configured model is installed
+async fn check_ollama(config: &Config) -> DoctorCheck {
+    const NAME: &str = "Ollama";
+
+    let provider = OllamaProvider::with_timeout(
+        config.ollama_url.clone(),
+        config.model.clone(),
+        NETWORK_CHECK_TIMEOUT,
+    );
+    // Without a cloud fallback an unreachable Ollama or a missing model leaves nothing
+    // to answer with
+    let local_problem = if config.cloud_token().is_some() {
+        DoctorCheck::warn
+    } else {
+        DoctorCheck::fail
+    };
+
+    match provider.list_models().await {
+        Ok(models) if OllamaProvider::is_installed(&models, &config.model) => DoctorCheck::pass(
+            NAME,
+            format!(
+                "Reachable at {}, model '{}' installed",
+                config.ollama_url, config.model
+            ),
+        ),
+        Ok(models) => local_problem(
+            NAME,
+            format!(
+                "Reachable at {}, but model '{}' is not installed ({} models available)",
+                config.ollama_url,
+                config.model,
+                models.len()
+            ),
+        )
+        .with_hints(vec![
+            format!("Install it: cliai model pull {}", config.model),
+            "Or pick an installed model: cliai list-models, then cliai select <name>".to_string(),
+        ]),
+        Err(e) => local_problem(NAME, format!("Not reachable at {}", config.ollama_url))
+            .with_error_hints(&e),
+    }
+}
+
+/// Check the cloud backend `/health` endpoint when a cloud token is configured
+async fn check_cloud(config: &Config) -> DoctorCheck {
+    const NAME: &str = "Cloud backend";
+
+    let Some(token) = config.cloud_token() else {
+        return DoctorCheck::pass(NAME, "Not configured (local only)".to_string());
+    };
+
+    let provider = CloudProvider::new(
+        config.backend_url.clone(),
+        token,
+        config.cloud_model.clone(),
+    );
+    let healthy = tokio::time::timeout(NETWORK_CHECK_TIMEOUT, provider.is_available())
+        .await
+        .unwrap_or(false);
+
+    if healthy {
+        DoctorCheck::pass(NAME, format!("{}/health is healthy", config.backend_url))
+    } else {
+        let error = anyhow!("Failed to connect to {}/health", config.backend_url);
+        DoctorCheck::fail(NAME, error.to_string()).with_error_hints(&error)
+    }
+}
+
+/// Check that the cached OS detection is readable, recent and matches the current shell
+fn check_os_cache(path: Option<&Path>, max_age: Duration) -> DoctorCheck {
+    const NAME: &str = "OS detection";
+
+    let Some(path) = path else {
+        return DoctorCheck::warn(NAME, "Could not determine cache directory".to_string());
+    };
+    let refresh_hint = vec![format!(
+        "Remove the cache to re-detect on the next run: rm {}",
+        path.display()
+    )];
+
+    if !path.exists() {
+        return DoctorCheck::pass(NAME, "Not cached yet, detected on next run".to_string());
+    }
+
+    let cached = fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<OSContext>(&content).ok());
+    let Some(cached) = cached else {
+        return DoctorCheck::warn(NAME, format!("{} is unreadable", path.display()))
+            .with_hints(refresh_hint);
+    };
+
+    let age = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .unwrap_or_default();
+    let days = age.as_secs() / (24 * 60 * 60);
+
+    if cached.shell != OSContext::detect_shell() {
+        DoctorCheck::warn(
+            NAME,
+            format!(
+                "Cached shell {:?} no longer matches the current shell",
+                cached.shell
+            ),
+        )
+        .with_hints(refresh_hint)
+    } else if age > max_age {
+        DoctorCheck::warn(
+            NAME,
+            format!(
+                "Cached {} days ago ({:?}), may be stale",
+                days, cached.os_type
+            ),
+        )
+        .with_hints(refresh_hint)
+    } else {
+        DoctorCheck::pass(
+            NAME,
+            format!(
+                "{:?} ({}), cached {} days ago",
+                cached.os_type, cached.architecture, days
+            ),
+        )
+    }
+}
+
+/// Check that a file cliai writes to is writable. Private files (chat history) should
+/// also not be readable by other users.
+fn check_file_access(name: &str, path: Option<&Path>, private: bool) -> DoctorCheck {
+    let Some(path) = path else {
+        return DoctorCheck::warn(name, "Could not determine config directory".to_string());
+    };
+
+    if !path.exists() {
+        return match path.parent() {
+            Some(parent) if parent.exists() && !is_writable_dir(parent) => {
+                DoctorCheck::fail(name, format!("{} is not writable", parent.display()))
+                    .with_error_hints(&anyhow!("Permission denied: {}", parent.display()))
+            }
+            _ => DoctorCheck::pass(
+                name,
+                format!("{} will be created on first use", path.display()),
+            ),
+        };
+    }
+
+    if let Err(e) = OpenOptions::new().append(true).open(path) {
+        let error = anyhow!("{}: {}", path.display(), e);
+        return DoctorCheck::fail(name, format!("{} is not writable", path.display()))
+            .with_error_hints(&error);
+    }
+
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path)
+            .map(|m| m.permissions().mode())
+            .unwrap_or(0);
+        if mode & 0o077 != 0 {
+            return DoctorCheck::warn(
+                name,
+                format!(
+                    "{} is accessible by other users (mode {:o})",
+                    path.display(),
+                    mode & 0o777
+                ),
+            )
+            .with_hints(vec![format!("Restrict it: chmod 600 {}", path.display())]);
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+
+    DoctorCheck::pass(name, format!("{} is writable", path.display()))
+}
+
+/// Check which shell commands will be generated for
+fn check_shell(shell_env: Option<&str>, detected: Shell) -> DoctorCheck {
+    const NAME: &str = "Shell";
+
+    match (shell_env, detected) {
+        (None, detected) => {
+            DoctorCheck::warn(NAME, format!("$SHELL is not set, assuming {:?}", detected))
+                .with_hints(vec![
+                    "Export SHELL with the path of your login shell".to_string()
+                ])
+        }
+        (Some(path), Shell::Unknown) => {
+            DoctorCheck::warn(NAME, format!("Unsupported shell {}", path)).with_hints(vec![
+                "Commands are generated for bash, zsh, fish and sh".to_string(),
+            ])
+        }
+        (Some(path), detected) => DoctorCheck::pass(NAME, format!("{:?} ({})", detected, path)),
+    }
+}
+
+/// Whether new files can be created in a directory
+fn is_writable_dir(dir: &Path) -> bool {
+    let probe: PathBuf = dir.join(format!(".cliai-doctor-{}", std::process::id()));
+    let writable = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .is_ok();
+    let _ = fs::remove_file(&probe);
+    writable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+
+        assert_eq!(check_config_file(Some(&path)).status, CheckStatus::Warn);
+
+        fs::write(&path, "{ not json").unwrap();
+        let check = check_config_file(Some(&path));
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(!check.hints.is_empty());
+
+        fs::write(
+            &path,
+            r#"{"model": "mistral", "ollama_url": "http://localhost:11434", "context_timeout": 0}"#,
+        )
+        .unwrap();
+        let check = check_config_file(Some(&path));
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(check.message.contains("context_timeout"));
+
+        fs::write(
+            &path,
+            r#"{"model": "mistral", "ollama_url": "http://localhost:11434"}"#,
+        )
+        .unwrap();
+        assert_eq!(check_config_file(Some(&path)).status, CheckStatus::Pass);
+    }
+
+    #[test]
+    fn test_check_network() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut network = NetworkConfig::default();
+        assert_eq!(check_network(&network).status, CheckStatus::Pass);
+
+        network.proxy = Some("http://proxy.corp:3128".to_string());
+        let check = check_network(&network);
+        assert_eq!(check.status, CheckStatus::Pass);
+        assert!(check.message.contains("proxy"));
+
+        network.ca_bundles = vec![dir.path().join("missing.pem").display().to_string()];
+        let check = check_network(&network);
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(check.message.contains("missing.pem"));
+    }
+
+    #[test]
+    fn test_check_os_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("os_context.json");
+
+        assert_eq!(
+            check_os_cache(Some(&path), OS_CACHE_MAX_AGE).status,
+            CheckStatus::Pass
+        );
+
+        fs::write(&path, "garbage").unwrap();
+        let check = check_os_cache(Some(&path), OS_CACHE_MAX_AGE);
+        assert_eq!(check.status, CheckStatus::Warn);
+        assert!(check.hints[0].contains("rm"));
+
+        let context = serde_json::json!({
+            "os_type": "Ubuntu",
+            "package_manager": "Apt",
+            "shell": OSContext::detect_shell(),
+            "paths": {
+                "config_dir": "/home/user/.config",
+                "home_dir": "/home/user",
+                "temp_dir": "/tmp",
+                "bin_dirs": ["/usr/bin"],
+                "os_release_file": null
+            },
+            "version_info": "24.04",
+            "architecture": "x86_64"
+        });
+        fs::write(&path, context.to_string()).unwrap();
+        assert_eq!(
+            check_os_cache(Some(&path), OS_CACHE_MAX_AGE).status,
+            CheckStatus::Pass
+        );
+        // Everything is stale with a zero maximum age
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(
+            check_os_cache(Some(&path), Duration::ZERO).status,
+            CheckStatus::Warn
+        );
+    }
+
+    #[test]
+    fn test_check_file_access() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.json");
+
+        assert_eq!(
+            check_file_access("History file", Some(&path), true).status,
+            CheckStatus::Pass
+        );
+
+        fs::write(&path, "{}").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+            let check = check_file_access("History file", Some(&path), true);
+            assert_eq!(check.status, CheckStatus::Warn);
+            assert!(check.hints[0].contains("chmod 600"));
+            // Logs hold no prompts, so shared read access is fine
+            assert_eq!(
+                check_file_access("Log file", Some(&path), false).status,
+                CheckStatus::Pass
+            );
+
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        }
+        assert_eq!(
+            check_file_access("History file", Some(&path), true).status,
+            CheckStatus::Pass
+        );
+    }
+
+    #[test]
+    fn test_check_shell_and_model_match() {
+        assert_eq!(
+            check_shell(Some("/usr/bin/zsh"), Shell::Zsh).status,
+            CheckStatus::Pass
+        );
+        assert_eq!(check_shell(None, Shell::Bash).status, CheckStatus::Warn);
+        assert_eq!(
+            check_shell(Some("/usr/bin/nu"), Shell::Unknown).status,
+            CheckStatus::Warn
+        );
+
+        let models = vec!["mistral:latest".to_string(), "llama3:8b".to_string()];
+        assert!(OllamaProvider::is_installed(&models, "mistral"));
+        assert!(OllamaProvider::is_installed(&models, "llama3:8b"));
+        assert!(!OllamaProvider::is_installed(&models, "llama3"));
+    }
+
+    #[test]
+    fn test_report_json_and_summary() {
+        let report = DoctorReport::new(vec![
+            DoctorCheck::pass("Config", "ok".to_string()),
+            DoctorCheck::fail("Ollama", "down".to_string())
+                .with_hints(vec!["ollama serve".to_string()]),
+        ]);
+        assert!(report.has_failures());
+        assert_eq!(report.count(CheckStatus::Pass), 1);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["checks"][0]["status"], "pass");
+        assert!(json["checks"][0].get("hints").is_none());
+        assert_eq!(json["checks"][1]["hints"][0], "ollama serve");
+    }
+}
@@ -128,9 +128,62 @@ impl fmt::Display for UserFriendlyError {
 
 impl std::error::Error for UserFriendlyError {}
 
+/// Messages of TLS failures as reported by OpenSSL, Secure Transport, SChannel and
+/// rustls. Bare words like "tls" or "certificate" also occur in model names and paths.
+const TLS_FAILURE_PHRASES: &[&str] = &[
+    "invalid peer certificate",
+    "certificate verify failed",
+    "unable to get local issuer certificate",
+    "self signed certificate",
+    "self-signed certificate",
+    "certificate has expired",
+    "certificate was not trusted",
+    "issued by an authority that is not trusted",
+    "handshake failure",
+    "tls handshake",
+    "wrong version number",
+];
+
 /// Convert common errors into user-friendly errors with actionable suggestions
 pub fn enhance_error(error: &anyhow::Error) -> UserFriendlyError {
     let error_msg = error.to_string().to_lowercase();
+    // TLS and proxy failures usually only show up in the underlying causes
+    let chain_msg = format!("{:#}", error).to_lowercase();
+
+    // TLS certificate errors
+    if TLS_FAILURE_PHRASES
+        .iter()
+        .any(|phrase| chain_msg.contains(phrase))
+    {
+        return UserFriendlyError::new(
+            ErrorType::Connection,
+            "TLS handshake with the AI provider failed".to_string(),
+        )
+        .with_suggestions(vec![
+            "If a corporate proxy or private CA re-signs traffic, add its PEM to network.ca_bundles in ~/.config/cliai/config.json".to_string(),
+            "If the server requires a client certificate, set network.client_cert and network.client_key".to_string(),
+            "Check that the provider URL uses the right scheme (http vs https) and host name".to_string(),
+            "Run 'cliai doctor' to check every provider connection".to_string(),
+        ])
+        .with_technical_details(format!("{:#}", error));
+    }
+
+    // Proxy errors
+    if (chain_msg.contains("proxy") && !chain_msg.contains("cloud proxy"))
+        || chain_msg.contains("socks")
+    {
+        return UserFriendlyError::new(
+            ErrorType::Connection,
+            "Could not reach the AI provider through the configured proxy".to_string(),
+        )
+        .with_suggestions(vec![
+            "Check network.proxy in ~/.config/cliai/config.json or the HTTPS_PROXY/ALL_PROXY environment variables".to_string(),
+            "Verify the proxy host, port and credentials".to_string(),
+            "Add local endpoints such as Ollama to network.no_proxy".to_string(),
+            "Run 'cliai doctor' to check every provider connection".to_string(),
+        ])
+        .with_technical_details(format!("{:#}", error));
+    }
 
     // Connection errors
     if error_msg.contains("connection refused") || error_msg.contains("failed to connect") {
@@ -159,7 +212,7 @@ pub fn enhance_error(error: &anyhow::Error) -> UserFriendlyError {
         )
         .with_suggestions(vec![
             "List available models: cliai list-models".to_string(),
-            "Install the default model: ollama pull mistral".to_string(),
+            "Install the default model: cliai model pull mistral".to_string(),
             "Select a different model: cliai select <model-name>".to_string(),
             "Check if Ollama is running: ollama serve".to_string(),
         ])
@@ -236,12 +289,12 @@ pub fn enhance_error(error: &anyhow::Error) -> UserFriendlyError {
         )
         .with_suggestions(vec![
             "Start Ollama for offline functionality: ollama serve".to_string(),
-            "Install a model: ollama pull mistral".to_string(),
+            "Install a model: cliai model pull mistral".to_string(),
             "Check provider status: cliai provider-status".to_string(),
             "Verify API keys are configured for cloud providers".to_string(),
             "Ensure at least one provider is properly configured".to_string(),
         ])
-        .with_technical_details(error.to_string());
+        .with_technical_details(format!("{:#}", error));
     }
 
     // Command validation errors
@@ -481,6 +534,39 @@ mod tests {
             .any(|s| s.contains("ollama serve")));
     }
 
+    #[test]
+    fn test_enhance_tls_and_proxy_errors() {
+        let error = anyhow!("invalid peer certificate: UnknownIssuer")
+            .context("Failed to connect to Anthropic at https://api.anthropic.com");
+        let enhanced = enhance_error(&error);
+        assert_eq!(enhanced.error_type, ErrorType::Connection);
+        assert!(enhanced.message.contains("TLS"));
+        assert!(enhanced
+            .suggestions
+            .iter()
+            .any(|s| s.contains("network.ca_bundles")));
+
+        let error = anyhow!("Failed to connect to Ollama: error sending request: unsuccessful tunnel: proxy authentication required");
+        let enhanced = enhance_error(&error);
+        assert!(enhanced.message.contains("proxy"));
+        assert!(enhanced
+            .suggestions
+            .iter()
+            .any(|s| s.contains("network.no_proxy")));
+
+        let error = anyhow!("error sending request: error:0A000086:SSL routines:tls_post_process_server_certificate:certificate verify failed");
+        assert!(enhance_error(&error).message.contains("TLS"));
+
+        // TLS-looking words elsewhere in a message are not a TLS failure
+        let error = anyhow!("model 'ssl-tls-certificate-handshake' not found");
+        assert_eq!(enhance_error(&error).error_type, ErrorType::Provider);
+        assert!(!enhance_error(&error).message.contains("TLS"));
+
+        // The cloud backend's own error prefix is not a proxy failure
+        let error = anyhow!("Cloud proxy failed: connection refused");
+        assert!(enhance_error(&error).message.contains("Unable to connect"));
+    }
+
     #[test]
     fn test_enhance_timeout_error() {
         let error = anyhow!("Request timed out");
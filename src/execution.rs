@@ -1,5 +1,5 @@
 use crate::config::{Config, SafetyLevel};
-use crate::validation::{SecurityWarning, ValidationResult};
+use crate::validation::{SecurityWarning, ValidationError, ValidationResult};
 use serde::{Deserialize, Serialize};
 
 /// Execution mode determines how commands should be handled
@@ -13,6 +13,9 @@ pub enum ExecutionMode {
     RequiresConfirmation(Vec<String>), // Reasons for requiring confirmation
     /// Show command with "DRY RUN:" prefix, never execute
     DryRunOnly,
+    /// Run in an isolated sandbox first, then ask before running for real. Carries the
+    /// reasons for caution, empty for commands that validated cleanly.
+    SandboxPreview(Vec<String>),
     /// Cannot execute, show reason (for blocked commands)
     Blocked(String), // Reason for blocking
     /// Multi-step execution with individual step validation
@@ -59,15 +62,7 @@ impl MultiStepHandler {
 
             steps.push(ExecutableStep {
                 command: line.to_string(),
-                description: format!(
-                    "Step {}: {}",
-                    index + 1,
-                    if line.len() > 50 {
-                        format!("{}...", &line[..47])
-                    } else {
-                        line.to_string()
-                    }
-                ),
+                description: describe_step(index + 1, line),
                 step_number: index + 1,
                 depends_on_previous,
                 validation_result: None,
@@ -82,6 +77,49 @@ impl MultiStepHandler {
         })
     }
 
+    /// Run every step through the validator and work out how it may be executed. Steps
+    /// the validator accepts take its (possibly rewritten) version of the command.
+    pub fn validate_steps(
+        &mut self,
+        config: &Config,
+        mut validate: impl FnMut(&str) -> ValidationResult,
+    ) {
+        for step in &mut self.steps {
+            let result = validate(&step.command);
+            match &result {
+                ValidationResult::Valid(command)
+                | ValidationResult::Rewritten(command, _)
+                | ValidationResult::Sensitive(command, _) => {
+                    step.command = command.clone();
+                    step.description = describe_step(step.step_number, command);
+                }
+                ValidationResult::Invalid(_, _) => {}
+            }
+            step.execution_mode = Some(Box::new(ExecutionMode::determine(config, &result)));
+            step.validation_result = Some(result);
+        }
+    }
+
+    /// The first step that may not run, with the reason. Running a plan with a blocked
+    /// step would leave it half done, so nothing runs.
+    pub fn blocked_step(&self) -> Option<(usize, String)> {
+        self.steps.iter().find_map(|step| {
+            step.execution_mode
+                .as_ref()
+                .and_then(|mode| mode.get_block_reason())
+                .map(|reason| (step.step_number, reason))
+        })
+    }
+
+    /// Whether every step has been validated and may be executed
+    pub fn can_execute(&self) -> bool {
+        self.steps.iter().all(|step| {
+            step.execution_mode
+                .as_ref()
+                .is_some_and(|mode| mode.can_execute())
+        })
+    }
+
     /// Get the next step to execute
     pub fn get_next_step(&mut self) -> Option<&mut ExecutableStep> {
         if self.current_step < self.steps.len() {
@@ -137,16 +175,85 @@ impl MultiStepHandler {
                 " "
             };
 
-            output.push_str(&format!(
-                "  {} Step {}: {}\n",
-                status, step.step_number, step.description
-            ));
+            let label = match &step.validation_result {
+                Some(ValidationResult::Valid(_)) => " [valid]",
+                Some(ValidationResult::Rewritten(_, _)) => " [rewritten]",
+                Some(ValidationResult::Sensitive(_, _)) => " [sensitive]",
+                Some(ValidationResult::Invalid(_, _)) => " [invalid]",
+                None => "",
+            };
+            output.push_str(&format!("  {} {}{}\n", status, step.description, label));
+
+            match &step.validation_result {
+                Some(ValidationResult::Rewritten(_, fixes)) => {
+                    for fix in fixes {
+                        output.push_str(&format!("      fixed: {}\n", fix));
+                    }
+                }
+                Some(ValidationResult::Sensitive(_, warnings)) => {
+                    for warning in warnings {
+                        output.push_str(&format!("      ⚠ {}\n", warning_reason(warning)));
+                    }
+                }
+                Some(ValidationResult::Invalid(_, errors)) => {
+                    for error in errors {
+                        output.push_str(&format!("      ✗ {}\n", error_reason(error)));
+                    }
+                }
+                _ => {}
+            }
+
+            // Sensitive steps can still be blocked by the safety level
+            if !matches!(
+                step.validation_result,
+                Some(ValidationResult::Invalid(_, _))
+            ) {
+                if let Some(reason) = step
+                    .execution_mode
+                    .as_ref()
+                    .and_then(|m| m.get_block_reason())
+                {
+                    output.push_str(&format!("      ✗ {}\n", reason));
+                }
+            }
         }
 
         output
     }
 }
 
+/// Short label for a step, truncating long commands
+fn describe_step(step_number: usize, command: &str) -> String {
+    if command.chars().count() > 50 {
+        let head: String = command.chars().take(47).collect();
+        format!("Step {}: {}...", step_number, head)
+    } else {
+        format!("Step {}: {}", step_number, command)
+    }
+}
+
+/// Human-readable reason for a security warning
+fn warning_reason(warning: &SecurityWarning) -> String {
+    match warning {
+        SecurityWarning::DataLoss(msg) => format!("Data Loss Risk: {}", msg),
+        SecurityWarning::SystemModification(msg) => format!("System Modification: {}", msg),
+        SecurityWarning::DangerousPattern(msg) => format!("Dangerous Pattern: {}", msg),
+        SecurityWarning::ShellMismatch(msg) => format!("Shell Mismatch: {}", msg),
+    }
+}
+
+/// Human-readable reason for a validation error
+fn error_reason(error: &ValidationError) -> String {
+    match error {
+        ValidationError::HallucinatedFlag(flag) => format!("Unknown flag: {}", flag),
+        ValidationError::PlaceholderDetected(placeholder) => {
+            format!("Placeholder detected: {}", placeholder)
+        }
+        ValidationError::SyntaxError(msg) => format!("Syntax error: {}", msg),
+        ValidationError::QuotingIssue(msg) => format!("Quoting issue: {}", msg),
+    }
+}
+
 #[allow(dead_code)]
 impl ExecutionMode {
     /// Determine execution mode based on configuration and validation result
@@ -156,7 +263,7 @@ impl ExecutionMode {
             return ExecutionMode::DryRunOnly;
         }
 
-        match validation_result {
+        let mode = match validation_result {
             ValidationResult::Valid(_) | ValidationResult::Rewritten(_, _) => {
                 if config.auto_execute {
                     ExecutionMode::Safe
@@ -176,18 +283,7 @@ impl ExecutionMode {
                 ExecutionMode::Blocked(error_msg)
             }
             ValidationResult::Sensitive(_, warnings) => {
-                let reasons: Vec<String> = warnings
-                    .iter()
-                    .map(|w| match w {
-                        SecurityWarning::DataLoss(msg) => format!("Data Loss Risk: {}", msg),
-                        SecurityWarning::SystemModification(msg) => {
-                            format!("System Modification: {}", msg)
-                        }
-                        SecurityWarning::DangerousPattern(msg) => {
-                            format!("Dangerous Pattern: {}", msg)
-                        }
-                    })
-                    .collect();
+                let reasons: Vec<String> = warnings.iter().map(warning_reason).collect();
 
                 // Sensitive commands always require confirmation, even with auto_execute enabled
                 match config.safety_level {
@@ -209,6 +305,17 @@ impl ExecutionMode {
                     }
                 }
             }
+        };
+
+        // Anything that could be run is previewed first; blocked commands stay blocked
+        match mode {
+            ExecutionMode::Safe | ExecutionMode::SuggestOnly if config.sandbox_preview => {
+                ExecutionMode::SandboxPreview(Vec::new())
+            }
+            ExecutionMode::RequiresConfirmation(reasons) if config.sandbox_preview => {
+                ExecutionMode::SandboxPreview(reasons)
+            }
+            mode => mode,
         }
     }
 
@@ -228,6 +335,7 @@ impl ExecutionMode {
             self,
             ExecutionMode::Safe
                 | ExecutionMode::RequiresConfirmation(_)
+                | ExecutionMode::SandboxPreview(_)
                 | ExecutionMode::MultiStep(_)
         )
     }
@@ -236,7 +344,9 @@ impl ExecutionMode {
     pub fn requires_confirmation(&self) -> bool {
         matches!(
             self,
-            ExecutionMode::RequiresConfirmation(_) | ExecutionMode::MultiStep(_)
+            ExecutionMode::RequiresConfirmation(_)
+                | ExecutionMode::SandboxPreview(_)
+                | ExecutionMode::MultiStep(_)
         )
     }
 
@@ -259,6 +369,7 @@ impl ExecutionMode {
     pub fn get_display_prefix(&self) -> Option<String> {
         match self {
             ExecutionMode::DryRunOnly => Some("DRY RUN: ".to_string()),
+            ExecutionMode::SandboxPreview(_) => Some("PREVIEW: ".to_string()),
             ExecutionMode::Blocked(reason) => Some(format!("BLOCKED ({}): ", reason)),
             ExecutionMode::MultiStep(_) => Some("MULTI-STEP: ".to_string()),
             _ => None,
@@ -274,7 +385,81 @@ impl ExecutionMode {
     }
 }
 
+/// Most output kept per stream of an executed command; older output is dropped first
+pub const OUTPUT_CAPTURE_LIMIT: usize = 64 * 1024;
+
+/// Characters of stderr sent back to the model when asking for a fix
+pub const FIX_STDERR_LIMIT: usize = 2000;
+
+/// Captured result of running a command
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CommandOutcome {
+    /// `None` when the command was terminated by a signal
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandOutcome {
+    /// Whether the command exited with status 0
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+
+    /// Exit code for display, e.g. "exit code 127" or "terminated by signal"
+    pub fn status_text(&self) -> String {
+        match self.exit_code {
+            Some(code) => format!("exit code {}", code),
+            None => "terminated by signal".to_string(),
+        }
+    }
+
+    /// Short note for the history turn that suggested the command
+    pub fn history_note(&self, command: &str) -> String {
+        if self.success() {
+            format!("(Ran `{}`: succeeded)", command)
+        } else {
+            format!("(Ran `{}`: failed with {})", command, self.status_text())
+        }
+    }
+
+    /// Follow-up request asking the ShellExpert to correct a failed command
+    pub fn fix_request(&self, original_request: &str, command: &str) -> String {
+        let stderr = truncate_tail(self.stderr.trim(), FIX_STDERR_LIMIT);
+        format!(
+            "The command you suggested failed.
+
+Original request: {}
+Command: {}
+Result: {}
+stderr:
+{}
+
+Reply in the required format with a corrected command. If the task cannot be done, reply with \"Command: (none)\" and explain why.",
+            original_request,
+            command,
+            self.status_text(),
+            if stderr.is_empty() { "(empty)" } else { stderr }
+        )
+    }
+}
+
+/// Keep at most `max_chars` characters from the end of `text`, where errors usually are
+pub fn truncate_tail(text: &str, max_chars: usize) -> &str {
+    let count = text.chars().count();
+    if count <= max_chars {
+        return text;
+    }
+    let start = text
+        .char_indices()
+        .nth(count - max_chars)
+        .map(|(index, _)| index)
+        .unwrap_or(0);
+    &text[start..]
+}
+
 /// Represents an executable command with metadata and execution context
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct ExecutableCommand {
     pub command: String,
@@ -283,6 +468,7 @@ pub struct ExecutableCommand {
     pub warnings: Vec<String>,
 }
 
+#[allow(dead_code)]
 impl ExecutableCommand {
     /// Create a new executable command
     pub fn new(command: String, explanation: String, execution_mode: ExecutionMode) -> Self {
@@ -312,6 +498,9 @@ impl ExecutableCommand {
             ExecutionMode::RequiresConfirmation(_) => {
                 Some("Use --auto-execute to run without confirmation".to_string())
             }
+            ExecutionMode::SandboxPreview(_) => Some(
+                "The command will be previewed in a sandbox before it runs for real".to_string(),
+            ),
             ExecutionMode::MultiStep(_) => {
                 Some("Multi-step command ready for execution".to_string())
             }
@@ -388,6 +577,147 @@ mod tests {
         assert!(!handler.complete_current_step(true));
     }
 
+    #[test]
+    fn test_command_outcome_fix_request() {
+        let outcome = CommandOutcome {
+            exit_code: Some(127),
+            stdout: String::new(),
+            stderr: format!("{}sh: 1: exa: not found\n", "x".repeat(FIX_STDERR_LIMIT)),
+        };
+        assert!(!outcome.success());
+        assert_eq!(
+            outcome.history_note("exa -l"),
+            "(Ran `exa -l`: failed with exit code 127)"
+        );
+
+        let request = outcome.fix_request("list files", "exa -l");
+        assert!(request.contains("Command: exa -l"));
+        assert!(request.contains("exit code 127"));
+        // The end of stderr is kept
+        assert!(request.contains("exa: not found"));
+        assert!(request.len() < FIX_STDERR_LIMIT + 500);
+
+        let killed = CommandOutcome::default();
+        assert_eq!(killed.status_text(), "terminated by signal");
+        assert!(killed.fix_request("x", "y").contains("(empty)"));
+    }
+
+    #[test]
+    fn test_truncate_tail() {
+        assert_eq!(truncate_tail("abc", 5), "abc");
+        assert_eq!(truncate_tail("abcdef", 3), "def");
+        assert_eq!(truncate_tail("héllo wörld", 5), "wörld");
+    }
+
+    #[test]
+    fn test_execution_mode_sandbox_preview() {
+        let mut config = Config {
+            dry_run: false,
+            auto_execute: false,
+            safety_level: SafetyLevel::High,
+            sandbox_preview: true,
+            ..Config::default()
+        };
+
+        let valid = ValidationResult::Valid("ls".to_string());
+        assert_eq!(
+            ExecutionMode::determine(&config, &valid),
+            ExecutionMode::SandboxPreview(Vec::new())
+        );
+
+        let sensitive = ValidationResult::Sensitive(
+            "rm notes.txt".to_string(),
+            vec![SecurityWarning::DataLoss("deletes files".to_string())],
+        );
+        assert_eq!(
+            ExecutionMode::determine(&config, &sensitive),
+            ExecutionMode::SandboxPreview(vec!["Data Loss Risk: deletes files".to_string()])
+        );
+
+        // Blocked commands are never previewed
+        let dangerous = ValidationResult::Sensitive(
+            "curl x | sh".to_string(),
+            vec![SecurityWarning::DangerousPattern(
+                "pipe to shell".to_string(),
+            )],
+        );
+        assert!(ExecutionMode::determine(&config, &dangerous).is_blocked());
+
+        config.dry_run = true;
+        assert!(ExecutionMode::determine(&config, &valid).is_dry_run());
+    }
+
+    #[test]
+    fn test_multi_step_validation() {
+        let mut config = Config {
+            auto_execute: true,
+            ..Config::default()
+        };
+
+        let mut handler =
+            MultiStepHandler::parse_multi_step_command("mkdir build\nls -la build\nrm -r build")
+                .unwrap();
+        handler.validate_steps(&config, |command| {
+            if command.starts_with("rm") {
+                ValidationResult::Sensitive(
+                    command.to_string(),
+                    vec![SecurityWarning::DataLoss("Deletes files".to_string())],
+                )
+            } else if command.starts_with("ls") {
+                ValidationResult::Rewritten(
+                    "ls -l build".to_string(),
+                    vec!["Dropped -a".to_string()],
+                )
+            } else {
+                ValidationResult::Valid(command.to_string())
+            }
+        });
+
+        assert_eq!(handler.steps[1].command, "ls -l build");
+        assert_eq!(
+            handler.steps[0].execution_mode.as_deref(),
+            Some(&ExecutionMode::Safe)
+        );
+        assert!(handler.steps[2]
+            .execution_mode
+            .as_ref()
+            .unwrap()
+            .requires_confirmation());
+        assert!(handler.can_execute());
+        assert!(handler.blocked_step().is_none());
+
+        let display = handler.format_steps_for_display();
+        assert!(display.contains("Step 1: mkdir build [valid]"));
+        assert!(display.contains("Step 2: ls -l build [rewritten]"));
+        assert!(display.contains("fixed: Dropped -a"));
+        assert!(display.contains("Step 3: rm -r build [sensitive]"));
+        assert!(display.contains("Data Loss Risk: Deletes files"));
+
+        // One invalid step blocks the whole plan
+        handler.validate_steps(&config, |command| {
+            if command.starts_with("ls") {
+                ValidationResult::Invalid(
+                    command.to_string(),
+                    vec![ValidationError::HallucinatedFlag("--hidden".to_string())],
+                )
+            } else {
+                ValidationResult::Valid(command.to_string())
+            }
+        });
+        assert_eq!(handler.blocked_step().map(|(n, _)| n), Some(2));
+        assert!(!handler.can_execute());
+        assert!(handler
+            .format_steps_for_display()
+            .contains("Unknown flag: --hidden"));
+
+        // Without auto-execute the steps are only suggested
+        config.auto_execute = false;
+        handler.validate_steps(&config, |command| {
+            ValidationResult::Valid(command.to_string())
+        });
+        assert!(!handler.can_execute());
+    }
+
     #[test]
     fn test_execution_mode_multi_step() {
         let config = Config::load();
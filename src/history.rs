@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
 
-#[derive(Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ChatTurn {
     pub role: String,
     pub content: String,
@@ -123,6 +123,14 @@ impl History {
         }
     }
 
+    /// Append a note to the most recent turn, e.g. the outcome of running its command
+    pub fn annotate_last_turn(&mut self, note: &str) {
+        if let Some(turn) = self.turns.last_mut() {
+            turn.content.push_str("\n\n");
+            turn.content.push_str(note);
+        }
+    }
+
     pub fn clear(&mut self) {
         self.turns.clear();
         let _ = self.save();
@@ -321,7 +329,7 @@ impl History {
         }
     }
 
-    fn get_history_path() -> Option<PathBuf> {
+    pub fn get_history_path() -> Option<PathBuf> {
         dirs::config_dir().map(|mut path| {
             path.push("cliai");
             path.push("history.json");
@@ -348,6 +356,20 @@ mod tests {
         history
     }
 
+    #[test]
+    fn test_annotate_last_turn() {
+        let mut history = create_test_history();
+        history.annotate_last_turn("(cancelled)");
+
+        assert_eq!(history.turns.len(), 6);
+        assert_eq!(history.turns[5].content, "Third response\n\n(cancelled)");
+        assert_eq!(history.turns[4].content, "Third message");
+
+        let mut empty = History { turns: Vec::new() };
+        empty.annotate_last_turn("(cancelled)");
+        assert!(empty.turns.is_empty());
+    }
+
     #[test]
     fn test_context_window_shell_expert() {
         let window = ContextWindow::shell_expert();
@@ -1,33 +1,53 @@
 pub mod agents;
 pub mod api_keys;
 pub mod builtin_commands;
+pub mod cancellation;
 pub mod config;
+pub mod confirm;
 pub mod context;
+pub mod doctor;
 pub mod error_handling;
 pub mod execution;
 pub mod history;
 pub mod intent;
 pub mod logging;
+pub mod network;
 pub mod os_context;
 pub mod performance;
+pub mod provider_health;
 pub mod providers;
 pub mod quoting;
+pub mod replay;
+pub mod response_cache;
+pub mod sandbox;
 pub mod test_suite;
+pub mod undo;
+pub mod usage;
 pub mod validation;
 
 pub use agents::*;
 pub use api_keys::*;
 pub use builtin_commands::*;
+pub use cancellation::*;
 pub use config::*;
+pub use confirm::*;
 pub use context::*;
+pub use doctor::*;
 pub use error_handling::*;
 pub use execution::*;
 pub use history::*;
 pub use intent::*;
 pub use logging::*;
+pub use network::*;
 pub use os_context::*;
 pub use performance::*;
+pub use provider_health::*;
 pub use providers::*;
 pub use quoting::*;
+pub use replay::*;
+pub use response_cache::*;
+pub use sandbox::*;
 pub use test_suite::*;
+pub use undo::*;
+pub use usage::*;
 pub use validation::*;
@@ -1,3 +1,4 @@
+use crate::providers::TokenUsage;
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -66,6 +67,12 @@ pub struct LogContext {
     pub success: Option<bool>,
 }
 
+impl Default for LogContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[allow(dead_code)]
 impl LogContext {
     /// Create a new empty log context
@@ -394,6 +401,42 @@ impl PrivacyLogger {
         self.write_log_entry(&entry)
     }
 
+    /// Log token usage and cost of a request (privacy-safe: counts only)
+    pub fn log_token_usage(
+        &self,
+        provider_type: &str,
+        model: &str,
+        agent: &str,
+        usage: Option<&TokenUsage>,
+        cost_usd: Option<f64>,
+    ) -> Result<()> {
+        let context = LogContext::new()
+            .with_component("provider".to_string())
+            .with_operation("token_usage".to_string())
+            .with_provider_type(provider_type.to_string());
+
+        let tokens = match usage {
+            Some(usage) => format!(
+                "{} prompt tokens, {} completion tokens",
+                usage.prompt_tokens, usage.completion_tokens
+            ),
+            None => "usage not reported".to_string(),
+        };
+        let cost = match cost_usd {
+            Some(cost) => format!("${:.6}", cost),
+            None => "unpriced".to_string(),
+        };
+
+        self.log_info(
+            LogCategory::Provider,
+            format!(
+                "{} usage for {} ({}): {}, {}",
+                provider_type, agent, model, tokens, cost
+            ),
+            Some(context),
+        )
+    }
+
     /// Log performance metrics (privacy-safe)
     pub fn log_performance(
         &self,
@@ -527,7 +570,7 @@ impl PrivacyLogger {
     }
 
     /// Get the log file path
-    fn get_log_file_path() -> Result<PathBuf> {
+    pub fn get_log_file_path() -> Result<PathBuf> {
         let config_dir =
             dirs::config_dir().ok_or_else(|| anyhow!("Could not find config directory"))?;
 
@@ -1,40 +1,59 @@
+use anyhow::Context;
 use clap::{Parser, Subcommand};
 use colored::*;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
 use std::env;
-use std::io::{self, Write};
-use std::process::Command;
+use std::io::{self, IsTerminal, Write};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
 
 mod agents;
 mod api_keys;
 mod builtin_commands;
+mod cancellation;
 mod config;
+mod confirm;
 mod context;
+mod doctor;
 mod error_handling;
 mod execution;
 mod history;
 mod intent;
 mod logging;
+mod network;
 mod os_context;
 mod performance;
+mod provider_health;
 mod providers;
 mod quoting;
+mod replay;
+mod response_cache;
+mod sandbox;
 mod test_suite;
+mod undo;
+mod usage;
 mod validation;
 
 use agents::Orchestrator;
-use cliai::ApiKeyManager;
+use api_keys::{ApiKeyManager, KeySource};
+use cancellation::{is_cancelled, CancellationToken, CANCELLED_EXIT_CODE};
 use config::{Config, SafetyLevel};
+use confirm::ConfirmChoice;
 use error_handling::{
     display_config_change, display_info, display_interface_reminder, display_success, display_tip,
     display_warning, enhance_error,
 };
-use execution::{ExecutableCommand, ExecutionMode, MultiStepHandler};
+use execution::{
+    CommandOutcome, ExecutableCommand, ExecutionMode, MultiStepHandler, OUTPUT_CAPTURE_LIMIT,
+};
 use history::History;
-use logging::{get_logger, init_logger};
+use logging::{get_logger, init_logger, LogCategory};
+use os_context::ExecutionShell;
 use performance::{OperationType, PerformanceStats};
-use providers::{CircuitBreakerState, ProviderType};
+use providers::{AIProvider, CircuitBreakerState, ModelDetails, OllamaProvider, ProviderType};
 use test_suite::{TestCategory, TestSuite};
+use undo::UndoJournal;
+use usage::{UsageLedger, UsageTotals};
 use validation::{SecurityWarning, ValidationError, ValidationResult};
 
 /// Copy-paste safe command output structure
@@ -143,6 +162,10 @@ struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
 
+    /// Bypass the response cache for this request
+    #[arg(long)]
+    no_cache: bool,
+
     /// The prompt to send to CLIAI
     #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     prompt: Vec<String>,
@@ -154,6 +177,11 @@ enum Commands {
     Config,
     /// List available Ollama models
     ListModels,
+    /// Manage local Ollama models
+    Model {
+        #[command(subcommand)]
+        action: ModelCommand,
+    },
     /// List supported AI providers
     ListProviders,
     /// Set API key for a provider
@@ -175,7 +203,7 @@ enum Commands {
     },
     /// Select the default model to use
     Select {
-        /// Name of the model
+        /// Model name, optionally provider-qualified (ollama/llama3, cloud/gpt-4o)
         name: String,
     },
     /// Set a custom prefix/alias for CLIAI (e.g. 'jarvis')
@@ -185,6 +213,15 @@ enum Commands {
     },
     /// Clear chat history
     Clear,
+    /// Restore files saved before a command overwrote, moved or deleted them
+    Undo {
+        /// Which snapshot to restore, counting back from the latest (1)
+        #[arg(default_value_t = 1)]
+        n: usize,
+        /// List saved snapshots instead of restoring one
+        #[arg(long)]
+        list: bool,
+    },
     /// Enable or disable auto-execution of commands
     AutoExecute {
         /// Mode: on, off, enable, or disable
@@ -195,6 +232,11 @@ enum Commands {
         /// Mode: on, off, enable, or disable
         mode: String,
     },
+    /// Preview commands in an isolated sandbox before running them
+    Sandbox {
+        /// Mode: on, off, enable, or disable
+        mode: String,
+    },
     /// Set safety level (low, medium, high)
     SafetyLevel {
         /// Safety level: low, medium, or high
@@ -212,6 +254,18 @@ enum Commands {
     },
     /// Check AI provider status and availability
     ProviderStatus,
+    /// Show token usage and cost per provider and agent
+    Usage {
+        /// Number of days in the daily breakdown
+        #[arg(long, default_value_t = 7)]
+        days: u32,
+    },
+    /// Diagnose configuration, providers and local files
+    Doctor {
+        /// Print the report as JSON (for bug reports)
+        #[arg(long)]
+        json: bool,
+    },
     /// Run the comprehensive test suite
     Test {
         /// Run only specific categories (comma-separated)
@@ -241,6 +295,27 @@ enum Commands {
     PerformanceStatus,
 }
 
+#[derive(Subcommand)]
+enum ModelCommand {
+    /// Download a model
+    Pull {
+        /// Name of the model (e.g. mistral, llama3:8b)
+        name: String,
+    },
+    /// Remove an installed model
+    Rm {
+        /// Name of the model
+        name: String,
+    },
+    /// Show parameters, context length, quantization and size of a model
+    Show {
+        /// Name of the model
+        name: String,
+    },
+    /// List models currently loaded in memory
+    Ps,
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Initialize the privacy-preserving logger
@@ -249,6 +324,10 @@ async fn main() -> anyhow::Result<()> {
     }
 
     let app_config = Config::load();
+    // Never fall back to direct connections: they would bypass the configured proxy and
+    // CA bundles
+    network::init_http_clients(&app_config.network)
+        .context("Invalid network settings in ~/.config/cliai/config.json")?;
 
     // Log application startup
     if let Ok(logger) = get_logger() {
@@ -323,7 +402,7 @@ async fn main() -> anyhow::Result<()> {
                                         );
                                         println!(
                                             "  {}",
-                                            "   Try running: ollama pull mistral".dimmed()
+                                            "   Try running: cliai model pull mistral".dimmed()
                                         );
                                     }
                                 }
@@ -339,10 +418,15 @@ async fn main() -> anyhow::Result<()> {
                         if models.is_empty() {
                             // Global fallback if everything is empty
                             println!("  {}", "No models found. Please check if Ollama is running and has models installed.".yellow());
-                            println!("  {}", "Try: ollama pull mistral".dimmed());
+                            println!("  {}", "Try: cliai model pull mistral".dimmed());
                         } else {
+                            let active_model = if app_config.use_cloud {
+                                &app_config.cloud_model
+                            } else {
+                                &app_config.model
+                            };
                             for model in models {
-                                if model == app_config.model {
+                                if &model == active_model {
                                     println!("* {} {}", model.green().bold(), "(active)".dimmed());
                                 } else {
                                     println!("  {}", model);
@@ -355,7 +439,7 @@ async fn main() -> anyhow::Result<()> {
                         enhanced_error.display();
 
                         // If error occurred but we suspect it's just local being down (and no cloud configured), give hint
-                        if !local_available && app_config.api_token.is_none() {
+                        if !local_available && app_config.cloud_token().is_none() {
                             println!(
                                 "\n{}",
                                 "Tip: Ensure Ollama is running with 'ollama serve'".dimmed()
@@ -366,7 +450,7 @@ async fn main() -> anyhow::Result<()> {
                 return Ok(());
             }
             Commands::ListProviders => {
-                let api_key_manager = ApiKeyManager::new();
+                let api_key_manager = ApiKeyManager::from_config(&app_config.api_keys);
                 let providers = ApiKeyManager::get_supported_providers();
                 let configured_providers = api_key_manager.list_configured_providers();
 
@@ -374,10 +458,13 @@ async fn main() -> anyhow::Result<()> {
 
                 for (provider_id, config) in &providers {
                     let status = if config.requires_key {
-                        if configured_providers.contains(provider_id) {
-                            "✅ Configured".green()
-                        } else {
-                            "❌ No API key".red()
+                        match api_key_manager.key_source(provider_id) {
+                            Some(source) => format!("✅ Configured ({})", source).green(),
+                            // The legacy plaintext token still counts for the cloud backend
+                            None if provider_id == "cloud" && app_config.api_token.is_some() => {
+                                "✅ Configured (config.json api_token)".green()
+                            }
+                            None => "❌ No API key".red(),
                         }
                     } else {
                         "✅ Ready".green()
@@ -403,12 +490,16 @@ async fn main() -> anyhow::Result<()> {
                         "{}",
                         "Example: cliai set-key openai sk-your-key-here".dimmed()
                     );
+                    println!(
+                        "{}",
+                        "Keys are also read from CLIAI_<PROVIDER>_API_KEY and ~/.config/cliai/keys/<provider>".dimmed()
+                    );
                 }
 
                 return Ok(());
             }
             Commands::SetKey { provider, key } => {
-                let api_key_manager = ApiKeyManager::new();
+                let api_key_manager = ApiKeyManager::from_config(&app_config.api_keys);
                 let providers = ApiKeyManager::get_supported_providers();
 
                 if !providers.contains_key(&provider) {
@@ -435,12 +526,19 @@ async fn main() -> anyhow::Result<()> {
                 }
 
                 match api_key_manager.set_key(&provider, &key) {
-                    Ok(_) => {
+                    Ok(stored_in) => {
                         println!(
-                            "{} API key configured for {}",
+                            "{} API key configured for {} (stored in {})",
                             "✅".green(),
-                            provider.bold()
+                            provider.bold(),
+                            stored_in
                         );
+                        if let Some(KeySource::Env(var)) = api_key_manager.key_source(&provider) {
+                            display_warning(&format!(
+                                "{} is set and takes precedence over the stored key",
+                                var
+                            ));
+                        }
                         println!(
                             "You can now use models: {}",
                             provider_config.models.join(", ").dimmed()
@@ -457,7 +555,7 @@ async fn main() -> anyhow::Result<()> {
                 return Ok(());
             }
             Commands::RemoveKey { provider } => {
-                let api_key_manager = ApiKeyManager::new();
+                let api_key_manager = ApiKeyManager::from_config(&app_config.api_keys);
 
                 if !api_key_manager.has_key(&provider) {
                     println!(
@@ -490,7 +588,7 @@ async fn main() -> anyhow::Result<()> {
                 return Ok(());
             }
             Commands::TestKey { provider } => {
-                let api_key_manager = ApiKeyManager::new();
+                let api_key_manager = ApiKeyManager::from_config(&app_config.api_keys);
 
                 if !api_key_manager.has_key(&provider) {
                     println!("{} No API key found for provider: {}", "❌".red(), provider);
@@ -526,9 +624,131 @@ async fn main() -> anyhow::Result<()> {
                 }
                 return Ok(());
             }
+            Commands::Model { action } => {
+                let provider = ollama_provider(&app_config);
+                match action {
+                    ModelCommand::Pull { name } => {
+                        if let Err(e) = pull_model_with_progress(&provider, &name).await {
+                            enhance_error(&e).display();
+                            std::process::exit(1);
+                        }
+                        display_success(&format!("Pulled model: {}", name.bold().yellow()));
+                    }
+                    ModelCommand::Rm { name } => {
+                        print!("{} ", format!("Remove model {}? (y/n):", name).bold());
+                        io::stdout().flush()?;
+
+                        let mut input = String::new();
+                        io::stdin().read_line(&mut input)?;
+
+                        if input.trim().to_lowercase() != "y" {
+                            println!("{}", "Cancelled.".dimmed());
+                            return Ok(());
+                        }
+
+                        match provider.delete_model(&name).await {
+                            Ok(()) => {
+                                display_success(&format!("Removed model: {}", name.bold()));
+                                if name == app_config.model {
+                                    display_warning(
+                                        "This was the selected model; choose another with: cliai select <name>",
+                                    );
+                                }
+                            }
+                            Err(e) => {
+                                enhance_error(&e).display();
+                                std::process::exit(1);
+                            }
+                        }
+                    }
+                    ModelCommand::Show { name } => match provider.show_model(&name).await {
+                        Ok(details) => print_model_details(&details),
+                        Err(e) => {
+                            enhance_error(&e).display();
+                            std::process::exit(1);
+                        }
+                    },
+                    ModelCommand::Ps => match provider.running_models().await {
+                        Ok(models) => {
+                            println!("{}", "Loaded Models:".bold().cyan());
+                            if models.is_empty() {
+                                println!("  {}", "No models loaded".dimmed());
+                            }
+                            for model in models {
+                                let placement = if model.size_vram == 0 {
+                                    "CPU".to_string()
+                                } else if model.size_vram >= model.size {
+                                    "GPU".to_string()
+                                } else {
+                                    format!("{}% GPU", model.size_vram * 100 / model.size.max(1))
+                                };
+                                println!(
+                                    "  • {} - {} ({}){}",
+                                    model.name.green(),
+                                    HumanBytes(model.size),
+                                    placement,
+                                    model
+                                        .expires_at
+                                        .map(|at| format!(", unloads at {}", at)
+                                            .dimmed()
+                                            .to_string())
+                                        .unwrap_or_default()
+                                );
+                            }
+                        }
+                        Err(e) => {
+                            enhance_error(&e).display();
+                            std::process::exit(1);
+                        }
+                    },
+                }
+                return Ok(());
+            }
             Commands::Select { name } => {
                 let mut new_config = app_config.clone();
-                new_config.model = name.clone();
+                let selected = match new_config.select_model(&name) {
+                    Ok(selected) => selected,
+                    Err(e) => {
+                        enhance_error(&e).display();
+                        return Ok(());
+                    }
+                };
+
+                if selected == ProviderType::Local {
+                    // Offer to download a model Ollama does not have yet
+                    let model = new_config.model.clone();
+                    let provider = ollama_provider(&app_config);
+                    if let Ok(models) = provider.list_models().await {
+                        if !OllamaProvider::is_installed(&models, &model) {
+                            print!(
+                                "{} ",
+                                format!("Model {} is not installed. Pull it now? (y/n):", model)
+                                    .bold()
+                            );
+                            io::stdout().flush()?;
+
+                            let mut input = String::new();
+                            io::stdin().read_line(&mut input)?;
+
+                            if input.trim().to_lowercase() == "y" {
+                                if let Err(e) = pull_model_with_progress(&provider, &model).await {
+                                    enhance_error(&e).display();
+                                    return Ok(());
+                                }
+                            } else {
+                                display_warning(&format!(
+                                    "Model {} is not installed; pull it later with: cliai model pull {}",
+                                    model, model
+                                ));
+                            }
+                        }
+                    }
+                } else if selected == ProviderType::Cloud && app_config.cloud_token().is_none() {
+                    display_warning(
+                        "No cloud token is configured; requests stay local until you run: cliai set-key cloud <token>",
+                    );
+                }
+
                 match new_config.save() {
                     Ok(_) => {
                         display_success(&format!("Switched to model: {}", name.bold().yellow()))
@@ -598,6 +818,57 @@ async fn main() -> anyhow::Result<()> {
                 println!("{} Chat history cleared.", "🧹".cyan());
                 return Ok(());
             }
+            Commands::Undo { n, list } => {
+                let result = UndoJournal::new(&app_config.undo).and_then(|journal| {
+                    journal.prune()?;
+                    if list {
+                        print_undo_snapshots(&journal.list()?);
+                        return Ok(());
+                    }
+
+                    let snapshot = match journal.get(n) {
+                        Ok(snapshot) => snapshot,
+                        Err(e) => {
+                            display_info(&format!("{} (see `cliai undo --list`)", e));
+                            return Ok(());
+                        }
+                    };
+                    println!(
+                        "{} {} {}",
+                        "↩️".cyan(),
+                        "Snapshot taken before:".bold(),
+                        snapshot.command
+                    );
+                    println!(
+                        "   {} in {}",
+                        snapshot.created_at.format("%Y-%m-%d %H:%M:%S"),
+                        snapshot.cwd.display()
+                    );
+                    for entry in &snapshot.entries {
+                        println!("   • {}", entry.original.display());
+                    }
+                    print!(
+                        "{} ",
+                        "Restore these paths, replacing their current contents? (y/n):".bold()
+                    );
+                    io::stdout().flush()?;
+
+                    let mut input = String::new();
+                    io::stdin().read_line(&mut input)?;
+                    if input.trim().to_lowercase() != "y" {
+                        display_info("Undo cancelled");
+                        return Ok(());
+                    }
+                    journal.restore(&snapshot)?;
+                    display_success(&format!("Restored {} path(s)", snapshot.entries.len()));
+                    Ok(())
+                });
+                if let Err(e) = result {
+                    let enhanced_error = enhance_error(&e);
+                    enhanced_error.display();
+                }
+                return Ok(());
+            }
             Commands::AutoExecute { mode } => {
                 let mut config = app_config.clone();
                 let enabled = match mode.to_lowercase().as_str() {
@@ -671,6 +942,49 @@ async fn main() -> anyhow::Result<()> {
                 }
                 return Ok(());
             }
+            Commands::Sandbox { mode } => {
+                let mut config = app_config.clone();
+                let enabled = match mode.to_lowercase().as_str() {
+                    "on" | "enable" | "true" | "yes" => true,
+                    "off" | "disable" | "false" | "no" => false,
+                    _ => {
+                        eprintln!(
+                            "{} Invalid mode. Use: on, off, enable, or disable",
+                            "❌".red()
+                        );
+                        return Ok(());
+                    }
+                };
+
+                let old_value = config.sandbox_preview.to_string();
+                match config.set_sandbox_preview(enabled) {
+                    Ok(_) => {
+                        display_config_change("sandbox_preview", &old_value, &enabled.to_string());
+                        if enabled {
+                            display_info(
+                                "Commands will run in a sandbox first; you decide afterwards whether to run them for real",
+                            );
+                            match sandbox::detect_backend().await {
+                                Ok(backend) => display_tip(&format!(
+                                    "Using {} with read-only system paths and no network",
+                                    backend
+                                )),
+                                Err(e) => display_warning(&format!(
+                                    "No sandbox is available on this machine ({}); commands will only be confirmed",
+                                    e
+                                )),
+                            }
+                        } else {
+                            display_info("Sandbox preview disabled");
+                        }
+                    }
+                    Err(e) => {
+                        let enhanced_error = enhance_error(&e);
+                        enhanced_error.display();
+                    }
+                }
+                return Ok(());
+            }
             Commands::SafetyLevel { level } => {
                 let mut config = app_config.clone();
                 let old_level = format!("{:?}", config.safety_level);
@@ -843,16 +1157,59 @@ async fn main() -> anyhow::Result<()> {
                 if !status.is_empty() {
                     println!("\n{}", "Provider Details:".bold());
                     for (name, provider_type, circuit_state) in status {
-                        let type_str = match provider_type {
-                            ProviderType::Local => "Local",
-                            ProviderType::Cloud => "Cloud",
-                        };
                         let state_str = match circuit_state {
                             CircuitBreakerState::Closed => "✅ Normal".green(),
                             CircuitBreakerState::Open => "❌ Failed".red(),
                             CircuitBreakerState::HalfOpen => "⚠️  Testing".yellow(),
                         };
-                        println!("  {} ({}): {}", name, type_str, state_str);
+                        println!(
+                            "  {} ({}): {}",
+                            name,
+                            provider_type_label(&provider_type),
+                            state_str
+                        );
+                    }
+                }
+
+                // Show breaker history persisted across invocations
+                let health = orchestrator.get_provider_health();
+                if !health.is_empty() {
+                    println!("\n{}", "Provider History:".bold());
+                    for (name, provider_type, record) in health {
+                        // Named Ollama endpoints are listed under their own name
+                        let label = if name == format!("{:?}", provider_type) {
+                            provider_type_label(&provider_type).to_string()
+                        } else {
+                            format!("{} ({})", provider_type_label(&provider_type), name)
+                        };
+                        println!(
+                            "  {}: {} successes, {} failures ({} consecutive)",
+                            label,
+                            record.total_successes,
+                            record.total_failures,
+                            record.failure_count
+                        );
+                        println!(
+                            "    Last success: {}",
+                            format_unix_time(record.last_success)
+                        );
+                        println!(
+                            "    Last failure: {}",
+                            format_unix_time(record.last_failure)
+                        );
+                        if record.opened_at.is_some() {
+                            let retry = match orchestrator.get_retry_after(&name) {
+                                Some(remaining) => {
+                                    format!("skipped for another {}s", remaining.as_secs().max(1))
+                                }
+                                None => "will be retried on the next request".to_string(),
+                            };
+                            println!(
+                                "    Circuit opened: {} ({})",
+                                format_unix_time(record.opened_at),
+                                retry
+                            );
+                        }
                     }
                 }
 
@@ -871,6 +1228,66 @@ async fn main() -> anyhow::Result<()> {
 
                 return Ok(());
             }
+            Commands::Usage { days } => {
+                let ledger = UsageLedger::new()?;
+                let records = ledger.load();
+
+                println!("{}", "📊 Token Usage:".bold().cyan());
+                if records.is_empty() {
+                    println!("No usage recorded yet.");
+                    return Ok(());
+                }
+
+                let since = (chrono::Local::now().date_naive()
+                    - chrono::Duration::days(i64::from(days.max(1)) - 1))
+                .format("%Y-%m-%d")
+                .to_string();
+
+                println!("\n{}", format!("Daily (last {} days):", days.max(1)).bold());
+                print_usage_rows(&usage::summarize(&records, |record| {
+                    (record.date >= since)
+                        .then(|| format!("{}  {} / {}", record.date, record.provider, record.agent))
+                }));
+
+                println!("\n{}", "Monthly:".bold());
+                print_usage_rows(&usage::summarize(&records, |record| {
+                    Some(format!(
+                        "{}  {} / {}",
+                        record.month(),
+                        record.provider,
+                        record.agent
+                    ))
+                }));
+
+                let month = UsageLedger::current_month();
+                let spent = ledger.month_cost(&month);
+                println!();
+                match app_config.monthly_budget_usd {
+                    Some(budget) if spent >= budget => println!(
+                        "Budget: {} - cloud providers are skipped until next month",
+                        format!("${:.2} of ${:.2} spent", spent, budget).red()
+                    ),
+                    Some(budget) => println!(
+                        "Budget: {}",
+                        format!("${:.2} of ${:.2} spent", spent, budget).green()
+                    ),
+                    None => println!("Spent this month: ${:.2} (no budget set)", spent),
+                }
+
+                return Ok(());
+            }
+            Commands::Doctor { json } => {
+                let report = doctor::run_diagnostics(&app_config).await;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                } else {
+                    report.display();
+                }
+                if report.has_failures() {
+                    std::process::exit(1);
+                }
+                return Ok(());
+            }
             Commands::Test {
                 categories,
                 save,
@@ -1201,90 +1618,513 @@ async fn main() -> anyhow::Result<()> {
         return Ok(());
     }
 
+    // --no-cache only applies to this invocation; the saved config is left alone
+    let mut app_config = app_config;
+    if cli.no_cache {
+        app_config.cache_enabled = false;
+    }
+
     run_ai_prompt(prompt, app_config).await
 }
 
+/// The request a confirmation prompt belongs to. Edited commands are re-validated through
+/// the orchestrator, which also explains commands and suggests alternatives, and every
+/// choice made at the prompt is recorded in the history.
+struct PromptSession<'a> {
+    orchestrator: &'a mut Orchestrator,
+    history: &'a mut History,
+    request: &'a str,
+}
+
+/// Run a command according to its execution mode, asking first where the mode calls for
+/// it. At the prompt the command can also be edited, explained, copied or swapped for an
+/// alternative. Returns the command that ran and its outcome, or `None` when nothing ran.
 async fn execute_command_with_confirmation(
     cmd: &str,
     execution_mode: &ExecutionMode,
-) -> anyhow::Result<()> {
-    match execution_mode {
-        ExecutionMode::Safe => {
-            println!("\n{} {}", "🚀 Executing:".bold().green(), cmd.green());
-            execute_shell_command(cmd).await
-        }
-        ExecutionMode::RequiresConfirmation(reasons) => {
-            println!(
-                "\n{} {}",
-                "⚠️  Sensitive command:".bold().yellow(),
-                cmd.red()
-            );
+    app_config: &Config,
+    cancellation: &CancellationToken,
+    session: &mut PromptSession<'_>,
+) -> anyhow::Result<Option<(String, CommandOutcome)>> {
+    let shell = ExecutionShell::resolve(app_config.shell.as_deref());
+    let mut command = cmd.to_string();
+    let mut mode = execution_mode.clone();
+    // Only the command as suggested may skip the prompt; edits and alternatives are
+    // always confirmed
+    let mut revised = false;
+
+    'present: loop {
+        match &mode {
+            ExecutionMode::Safe if !revised => {
+                return run_confirmed(command, app_config, &shell, cancellation).await;
+            }
+            ExecutionMode::Safe => {
+                println!(
+                    "\n{} {}",
+                    "▶️  Ready to run:".bold().green(),
+                    command.green()
+                );
+            }
+            ExecutionMode::RequiresConfirmation(reasons) => {
+                println!(
+                    "\n{} {}",
+                    "⚠️  Sensitive command:".bold().yellow(),
+                    command.red()
+                );
+
+                // Show confirmation reasons
+                for reason in reasons {
+                    println!("   • {}", reason.yellow());
+                }
+            }
+            ExecutionMode::SandboxPreview(reasons) => {
+                println!(
+                    "\n{} {}",
+                    "🧪 Previewing in a sandbox:".bold().cyan(),
+                    command.cyan()
+                );
+                match sandbox::preview_command(&command, &shell, &env::current_dir()?, cancellation)
+                    .await
+                {
+                    Ok(report) => display_sandbox_report(&report),
+                    Err(e) if is_cancelled(&e) => return Err(e),
+                    Err(e) => display_warning(&format!(
+                        "Sandbox preview unavailable, the command was not previewed: {:#}",
+                        e
+                    )),
+                }
 
-            // Show confirmation reasons
-            for reason in reasons {
-                println!("   • {}", reason.yellow());
+                for reason in reasons {
+                    println!("   • {}", reason.yellow());
+                }
+            }
+            ExecutionMode::SuggestOnly => {
+                // Nobody to ask when input is piped in
+                if !io::stdin().is_terminal() {
+                    println!(
+                        "\n{} To execute this command, copy and paste it into your terminal:",
+                        "💡".cyan()
+                    );
+                    println!("{}", command.green());
+                    return Ok(None);
+                }
+                println!(
+                    "\n{} {}",
+                    "💡 Suggested command:".bold().cyan(),
+                    command.green()
+                );
+            }
+            ExecutionMode::DryRunOnly => {
+                println!("\n{} {}", "🔍 DRY RUN:".bold().blue(), command.blue());
+                println!(
+                    "{}",
+                    "Command shown for preview only (dry-run mode enabled)".dimmed()
+                );
+                return Ok(None);
+            }
+            ExecutionMode::Blocked(reason) => {
+                println!("\n{} {}", "🚫 Command blocked:".bold().red(), reason.red());
+                println!("{} {}", "Original command:".dimmed(), command.dimmed());
+                if !io::stdin().is_terminal() {
+                    return Ok(None);
+                }
             }
+            ExecutionMode::MultiStep(_) => {
+                println!(
+                    "\n{} Multi-step commands should be handled separately",
+                    "⚠️".yellow()
+                );
+                return Ok(None);
+            }
+        }
 
-            print!("{} ", "Run this command? (y/n):".bold());
-            io::stdout().flush()?;
+        loop {
+            let choice = read_confirm_choice(mode.can_execute(), cancellation).await?;
+            session
+                .history
+                .annotate_last_turn(&choice.history_note(&command));
+            let _ = session.history.save();
 
-            let mut input = String::new();
-            io::stdin().read_line(&mut input)?;
+            match choice {
+                ConfirmChoice::Run => {
+                    return run_confirmed(command, app_config, &shell, cancellation).await;
+                }
+                ConfirmChoice::No => {
+                    println!("{}", "Aborted.".dimmed());
+                    return Ok(None);
+                }
+                ConfirmChoice::Copy => match confirm::copy_to_clipboard(&command) {
+                    Ok(via) => {
+                        println!("{} Copied to the clipboard ({})", "📋".cyan(), via.dimmed());
+                        return Ok(None);
+                    }
+                    Err(e) => display_warning(&format!("Could not copy the command: {:#}", e)),
+                },
+                ConfirmChoice::Explain => {
+                    let pb = spinner("Explaining the command...")?;
+                    let explanation = session.orchestrator.explain_command(&command).await;
+                    pb.finish_and_clear();
+                    match explanation {
+                        Ok(explanation) => println!("\n{}\n", explanation.trim()),
+                        Err(e) if is_cancelled(&e) => return Err(e),
+                        Err(e) => enhance_error(&e).display(),
+                    }
+                }
+                ConfirmChoice::Edit => {
+                    let initial = command.clone();
+                    let edited =
+                        tokio::task::spawn_blocking(move || confirm::edit_command(&initial))
+                            .await??;
+                    match edited {
+                        Some(edited) if edited != command => {
+                            session
+                                .history
+                                .annotate_last_turn(&format!("(Edited to `{}`)", edited));
+                            let _ = session.history.save();
+                            (command, mode) = revalidate(&edited, app_config, session);
+                            revised = true;
+                            continue 'present;
+                        }
+                        _ => println!("{}", "Command unchanged.".dimmed()),
+                    }
+                }
+                ConfirmChoice::Alternative => {
+                    let pb = spinner("Asking for an alternative...")?;
+                    session.orchestrator.set_history(session.history.clone());
+                    let response = session
+                        .orchestrator
+                        .propose_alternative(session.request, &command)
+                        .await;
+                    pb.finish_and_clear();
+                    let response = match response {
+                        Ok(response) => response,
+                        Err(e) if is_cancelled(&e) => return Err(e),
+                        Err(e) => {
+                            enhance_error(&e).display();
+                            continue;
+                        }
+                    };
 
-            if input.trim().to_lowercase() != "y" {
-                println!("{}", "Aborted.".dimmed());
-                return Ok(());
+                    session
+                        .history
+                        .add_turn("user", &format!("Suggest an alternative to `{}`", command));
+                    session.history.add_turn("assistant", &response);
+                    let _ = session.history.save();
+
+                    println!("\n{} Alternative:", "🔀".cyan());
+                    let output = parse_response_to_command_output(&response);
+                    display_command_output(&output);
+                    match output.command {
+                        Some(alternative) if alternative.trim() != command.trim() => {
+                            (command, mode) = revalidate(&alternative, app_config, session);
+                            revised = true;
+                            continue 'present;
+                        }
+                        _ => println!(
+                            "{}",
+                            "No different command was suggested; keeping the current one.".dimmed()
+                        ),
+                    }
+                }
             }
+        }
+    }
+}
 
-            println!("\n{} {}", "🚀 Executing:".bold().green(), cmd.green());
-            execute_shell_command(cmd).await
+/// Ask which [`ConfirmChoice`] to make until the answer is understood. Closed input
+/// counts as declining.
+async fn read_confirm_choice(
+    can_run: bool,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<ConfirmChoice> {
+    loop {
+        print!("{} ", ConfirmChoice::prompt(can_run).bold());
+        io::stdout().flush()?;
+
+        let input = read_line_cancellable(cancellation).await?;
+        if input.is_empty() {
+            return Ok(ConfirmChoice::No);
         }
-        ExecutionMode::SuggestOnly => {
-            println!(
-                "\n{} To execute this command, copy and paste it into your terminal:",
-                "💡".cyan()
-            );
-            println!("{}", cmd.green());
-            Ok(())
+        match ConfirmChoice::parse(&input) {
+            Some(ConfirmChoice::Run) if !can_run => {
+                println!("{}", "This command cannot be run; edit it first.".dimmed());
+            }
+            Some(choice) => return Ok(choice),
+            None => {}
         }
-        ExecutionMode::DryRunOnly => {
-            println!("\n{} {}", "🔍 DRY RUN:".bold().blue(), cmd.blue());
-            println!(
-                "{}",
-                "Command shown for preview only (dry-run mode enabled)".dimmed()
-            );
-            Ok(())
+    }
+}
+
+/// Validate an edited or alternative command, show what the validator found and work out
+/// its new execution mode
+fn revalidate(
+    command: &str,
+    app_config: &Config,
+    session: &mut PromptSession<'_>,
+) -> (String, ExecutionMode) {
+    let validation_result = session.orchestrator.validate_command(command);
+    display_validation_result(&validation_result);
+    let mode = ExecutionMode::determine(app_config, &validation_result);
+    let command = match validation_result {
+        ValidationResult::Valid(cmd)
+        | ValidationResult::Rewritten(cmd, _)
+        | ValidationResult::Sensitive(cmd, _)
+        | ValidationResult::Invalid(cmd, _) => cmd,
+    };
+    (command, mode)
+}
+
+/// Run a command the user (or the execution mode) has approved
+async fn run_confirmed(
+    command: String,
+    app_config: &Config,
+    shell: &ExecutionShell,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<Option<(String, CommandOutcome)>> {
+    println!("\n{} {}", "🚀 Executing:".bold().green(), command.green());
+    snapshot_for_undo(&command, &app_config.undo);
+    let outcome = execute_shell_command(&command, shell, cancellation).await?;
+    Ok(Some((command, outcome)))
+}
+
+/// Spinner shown while waiting on the model
+fn spinner(message: &str) -> anyhow::Result<ProgressBar> {
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(
+        ProgressStyle::default_spinner()
+            .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈")
+            .template("{spinner:.cyan} {msg}")?,
+    );
+    pb.set_message(message.to_string());
+    pb.enable_steady_tick(std::time::Duration::from_millis(100));
+    Ok(pb)
+}
+
+/// Read a line from stdin, giving up when the cancellation token fires
+async fn read_line_cancellable(cancellation: &CancellationToken) -> anyhow::Result<String> {
+    // The blocking read cannot be interrupted; it is left behind on cancellation
+    let read = tokio::task::spawn_blocking(|| {
+        let mut input = String::new();
+        io::stdin().read_line(&mut input).map(|_| input)
+    });
+    cancellation.run(async { Ok(read.await??) }).await
+}
+
+/// Table of undo snapshots, newest first, numbered as `cliai undo <n>` expects
+fn print_undo_snapshots(snapshots: &[undo::Snapshot]) {
+    if snapshots.is_empty() {
+        println!("No undo snapshots.");
+        return;
+    }
+    println!("{}", "↩️  Undo snapshots:".bold());
+    for (index, snapshot) in snapshots.iter().enumerate() {
+        println!(
+            "{:>3}  {}  {:>9}  {}",
+            index + 1,
+            snapshot.created_at.format("%Y-%m-%d %H:%M"),
+            HumanBytes(snapshot.bytes).to_string(),
+            snapshot.command
+        );
+    }
+}
+
+/// Save the files a command is about to overwrite, move or delete so `cliai undo` can
+/// restore them. Never stops the command from running.
+fn snapshot_for_undo(cmd: &str, settings: &config::UndoConfig) {
+    if !settings.enabled {
+        return;
+    }
+    let Ok(cwd) = env::current_dir() else {
+        return;
+    };
+    match undo::affected_paths(cmd, &cwd) {
+        Ok(paths) if paths.is_empty() => {}
+        Ok(paths) => {
+            match UndoJournal::new(settings).and_then(|journal| journal.snapshot(cmd, &cwd, &paths))
+            {
+                Ok(snapshot) => println!(
+                    "{} {}",
+                    "↩️".dimmed(),
+                    format!(
+                        "Saved {} path(s) ({}) first; restore them with `cliai undo`",
+                        snapshot.entries.len(),
+                        HumanBytes(snapshot.bytes)
+                    )
+                    .dimmed()
+                ),
+                Err(e) => display_warning(&format!("No undo snapshot was taken: {:#}", e)),
+            }
         }
-        ExecutionMode::Blocked(reason) => {
-            println!("\n{} {}", "🚫 Command blocked:".bold().red(), reason.red());
-            println!("{} {}", "Original command:".dimmed(), cmd.dimmed());
-            Ok(())
+        Err(reason) => display_warning(&format!(
+            "Can't tell which files this command will change ({}); no undo snapshot was taken",
+            reason
+        )),
+    }
+}
+
+/// Most changed paths listed after a sandbox preview
+const SANDBOX_CHANGES_SHOWN: usize = 50;
+
+/// Most output lines per stream shown after a sandbox preview
+const SANDBOX_OUTPUT_LINES_SHOWN: usize = 20;
+
+/// Show the output and file changes of a sandboxed preview run
+fn display_sandbox_report(report: &sandbox::SandboxReport) {
+    let status = if report.outcome.success() {
+        "succeeded".green()
+    } else {
+        format!("failed with {}", report.outcome.status_text()).red()
+    };
+    println!(
+        "  {} {} {}",
+        "Preview".bold(),
+        status,
+        format!("({}, no network)", report.backend).dimmed()
+    );
+
+    for (label, text) in [
+        ("stdout", &report.outcome.stdout),
+        ("stderr", &report.outcome.stderr),
+    ] {
+        let lines: Vec<&str> = text.lines().collect();
+        if lines.is_empty() {
+            continue;
         }
-        ExecutionMode::MultiStep(_) => {
+        println!("  {}:", label.dimmed());
+        if lines.len() > SANDBOX_OUTPUT_LINES_SHOWN {
             println!(
-                "\n{} Multi-step commands should be handled separately",
-                "⚠️".yellow()
+                "    {}",
+                format!(
+                    "... {} earlier lines",
+                    lines.len() - SANDBOX_OUTPUT_LINES_SHOWN
+                )
+                .dimmed()
             );
-            Ok(())
         }
+        for line in lines
+            .iter()
+            .skip(lines.len().saturating_sub(SANDBOX_OUTPUT_LINES_SHOWN))
+        {
+            println!("    {}", line);
+        }
+    }
+
+    if report.changes.is_empty() {
+        println!(
+            "  {}",
+            "No files in the working directory would change".dimmed()
+        );
+        return;
+    }
+    println!("  {} ({}):", "Files changed".bold(), report.changes.len());
+    for change in report.changes.iter().take(SANDBOX_CHANGES_SHOWN) {
+        let line = change.to_string();
+        let line = match change {
+            sandbox::FileChange::Created(_) => line.green(),
+            sandbox::FileChange::Modified(_) => line.yellow(),
+            sandbox::FileChange::Deleted(_) => line.red(),
+        };
+        println!("    {}", line);
+    }
+    if report.changes.len() > SANDBOX_CHANGES_SHOWN {
+        println!(
+            "    {}",
+            format!(
+                "... and {} more",
+                report.changes.len() - SANDBOX_CHANGES_SHOWN
+            )
+            .dimmed()
+        );
     }
 }
 
-async fn execute_shell_command(cmd: &str) -> anyhow::Result<()> {
-    let status = if cfg!(target_os = "windows") {
-        Command::new("cmd").args(["/C", cmd]).status()?
-    } else {
-        Command::new("sh").args(["-c", cmd]).status()?
+/// Run a command, echoing its output live while capturing it for the fix loop
+async fn execute_shell_command(
+    cmd: &str,
+    shell: &ExecutionShell,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<CommandOutcome> {
+    let mut child = shell
+        .command(cmd)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let stdout = child
+        .stdout
+        .take()
+        .map(|out| tokio::spawn(tee_output(out, false)));
+    let stderr = child
+        .stderr
+        .take()
+        .map(|err| tokio::spawn(tee_output(err, true)));
+
+    let status = match cancellation.run(async { Ok(child.wait().await?) }).await {
+        Ok(status) => status,
+        Err(e) => {
+            // Ctrl-C reaches the command too; give it a moment to exit on its own, then
+            // stop it and reap it before handing the terminal back
+            if tokio::time::timeout(std::time::Duration::from_secs(2), child.wait())
+                .await
+                .is_err()
+            {
+                let _ = child.start_kill();
+                let _ = child.wait().await;
+            }
+            return Err(e);
+        }
     };
 
-    if !status.success() {
-        eprintln!("\n{}", "Command failed.".red());
+    let outcome = CommandOutcome {
+        exit_code: status.code(),
+        stdout: match stdout {
+            Some(task) => task.await.unwrap_or_default(),
+            None => String::new(),
+        },
+        stderr: match stderr {
+            Some(task) => task.await.unwrap_or_default(),
+            None => String::new(),
+        },
+    };
+    if !outcome.success() {
+        eprintln!(
+            "\n{} {}",
+            "Command failed:".red(),
+            outcome.status_text().red()
+        );
     }
 
-    Ok(())
+    Ok(outcome)
+}
+
+/// Copy a child's output stream to our stdout or stderr as it arrives and return the
+/// captured text, keeping the last `OUTPUT_CAPTURE_LIMIT` bytes
+async fn tee_output(mut stream: impl tokio::io::AsyncRead + Unpin, to_stderr: bool) -> String {
+    use tokio::io::AsyncReadExt;
+
+    let mut captured = Vec::new();
+    let mut buf = [0u8; 4096];
+    while let Ok(n) = stream.read(&mut buf).await {
+        if n == 0 {
+            break;
+        }
+        let _ = if to_stderr {
+            let mut err = io::stderr();
+            err.write_all(&buf[..n]).and_then(|_| err.flush())
+        } else {
+            let mut out = io::stdout();
+            out.write_all(&buf[..n]).and_then(|_| out.flush())
+        };
+        captured.extend_from_slice(&buf[..n]);
+        if captured.len() > OUTPUT_CAPTURE_LIMIT {
+            captured.drain(..captured.len() - OUTPUT_CAPTURE_LIMIT);
+        }
+    }
+    String::from_utf8_lossy(&captured).into_owned()
 }
 
 async fn run_ai_prompt(prompt: String, app_config: config::Config) -> anyhow::Result<()> {
+    let cancellation = CancellationToken::on_ctrl_c();
     let mut history = History::load();
 
     let pb = ProgressBar::new_spinner();
@@ -1297,6 +2137,20 @@ async fn run_ai_prompt(prompt: String, app_config: config::Config) -> anyhow::Re
     pb.enable_steady_tick(std::time::Duration::from_millis(100));
 
     let mut orchestrator = Orchestrator::new(app_config.clone(), history.clone());
+    orchestrator.set_cancellation(cancellation.clone());
+
+    // Render the answer as it streams in; the spinner stops at the first visible text
+    let renderer = Arc::new(Mutex::new(StreamRenderer::default()));
+    if app_config.stream_output {
+        let renderer = Arc::clone(&renderer);
+        let pb = pb.clone();
+        orchestrator.set_stream_handler(Box::new(move |chunk| {
+            if let Ok(mut renderer) = renderer.lock() {
+                let text = renderer.push(chunk);
+                print_streamed_text(&mut renderer, &text, &pb);
+            }
+        }));
+    }
 
     match orchestrator.process(&prompt).await {
         Ok(response) => {
@@ -1306,7 +2160,7 @@ async fn run_ai_prompt(prompt: String, app_config: config::Config) -> anyhow::Re
             let command_output = parse_response_to_command_output(&response);
 
             // Display the formatted output
-            display_command_output(&command_output);
+            display_streamed_command_output(&renderer, &pb, &response, &command_output);
 
             history.add_turn("user", &prompt);
             history.add_turn("assistant", &response);
@@ -1315,12 +2169,40 @@ async fn run_ai_prompt(prompt: String, app_config: config::Config) -> anyhow::Re
             // Handle command execution if a command is present
             if let Some(cmd) = &command_output.command {
                 // Check if this is a multi-step command first
-                if let Some(multi_step_handler) = MultiStepHandler::parse_multi_step_command(cmd) {
+                if let Some(mut multi_step_handler) =
+                    MultiStepHandler::parse_multi_step_command(cmd)
+                {
+                    multi_step_handler
+                        .validate_steps(&app_config, |step| orchestrator.validate_command(step));
                     println!("\n{} Multi-step command detected:", "🔄".cyan());
                     println!("{}", multi_step_handler.format_steps_for_display());
 
-                    if app_config.auto_execute {
-                        execute_multi_step_command(multi_step_handler, &app_config).await?;
+                    if let Some((step_number, reason)) = multi_step_handler.blocked_step() {
+                        println!(
+                            "\n{} Step {} is blocked, so no steps were run: {}",
+                            "🚫".red(),
+                            step_number,
+                            reason.red()
+                        );
+                    } else if app_config.dry_run {
+                        println!(
+                            "\n{} {}",
+                            "🔍 DRY RUN:".bold().blue(),
+                            "Steps shown for preview only (dry-run mode enabled)".dimmed()
+                        );
+                    } else if multi_step_handler.can_execute() {
+                        let result = execute_multi_step_command(
+                            multi_step_handler,
+                            &app_config,
+                            &cancellation,
+                            &mut PromptSession {
+                                orchestrator: &mut orchestrator,
+                                history: &mut history,
+                                request: &prompt,
+                            },
+                        )
+                        .await;
+                        finish_execution(result, &mut history)?;
                     } else {
                         println!(
                             "\n{} Use --auto-execute to run multi-step commands automatically",
@@ -1332,103 +2214,86 @@ async fn run_ai_prompt(prompt: String, app_config: config::Config) -> anyhow::Re
                         }
                     }
                 } else {
-                    // Single command execution (existing logic)
-                    let validation_result = orchestrator.validate_command(cmd);
-                    let execution_mode = ExecutionMode::determine(&app_config, &validation_result);
-
-                    let mut executable_cmd = ExecutableCommand::new(
-                        cmd.clone(),
-                        command_output.explanation.clone(),
-                        execution_mode.clone(),
-                    );
-
-                    for warning in &command_output.warnings {
-                        executable_cmd.add_warning(warning.clone());
-                    }
-
-                    // Handle different validation results with integrated safety checking
-                    match validation_result {
-                        ValidationResult::Valid(validated_cmd) => {
-                            executable_cmd.command = validated_cmd.clone();
-                            if execution_mode.can_execute() {
-                                execute_command_with_confirmation(&validated_cmd, &execution_mode)
-                                    .await?;
-                            } else if let Some(instructions) =
-                                executable_cmd.get_execution_instructions()
-                            {
-                                println!("\n{} {}", "💡".cyan(), instructions.dimmed());
-                            }
+                    // Single command execution; a failed run is fed back to the
+                    // ShellExpert for a corrected command, up to `fix_attempts` times
+                    let mut command = cmd.clone();
+                    let mut command_output = command_output;
+                    let mut attempts = 0;
+                    while let Some((ran, outcome)) = validate_and_execute(
+                        &command,
+                        &command_output,
+                        &mut PromptSession {
+                            orchestrator: &mut orchestrator,
+                            history: &mut history,
+                            request: &prompt,
+                        },
+                        &app_config,
+                        &cancellation,
+                    )
+                    .await?
+                    {
+                        history.annotate_last_turn(&outcome.history_note(&ran));
+                        let _ = history.save();
+                        if outcome.success() || attempts >= app_config.fix_attempts {
+                            break;
                         }
-                        ValidationResult::Rewritten(rewritten_cmd, fixes) => {
-                            println!("\n{} Command was automatically fixed:", "🔧".yellow());
-                            for fix in &fixes {
-                                println!("  • {}", fix.dimmed());
-                            }
-                            executable_cmd.command = rewritten_cmd.clone();
-                            if execution_mode.can_execute() {
-                                execute_command_with_confirmation(&rewritten_cmd, &execution_mode)
-                                    .await?;
-                            } else if let Some(instructions) =
-                                executable_cmd.get_execution_instructions()
-                            {
-                                println!("\n{} {}", "💡".cyan(), instructions.dimmed());
-                            }
-                        }
-                        ValidationResult::Invalid(invalid_cmd, errors) => {
-                            println!("\n{} Command validation failed:", "❌".red());
-                            for error in &errors {
-                                match error {
-                                    ValidationError::HallucinatedFlag(flag) => {
-                                        println!("  • Unknown flag: {}", flag.red());
-                                    }
-                                    ValidationError::PlaceholderDetected(placeholder) => {
-                                        println!("  • Placeholder detected: {}", placeholder.red());
-                                        println!("    Please provide specific values instead of placeholders.");
-                                    }
-                                    ValidationError::SyntaxError(msg) => {
-                                        println!("  • Syntax error: {}", msg.red());
-                                    }
-                                    ValidationError::QuotingIssue(msg) => {
-                                        println!("  • Quoting issue: {}", msg.red());
-                                    }
-                                }
+                        attempts += 1;
+
+                        let pb = ProgressBar::new_spinner();
+                        pb.set_style(
+                            ProgressStyle::default_spinner()
+                                .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈")
+                                .template("{spinner:.cyan} {msg}")?,
+                        );
+                        pb.set_message(format!(
+                            "Asking for a fix (attempt {}/{})...",
+                            attempts, app_config.fix_attempts
+                        ));
+                        pb.enable_steady_tick(std::time::Duration::from_millis(100));
+
+                        orchestrator.set_history(history.clone());
+                        let fix = match orchestrator.propose_fix(&prompt, &ran, &outcome).await {
+                            Ok(fix) => fix,
+                            Err(e) if is_cancelled(&e) => {
+                                pb.finish_and_clear();
+                                exit_cancelled("Generation");
                             }
-                            println!(
-                                "\n{} {}",
-                                "Original command:".dimmed(),
-                                invalid_cmd.dimmed()
-                            );
-                            if let Some(reason) = execution_mode.get_block_reason() {
-                                println!("{} {}", "🚫".red(), reason.red());
+                            Err(e) => {
+                                pb.finish_and_clear();
+                                enhance_error(&e).display();
+                                break;
                             }
-                        }
-                        ValidationResult::Sensitive(sensitive_cmd, warnings) => {
-                            println!("\n{} Sensitive command detected:", "⚠️".yellow());
-                            for warning in &warnings {
-                                match warning {
-                                    SecurityWarning::DataLoss(msg) => {
-                                        println!("  • {}: {}", "Data Loss Risk".red(), msg);
-                                    }
-                                    SecurityWarning::SystemModification(msg) => {
-                                        println!("  • {}: {}", "System Modification".yellow(), msg);
-                                    }
-                                    SecurityWarning::DangerousPattern(msg) => {
-                                        println!("  • {}: {}", "Dangerous Pattern".red(), msg);
-                                    }
-                                }
-                            }
-                            executable_cmd.command = sensitive_cmd.clone();
-                            if execution_mode.can_execute() {
-                                execute_command_with_confirmation(&sensitive_cmd, &execution_mode)
-                                    .await?;
-                            } else if let Some(reason) = execution_mode.get_block_reason() {
-                                println!("\n{} {}", "🚫".red(), reason.red());
+                        };
+                        pb.finish_and_clear();
+
+                        history.add_turn("user", &outcome.fix_request(&prompt, &ran));
+                        history.add_turn("assistant", &fix);
+                        let _ = history.save();
+
+                        println!("\n{} Suggested fix:", "🩹".yellow());
+                        command_output = parse_response_to_command_output(&fix);
+                        display_command_output(&command_output);
+                        match &command_output.command {
+                            Some(next) if next.trim() != ran.trim() => command = next.clone(),
+                            _ => {
+                                println!(
+                                    "{}",
+                                    "No different command was suggested; stopping.".dimmed()
+                                );
+                                break;
                             }
                         }
                     }
                 }
             }
         }
+        Err(e) if is_cancelled(&e) => {
+            pb.finish_and_clear();
+            history.add_turn("user", &prompt);
+            history.add_turn("assistant", "(Cancelled before an answer was generated)");
+            let _ = history.save();
+            exit_cancelled("Generation");
+        }
         Err(e) => {
             pb.finish_and_clear();
             let enhanced_error = enhance_error(&e);
@@ -1438,10 +2303,158 @@ async fn run_ai_prompt(prompt: String, app_config: config::Config) -> anyhow::Re
     Ok(())
 }
 
-/// Execute a multi-step command with progress tracking
+/// Validate a single suggested command and run it if its execution mode allows. Returns
+/// the command that actually ran (after any rewrite or edit) and its outcome, or `None`
+/// when nothing was executed.
+async fn validate_and_execute(
+    cmd: &str,
+    command_output: &CommandOutput,
+    session: &mut PromptSession<'_>,
+    app_config: &config::Config,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<Option<(String, CommandOutcome)>> {
+    let validation_result = session.orchestrator.validate_command(cmd);
+    let execution_mode = ExecutionMode::determine(app_config, &validation_result);
+
+    let mut executable_cmd = ExecutableCommand::new(
+        cmd.to_string(),
+        command_output.explanation.clone(),
+        execution_mode.clone(),
+    );
+
+    for warning in &command_output.warnings {
+        executable_cmd.add_warning(warning.clone());
+    }
+
+    display_validation_result(&validation_result);
+
+    // Handle different validation results with integrated safety checking
+    let validated_cmd = match validation_result {
+        ValidationResult::Valid(cmd)
+        | ValidationResult::Rewritten(cmd, _)
+        | ValidationResult::Sensitive(cmd, _) => cmd,
+        ValidationResult::Invalid(invalid_cmd, _) => {
+            println!(
+                "\n{} {}",
+                "Original command:".dimmed(),
+                invalid_cmd.dimmed()
+            );
+            if let Some(reason) = execution_mode.get_block_reason() {
+                println!("{} {}", "🚫".red(), reason.red());
+            }
+            return Ok(None);
+        }
+    };
+    executable_cmd.command = validated_cmd.clone();
+
+    // Suggestions get the interactive prompt too when there is someone to answer it
+    let interactive =
+        matches!(execution_mode, ExecutionMode::SuggestOnly) && io::stdin().is_terminal();
+    if execution_mode.can_execute() || interactive {
+        let result = execute_command_with_confirmation(
+            &validated_cmd,
+            &execution_mode,
+            app_config,
+            cancellation,
+            session,
+        )
+        .await;
+        return finish_execution(result, session.history);
+    } else if let Some(reason) = execution_mode.get_block_reason() {
+        println!("\n{} {}", "🚫".red(), reason.red());
+    } else if let Some(instructions) = executable_cmd.get_execution_instructions() {
+        println!("\n{} {}", "💡".cyan(), instructions.dimmed());
+    }
+    Ok(None)
+}
+
+/// Show what the validator found: automatic fixes, errors or security warnings
+fn display_validation_result(validation_result: &ValidationResult) {
+    match validation_result {
+        ValidationResult::Valid(_) => {}
+        ValidationResult::Rewritten(_, fixes) => {
+            println!("\n{} Command was automatically fixed:", "🔧".yellow());
+            for fix in fixes {
+                println!("  • {}", fix.dimmed());
+            }
+        }
+        ValidationResult::Invalid(_, errors) => {
+            println!("\n{} Command validation failed:", "❌".red());
+            for error in errors {
+                match error {
+                    ValidationError::HallucinatedFlag(flag) => {
+                        println!("  • Unknown flag: {}", flag.red());
+                    }
+                    ValidationError::PlaceholderDetected(placeholder) => {
+                        println!("  • Placeholder detected: {}", placeholder.red());
+                        println!("    Please provide specific values instead of placeholders.");
+                    }
+                    ValidationError::SyntaxError(msg) => {
+                        println!("  • Syntax error: {}", msg.red());
+                    }
+                    ValidationError::QuotingIssue(msg) => {
+                        println!("  • Quoting issue: {}", msg.red());
+                    }
+                }
+            }
+        }
+        ValidationResult::Sensitive(_, warnings) => {
+            println!("\n{} Sensitive command detected:", "⚠️".yellow());
+            for warning in warnings {
+                match warning {
+                    SecurityWarning::DataLoss(msg) => {
+                        println!("  • {}: {}", "Data Loss Risk".red(), msg);
+                    }
+                    SecurityWarning::SystemModification(msg) => {
+                        println!("  • {}: {}", "System Modification".yellow(), msg);
+                    }
+                    SecurityWarning::DangerousPattern(msg) => {
+                        println!("  • {}: {}", "Dangerous Pattern".red(), msg);
+                    }
+                    SecurityWarning::ShellMismatch(msg) => {
+                        println!("  • {}: {}", "Shell Mismatch".yellow(), msg);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Record a command run stopped with Ctrl-C against the answer that suggested it, then
+/// exit; any other outcome is passed through
+fn finish_execution<T>(result: anyhow::Result<T>, history: &mut History) -> anyhow::Result<T> {
+    match result {
+        Err(e) if is_cancelled(&e) => {
+            history.annotate_last_turn("(Command execution was cancelled)");
+            let _ = history.save();
+            exit_cancelled("Command execution");
+        }
+        other => other,
+    }
+}
+
+/// Log a Ctrl-C cancellation and exit with the cancellation exit code
+fn exit_cancelled(what: &str) -> ! {
+    if let Ok(logger) = get_logger() {
+        if let Ok(logger_guard) = logger.lock() {
+            let _ = logger_guard.log_info(
+                LogCategory::System,
+                format!("{} cancelled by user", what),
+                None,
+            );
+        }
+    }
+    println!("\n{} {} cancelled.", "⏹️".yellow(), what);
+    std::process::exit(CANCELLED_EXIT_CODE);
+}
+
+/// Execute a validated multi-step command with progress tracking. Each step runs in its
+/// own execution mode, so sensitive steps are confirmed one at a time.
 async fn execute_multi_step_command(
     mut handler: MultiStepHandler,
     config: &Config,
+    cancellation: &CancellationToken,
+    session: &mut PromptSession<'_>,
 ) -> anyhow::Result<()> {
     println!("\n{} Starting multi-step execution...", "🚀".green());
 
@@ -1457,35 +2470,47 @@ async fn execute_multi_step_command(
                 step.description
             );
 
-            // Validate each step
-            let validation_result = validate_single_command(&step.command);
-            let execution_mode = ExecutionMode::determine(config, &validation_result);
+            let command = step.command.clone();
+            let execution_mode = step
+                .execution_mode
+                .as_deref()
+                .cloned()
+                .unwrap_or(ExecutionMode::SuggestOnly);
 
-            if execution_mode.is_blocked() {
-                println!(
-                    "  {} Step blocked: {}",
-                    "🚫".red(),
-                    execution_mode.get_display_prefix().unwrap_or_default()
-                );
+            if let Some(reason) = execution_mode.get_block_reason() {
+                println!("  {} Step blocked: {}", "🚫".red(), reason.red());
                 break;
             }
 
             // Execute the step
-            let command = step.command.clone(); // Clone to avoid borrowing issues
-            let success = match execute_single_step(&command).await {
-                Ok(output) => {
-                    if output.status.success() {
-                        let stdout = String::from_utf8_lossy(&output.stdout);
-                        if !stdout.trim().is_empty() {
-                            println!("  {}", stdout.trim().dimmed());
-                        }
-                        println!("  {} Step completed successfully", "✅".green());
-                        true
-                    } else {
-                        let stderr = String::from_utf8_lossy(&output.stderr);
-                        println!("  {} Step failed: {}", "❌".red(), stderr.trim().red());
-                        false
-                    }
+            let success = match execute_command_with_confirmation(
+                &command,
+                &execution_mode,
+                config,
+                cancellation,
+                session,
+            )
+            .await
+            {
+                Ok(Some((_, outcome))) if outcome.success() => {
+                    println!("  {} Step completed successfully", "✅".green());
+                    true
+                }
+                Ok(Some((_, outcome))) => {
+                    println!(
+                        "  {} Step failed ({})",
+                        "❌".red(),
+                        outcome.status_text().red()
+                    );
+                    false
+                }
+                Ok(None) => {
+                    println!("  {} Step skipped", "⏭️".yellow());
+                    false
+                }
+                Err(e) if is_cancelled(&e) => {
+                    println!("  {} Step cancelled", "⏹️".yellow());
+                    return Err(e);
                 }
                 Err(e) => {
                     println!("  {} Execution error: {}", "❌".red(), e.to_string().red());
@@ -1524,27 +2549,278 @@ async fn execute_multi_step_command(
     Ok(())
 }
 
-/// Execute a single step of a multi-step command
-async fn execute_single_step(command: &str) -> anyhow::Result<std::process::Output> {
-    use std::process::Command;
+/// Incrementally renders a streamed response. The `Command:` line is held back so the
+/// command is only shown after the complete response has been extracted and validated.
+#[derive(Default)]
+struct StreamRenderer {
+    /// Everything received so far, used to check the final response matches the stream
+    received: String,
+    /// Start of the current line while we cannot yet tell whether it is the command line
+    line: String,
+    line_decided: bool,
+    hiding_line: bool,
+    /// A real command (not `(none)`) was held back, so the text is its explanation
+    saw_command: bool,
+    started: bool,
+    header_shown: bool,
+}
+
+impl StreamRenderer {
+    const COMMAND_PREFIX: &'static str = "Command:";
+
+    /// Feed a chunk and return the text that can be displayed now
+    fn push(&mut self, chunk: &str) -> String {
+        self.received.push_str(chunk);
+        let mut out = String::new();
+
+        for ch in chunk.chars() {
+            if self.line_decided {
+                if self.hiding_line {
+                    self.line.push(ch);
+                } else {
+                    self.emit(ch, &mut out);
+                }
+            } else {
+                self.line.push(ch);
+                let trimmed = self.line.trim_start();
+                if trimmed.starts_with(Self::COMMAND_PREFIX) {
+                    self.line_decided = true;
+                    self.hiding_line = true;
+                } else if ch == '\n' || !Self::COMMAND_PREFIX.starts_with(trimmed) {
+                    self.line_decided = true;
+                    for pending in std::mem::take(&mut self.line).chars() {
+                        self.emit(pending, &mut out);
+                    }
+                }
+            }
+
+            if ch == '\n' {
+                if self.hiding_line {
+                    let command = self.line.trim_start()[Self::COMMAND_PREFIX.len()..].trim();
+                    self.saw_command = !command.is_empty() && command != "(none)";
+                }
+                self.line_decided = false;
+                self.hiding_line = false;
+                self.line.clear();
+            }
+        }
 
-    let output = Command::new("sh").arg("-c").arg(command).output()?;
+        out
+    }
 
-    Ok(output)
+    /// Flush whatever is still buffered once the stream has ended
+    fn finish(&mut self) -> String {
+        let mut out = String::new();
+        if !self.line_decided && !self.line.trim_start().starts_with(Self::COMMAND_PREFIX) {
+            for pending in std::mem::take(&mut self.line).chars() {
+                self.emit(pending, &mut out);
+            }
+        }
+        self.line.clear();
+        out.trim_end().to_string()
+    }
+
+    fn emit(&mut self, ch: char, out: &mut String) {
+        // Skip whitespace between the command line and the explanation
+        if !self.started && ch.is_whitespace() {
+            return;
+        }
+        self.started = true;
+        out.push(ch);
+    }
+
+    /// Whether any text has been displayed
+    fn has_output(&self) -> bool {
+        self.started
+    }
+
+    /// Whether the streamed text is the response that was finally returned
+    fn matches(&self, response: &str) -> bool {
+        self.received.trim() == response.trim()
+    }
 }
 
-/// Validate a single command (simplified version for multi-step)
-fn validate_single_command(command: &str) -> ValidationResult {
-    // Basic validation - in production, use the full validator
-    if command.contains("rm -rf /") || command.contains("sudo rm") {
-        ValidationResult::Sensitive(
-            command.to_string(),
-            vec![SecurityWarning::DangerousPattern(
-                "Potentially destructive command".to_string(),
-            )],
-        )
+/// Display name of a provider type
+fn provider_type_label(provider_type: &ProviderType) -> &'static str {
+    match provider_type {
+        ProviderType::Local => "Local",
+        ProviderType::Cloud => "Cloud",
+        ProviderType::OpenAI => "OpenAI-compatible",
+        ProviderType::Anthropic => "Anthropic",
+        ProviderType::Gemini => "Gemini",
+        ProviderType::Cohere => "Cohere",
+    }
+}
+
+/// Ollama provider for model management, using the configured URL and timeout
+fn ollama_provider(config: &config::Config) -> OllamaProvider {
+    OllamaProvider::with_timeout(
+        config.ollama_url.clone(),
+        config.model.clone(),
+        std::time::Duration::from_millis(std::cmp::max(config.ai_timeout, 10000)),
+    )
+}
+
+/// Pull a model, showing a byte progress bar per layer and a spinner for other steps
+async fn pull_model_with_progress(provider: &OllamaProvider, name: &str) -> anyhow::Result<()> {
+    let pb = ProgressBar::new_spinner();
+    pb.set_style(
+        ProgressStyle::default_spinner()
+            .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈")
+            .template("{spinner:.cyan} {msg}")?,
+    );
+    pb.set_message(format!("Pulling {}...", name));
+    pb.enable_steady_tick(std::time::Duration::from_millis(100));
+
+    let bar_style = ProgressStyle::default_bar()
+        .template("{msg} [{bar:30.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})")?
+        .progress_chars("=> ");
+    let spinner_style = pb.style();
+
+    let result = provider
+        .pull_model(name, |progress| {
+            match (progress.total, progress.completed) {
+                (Some(total), completed) => {
+                    if pb.length() != Some(total) {
+                        pb.set_style(bar_style.clone());
+                        pb.set_length(total);
+                    }
+                    pb.set_position(completed.unwrap_or(0));
+                    let digest = progress.digest.as_deref().unwrap_or_default();
+                    let digest = digest.strip_prefix("sha256:").unwrap_or(digest);
+                    pb.set_message(format!("pulling {}", &digest[..digest.len().min(12)]));
+                }
+                (None, _) => {
+                    if pb.length().is_some() {
+                        pb.set_style(spinner_style.clone());
+                        pb.unset_length();
+                    }
+                    pb.set_message(progress.status.clone());
+                }
+            }
+        })
+        .await;
+
+    pb.finish_and_clear();
+    result
+}
+
+/// Print the details of an installed model
+fn print_model_details(details: &ModelDetails) {
+    println!("{} {}", "Model:".bold().cyan(), details.name.bold());
+
+    let rows = [
+        ("Family", details.family.clone()),
+        ("Parameters", details.parameter_size.clone()),
+        ("Quantization", details.quantization.clone()),
+        ("Format", details.format.clone()),
+        (
+            "Context length",
+            details.context_length.map(|n| n.to_string()),
+        ),
+        ("Size", details.size.map(|n| HumanBytes(n).to_string())),
+    ];
+    for (label, value) in rows {
+        println!(
+            "  {:<15} {}",
+            format!("{}:", label),
+            value.unwrap_or_else(|| "unknown".dimmed().to_string())
+        );
+    }
+
+    if !details.parameters.is_empty() {
+        println!("  {}", "Defaults:".bold());
+        for (key, value) in &details.parameters {
+            println!("    {:<13} {}", key, value);
+        }
+    }
+}
+
+/// Print grouped usage totals as aligned rows
+fn print_usage_rows(rows: &[(String, UsageTotals)]) {
+    if rows.is_empty() {
+        println!("  (none)");
+        return;
+    }
+    let width = rows.iter().map(|(key, _)| key.len()).max().unwrap_or(0);
+    for (key, totals) in rows {
+        println!(
+            "  {:<width$}  {:>5} req  {:>9} in  {:>9} out  ${:.4}",
+            key,
+            totals.requests,
+            totals.prompt_tokens,
+            totals.completion_tokens,
+            totals.cost_usd,
+            width = width
+        );
+    }
+}
+
+/// Format a persisted unix timestamp in local time
+fn format_unix_time(secs: Option<u64>) -> String {
+    secs.and_then(|secs| chrono::DateTime::from_timestamp(secs as i64, 0))
+        .map(|time| {
+            time.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_else(|| "never".to_string())
+}
+
+/// Print streamed text, replacing the spinner with the answer header on first output
+fn print_streamed_text(renderer: &mut StreamRenderer, text: &str, pb: &ProgressBar) {
+    if text.is_empty() {
+        return;
+    }
+    if !renderer.header_shown {
+        renderer.header_shown = true;
+        pb.finish_and_clear();
+        if renderer.saw_command {
+            print!("{} ", "💡".cyan());
+        } else {
+            print!("{} ", "🤖 AI:".bold().cyan());
+        }
+    }
+    if renderer.saw_command {
+        print!("{}", text.dimmed());
     } else {
-        ValidationResult::Valid(command.to_string())
+        print!("{}", text);
+    }
+    let _ = io::stdout().flush();
+}
+
+/// Complete the display of a response that may have been streamed. If the explanation
+/// was already streamed, only the withheld command line remains; otherwise (no stream,
+/// or the answer was replaced by a format retry) show the full formatted output.
+fn display_streamed_command_output(
+    renderer: &Mutex<StreamRenderer>,
+    pb: &ProgressBar,
+    response: &str,
+    output: &CommandOutput,
+) {
+    let mut renderer = renderer
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let rest = renderer.finish();
+    print_streamed_text(&mut renderer, &rest, pb);
+
+    if !renderer.has_output() {
+        display_command_output(output);
+        return;
+    }
+
+    println!();
+    if renderer.matches(response) {
+        if let Some(cmd) = &output.command {
+            println!();
+            println!("{}", cmd);
+        }
+        for warning in &output.warnings {
+            println!("{} {}", "⚠️".yellow(), warning.yellow());
+        }
+    } else {
+        println!();
+        display_command_output(output);
     }
 }
 
@@ -1766,4 +3042,51 @@ mod tests {
         // Third line should be the explanation
         assert_eq!(lines[2], "This command lists files");
     }
+
+    #[test]
+    fn test_stream_renderer_holds_back_command_line() {
+        let mut renderer = StreamRenderer::default();
+        let mut shown = String::new();
+
+        for chunk in ["Comm", "and: ls", " -la\n", "\nLists all", " files"] {
+            shown.push_str(&renderer.push(chunk));
+        }
+        shown.push_str(&renderer.finish());
+
+        assert_eq!(shown, "Lists all files");
+        assert!(renderer.saw_command);
+        assert!(renderer.matches("Command: ls -la\n\nLists all files"));
+    }
+
+    #[test]
+    fn test_stream_renderer_plain_answer() {
+        let mut renderer = StreamRenderer::default();
+        let mut shown = renderer.push("Co");
+        shown.push_str(&renderer.push("mpilers translate"));
+        shown.push_str(&renderer.push(" source code.\nDone"));
+        shown.push_str(&renderer.finish());
+
+        assert_eq!(shown, "Compilers translate source code.\nDone");
+        assert!(!renderer.saw_command);
+        assert!(renderer.has_output());
+    }
+
+    #[test]
+    fn test_stream_renderer_none_command() {
+        let mut renderer = StreamRenderer::default();
+        let mut shown = renderer.push("Command: (none)\n\nI can explain that.");
+        shown.push_str(&renderer.finish());
+
+        assert_eq!(shown, "I can explain that.");
+        assert!(!renderer.saw_command);
+    }
+
+    #[test]
+    fn test_stream_renderer_command_only() {
+        let mut renderer = StreamRenderer::default();
+        let shown = renderer.push("Command: df -h");
+        assert!(shown.is_empty());
+        assert!(renderer.finish().is_empty());
+        assert!(!renderer.has_output());
+    }
 }
@@ -1,7 +1,7 @@
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 /// Operating system types with primary focus on Linux distributions
@@ -37,6 +37,33 @@ pub enum Shell {
     Unknown,
 }
 
+impl Shell {
+    /// Shell kind of a name such as `fish` or a path such as `/usr/bin/zsh`
+    pub fn from_name(name: &str) -> Shell {
+        let file_name = Path::new(name)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(name);
+        match file_name {
+            "bash" => Shell::Bash,
+            "zsh" => Shell::Zsh,
+            "fish" => Shell::Fish,
+            "sh" | "dash" | "ash" => Shell::Sh,
+            _ => Shell::Unknown,
+        }
+    }
+
+    /// Program started for this shell when no path is known
+    pub fn program(&self) -> &'static str {
+        match self {
+            Shell::Bash => "bash",
+            Shell::Zsh => "zsh",
+            Shell::Fish => "fish",
+            Shell::Sh | Shell::Unknown => "sh",
+        }
+    }
+}
+
 /// System paths for different operating systems
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SystemPaths {
@@ -160,7 +187,7 @@ impl OSContext {
     }
 
     /// Detect current shell
-    fn detect_shell() -> Shell {
+    pub fn detect_shell() -> Shell {
         if let Ok(shell_path) = std::env::var("SHELL") {
             if shell_path.contains("bash") {
                 return Shell::Bash;
@@ -379,7 +406,7 @@ impl OSContext {
     }
 
     /// Get cache file path
-    fn get_cache_path() -> Result<PathBuf> {
+    pub fn get_cache_path() -> Result<PathBuf> {
         let cache_dir = dirs::cache_dir()
             .or_else(dirs::config_dir)
             .ok_or_else(|| anyhow!("Could not find cache directory"))?;
@@ -416,6 +443,7 @@ impl Default for ArchCommands {
     }
 }
 
+#[allow(dead_code)]
 impl ArchCommands {
     pub fn new() -> Self {
         let aur_helper = Self::detect_aur_helper();
@@ -499,6 +527,63 @@ impl ArchCommands {
     }
 }
 
+/// Shell that executed commands run in, and whose syntax they are validated against
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionShell {
+    pub kind: Shell,
+    pub program: String,
+}
+
+#[allow(dead_code)]
+impl ExecutionShell {
+    /// Use the configured shell (a name or a path) when set, otherwise the detected one.
+    /// Falls back to `sh` when the shell's program can't be found.
+    pub fn resolve(configured: Option<&str>) -> Self {
+        let (kind, program) = match configured {
+            Some(configured) => (Shell::from_name(configured), configured.to_string()),
+            None => {
+                let kind = OSContext::detect_shell();
+                // Prefer the user's own binary, e.g. a Homebrew zsh, over the one on PATH
+                let program = std::env::var("SHELL")
+                    .ok()
+                    .filter(|path| Shell::from_name(path) == kind)
+                    .unwrap_or_else(|| kind.program().to_string());
+                (kind, program)
+            }
+        };
+
+        if kind == Shell::Unknown || !Self::is_available(&program) {
+            return Self {
+                kind: Shell::Sh,
+                program: "sh".to_string(),
+            };
+        }
+        Self { kind, program }
+    }
+
+    fn is_available(program: &str) -> bool {
+        if program.contains('/') {
+            return Path::new(program).is_file();
+        }
+        std::env::var_os("PATH")
+            .map(|paths| std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+            .unwrap_or(false)
+    }
+
+    /// Command running `command` non-interactively. It inherits cliai's environment and
+    /// working directory; `SHELL` is set to the shell that runs it.
+    pub fn command(&self, command: &str) -> tokio::process::Command {
+        if cfg!(target_os = "windows") {
+            let mut cmd = tokio::process::Command::new("cmd");
+            cmd.args(["/C", command]);
+            return cmd;
+        }
+        let mut cmd = tokio::process::Command::new(&self.program);
+        cmd.arg("-c").arg(command).env("SHELL", &self.program);
+        cmd
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -601,6 +686,23 @@ mod tests {
     }
 
     #[test]
+    fn test_execution_shell_resolution() {
+        assert_eq!(Shell::from_name("/usr/local/bin/fish"), Shell::Fish);
+        assert_eq!(Shell::from_name("dash"), Shell::Sh);
+        assert_eq!(Shell::from_name("nu"), Shell::Unknown);
+
+        let sh = ExecutionShell::resolve(Some("sh"));
+        assert_eq!(sh.kind, Shell::Sh);
+        assert_eq!(sh.program, "sh");
+
+        // Missing or unknown shells fall back to sh
+        let missing = ExecutionShell::resolve(Some("/nonexistent/bin/zsh"));
+        assert_eq!(missing.program, "sh");
+        assert_eq!(ExecutionShell::resolve(Some("nu")).kind, Shell::Sh);
+    }
+
+    #[test]
+    #[allow(clippy::cmp_owned)]
     fn test_system_paths() {
         let context = OSContext::detect();
 
@@ -124,12 +124,28 @@ impl PerformanceMeasurement {
     }
 }
 
+/// Token usage summed over the requests answered by one provider
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenTotals {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
 /// Performance monitor for tracking operation timings
 pub struct PerformanceMonitor {
     targets: PerformanceTargets,
     measurements: Vec<PerformanceMeasurement>,
     active_timers: HashMap<String, (OperationType, Instant)>,
     max_measurements: usize,
+    token_totals: HashMap<String, TokenTotals>,
+    provider_latency: HashMap<String, Duration>,
+}
+
+impl Default for PerformanceMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[allow(dead_code)]
@@ -141,6 +157,8 @@ impl PerformanceMonitor {
             measurements: Vec::new(),
             active_timers: HashMap::new(),
             max_measurements: 100, // Keep last 100 measurements
+            token_totals: HashMap::new(),
+            provider_latency: HashMap::new(),
         }
     }
 
@@ -151,6 +169,8 @@ impl PerformanceMonitor {
             measurements: Vec::new(),
             active_timers: HashMap::new(),
             max_measurements: 100,
+            token_totals: HashMap::new(),
+            provider_latency: HashMap::new(),
         }
     }
 
@@ -233,6 +253,38 @@ impl PerformanceMonitor {
         measurement
     }
 
+    /// Add the token usage of an answered request to the provider's session totals
+    pub fn record_token_usage(
+        &mut self,
+        provider: &str,
+        prompt_tokens: u64,
+        completion_tokens: u64,
+    ) {
+        let totals = self.token_totals.entry(provider.to_string()).or_default();
+        totals.requests += 1;
+        totals.prompt_tokens += prompt_tokens;
+        totals.completion_tokens += completion_tokens;
+    }
+
+    /// Token usage per provider since this monitor was created
+    pub fn get_token_totals(&self) -> &HashMap<String, TokenTotals> {
+        &self.token_totals
+    }
+
+    /// Fold a response time of a provider instance into its moving average, which
+    /// weights the newest response by a quarter
+    pub fn record_provider_latency(&mut self, provider: &str, duration: Duration) {
+        self.provider_latency
+            .entry(provider.to_string())
+            .and_modify(|average| *average = (*average * 3 + duration) / 4)
+            .or_insert(duration);
+    }
+
+    /// Average response time of a provider instance, if it has answered before
+    pub fn get_provider_latency(&self, provider: &str) -> Option<Duration> {
+        self.provider_latency.get(provider).copied()
+    }
+
     /// Add measurement to history
     fn add_measurement(&mut self, measurement: PerformanceMeasurement) {
         self.measurements.push(measurement);
@@ -256,23 +308,23 @@ impl PerformanceMonitor {
                 let message = if measurement.success {
                     if measurement.exceeded_target() {
                         format!(
-                            "Performance target exceeded: {} took {} (target: {})",
-                            format!("{:?}", measurement.operation_type),
+                            "Performance target exceeded: {:?} took {} (target: {})",
+                            measurement.operation_type,
                             measurement.format_duration(),
                             measurement.format_target()
                         )
                     } else {
                         format!(
-                            "Performance within target: {} took {} (target: {})",
-                            format!("{:?}", measurement.operation_type),
+                            "Performance within target: {:?} took {} (target: {})",
+                            measurement.operation_type,
                             measurement.format_duration(),
                             measurement.format_target()
                         )
                     }
                 } else {
                     format!(
-                        "Operation failed: {} took {} (error: {})",
-                        format!("{:?}", measurement.operation_type),
+                        "Operation failed: {:?} took {} (error: {})",
+                        measurement.operation_type,
                         measurement.format_duration(),
                         measurement.error.as_deref().unwrap_or("unknown")
                     )
@@ -400,6 +452,8 @@ impl PerformanceMonitor {
     pub fn clear_measurements(&mut self) {
         self.measurements.clear();
         self.active_timers.clear();
+        self.token_totals.clear();
+        self.provider_latency.clear();
     }
 
     /// Update performance targets
@@ -534,6 +588,12 @@ pub struct DegradationManager {
     strategies: HashMap<OperationType, Vec<DegradationStrategy>>,
 }
 
+impl Default for DegradationManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[allow(dead_code)]
 impl DegradationManager {
     /// Create a new degradation manager with default strategies
@@ -719,6 +779,43 @@ mod tests {
         assert_eq!(monitor.measurements.len(), 1);
     }
 
+    #[test]
+    fn test_performance_monitor_token_totals() {
+        let mut monitor = PerformanceMonitor::new();
+        monitor.record_token_usage("OpenAI", 100, 20);
+        monitor.record_token_usage("OpenAI", 50, 5);
+        monitor.record_token_usage("Local", 10, 1);
+
+        let openai = monitor.get_token_totals()["OpenAI"];
+        assert_eq!(openai.requests, 2);
+        assert_eq!(openai.prompt_tokens, 150);
+        assert_eq!(openai.completion_tokens, 25);
+
+        monitor.clear_measurements();
+        assert!(monitor.get_token_totals().is_empty());
+    }
+
+    #[test]
+    fn test_performance_monitor_provider_latency() {
+        let mut monitor = PerformanceMonitor::new();
+        assert!(monitor.get_provider_latency("workstation").is_none());
+
+        monitor.record_provider_latency("workstation", Duration::from_millis(400));
+        assert_eq!(
+            monitor.get_provider_latency("workstation"),
+            Some(Duration::from_millis(400))
+        );
+
+        monitor.record_provider_latency("workstation", Duration::from_millis(800));
+        assert_eq!(
+            monitor.get_provider_latency("workstation"),
+            Some(Duration::from_millis(500))
+        );
+
+        monitor.clear_measurements();
+        assert!(monitor.get_provider_latency("workstation").is_none());
+    }
+
     #[test]
     fn test_performance_stats() {
         let mut monitor = PerformanceMonitor::new();
@@ -0,0 +1,220 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Persisted circuit-breaker state and history of one provider (timestamps in unix seconds)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BreakerRecord {
+    /// Consecutive failures since the last success
+    pub failure_count: u32,
+    /// When the breaker last opened; cleared by a success
+    pub opened_at: Option<u64>,
+    pub last_failure: Option<u64>,
+    pub last_success: Option<u64>,
+    #[serde(default)]
+    pub total_failures: u64,
+    #[serde(default)]
+    pub total_successes: u64,
+    /// Moving average of response times in milliseconds (used for latency routing)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+}
+
+impl BreakerRecord {
+    /// Record a successful call
+    pub fn record_success(&mut self, now: u64) {
+        self.failure_count = 0;
+        self.opened_at = None;
+        self.last_success = Some(now);
+        self.total_successes += 1;
+    }
+
+    /// Fold a response time into the moving average, weighting it by a quarter
+    pub fn record_latency(&mut self, latency_ms: u64) {
+        self.latency_ms = Some(match self.latency_ms {
+            Some(average) => (average * 3 + latency_ms) / 4,
+            None => latency_ms,
+        });
+    }
+
+    /// Record a failed call; the breaker (re)opens once `failure_threshold` is reached
+    pub fn record_failure(&mut self, now: u64, failure_threshold: u32) {
+        self.failure_count += 1;
+        self.last_failure = Some(now);
+        self.total_failures += 1;
+
+        if self.failure_count >= failure_threshold {
+            self.opened_at = Some(now);
+        }
+    }
+}
+
+/// Breaker records of all providers, shared between CLI invocations through a JSON
+/// file in the cache directory. Every read-modify-write holds an exclusive file lock
+/// so concurrent invocations do not lose each other's updates.
+#[derive(Debug, Clone)]
+pub struct ProviderHealthStore {
+    path: PathBuf,
+}
+
+#[allow(dead_code)]
+impl ProviderHealthStore {
+    /// Store at the default location in the cache directory
+    pub fn new() -> Result<Self> {
+        Ok(Self::at(Self::get_health_path()?))
+    }
+
+    /// Store at a specific file
+    pub fn at(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Load all records under a shared lock. A missing or unreadable file yields no records.
+    pub fn load(&self) -> HashMap<String, BreakerRecord> {
+        let Ok(mut file) = File::open(&self.path) else {
+            return HashMap::new();
+        };
+        if file.lock_shared().is_err() {
+            return HashMap::new();
+        }
+
+        let mut content = String::new();
+        let records = match file.read_to_string(&mut content) {
+            Ok(_) => serde_json::from_str(&content).unwrap_or_default(),
+            Err(_) => HashMap::new(),
+        };
+        let _ = file.unlock();
+        records
+    }
+
+    /// Update the records under an exclusive lock and return the result
+    pub fn update<F>(&self, apply: F) -> Result<HashMap<String, BreakerRecord>>
+    where
+        F: FnOnce(&mut HashMap<String, BreakerRecord>),
+    {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)?;
+        file.lock()
+            .map_err(|e| anyhow!("Failed to lock provider health file: {}", e))?;
+
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        let mut records: HashMap<String, BreakerRecord> =
+            serde_json::from_str(&content).unwrap_or_default();
+
+        apply(&mut records);
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(serde_json::to_string_pretty(&records)?.as_bytes())?;
+        file.flush()?;
+        file.unlock()?;
+
+        Ok(records)
+    }
+
+    /// Current time in unix seconds
+    pub fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+
+    /// Get provider health file path
+    pub fn get_health_path() -> Result<PathBuf> {
+        let cache_dir = dirs::cache_dir()
+            .or_else(dirs::config_dir)
+            .ok_or_else(|| anyhow!("Could not find cache directory"))?;
+        Ok(cache_dir.join("cliai").join("provider_health.json"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_opens_at_threshold_and_success_resets() {
+        let mut record = BreakerRecord::default();
+        record.record_failure(100, 2);
+        assert_eq!(record.failure_count, 1);
+        assert!(record.opened_at.is_none());
+
+        record.record_failure(105, 2);
+        assert_eq!(record.opened_at, Some(105));
+
+        record.record_success(200);
+        assert_eq!(record.failure_count, 0);
+        assert!(record.opened_at.is_none());
+        assert_eq!(record.last_success, Some(200));
+        // History survives the reset
+        assert_eq!(record.total_failures, 2);
+        assert_eq!(record.total_successes, 1);
+
+        record.record_latency(400);
+        record.record_latency(800);
+        assert_eq!(record.latency_ms, Some(500));
+    }
+
+    #[test]
+    fn test_store_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ProviderHealthStore::at(dir.path().join("health.json"));
+        assert!(store.load().is_empty());
+
+        store
+            .update(|records| {
+                records
+                    .entry("Local".to_string())
+                    .or_default()
+                    .record_failure(10, 5);
+            })
+            .unwrap();
+        let updated = store
+            .update(|records| {
+                records
+                    .entry("Local".to_string())
+                    .or_default()
+                    .record_failure(20, 5);
+            })
+            .unwrap();
+        assert_eq!(updated["Local"].failure_count, 2);
+
+        // A separate handle (another invocation) sees the same state
+        let reloaded = ProviderHealthStore::at(dir.path().join("health.json")).load();
+        assert_eq!(reloaded["Local"].failure_count, 2);
+        assert_eq!(reloaded["Local"].last_failure, Some(20));
+    }
+
+    #[test]
+    fn test_store_tolerates_corrupt_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("health.json");
+        fs::write(&path, "not json").unwrap();
+
+        let store = ProviderHealthStore::at(path);
+        assert!(store.load().is_empty());
+        let records = store
+            .update(|records| {
+                records
+                    .entry("Cloud".to_string())
+                    .or_default()
+                    .record_success(1);
+            })
+            .unwrap();
+        assert_eq!(records.len(), 1);
+    }
+}
@@ -1,26 +1,223 @@
-use crate::agents::profiles::AgentProfile;
+use crate::agents::profiles::{AgentProfile, GenerationParams};
+use crate::cancellation::{is_cancelled, CancellationToken};
+use crate::history::ChatTurn;
+use crate::logging::{get_logger, LogCategory};
+use crate::network::{client_builder, describe_request_error};
 use crate::performance::{
     OperationType, PerformanceMonitor, SystemPerformanceSummary, TimeoutHandler,
 };
+use crate::provider_health::{BreakerRecord, ProviderHealthStore};
+use crate::response_cache::{stable_hash, ResponseCache};
+use crate::usage::UsageTracker;
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use futures::stream::{self, Stream, StreamExt};
 use reqwest::Client;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
-use std::collections::HashMap;
-use std::time::Duration;
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 /// Provider type enumeration for different AI backends
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ProviderType {
     Local,
     Cloud,
+    OpenAI,
+    Anthropic,
+    Gemini,
+    Cohere,
+}
+
+/// Token counts of one request as reported by the provider
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+}
+
+/// A complete reply together with the model that produced it and its token usage
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProviderResponse {
+    pub text: String,
+    /// `None` when the provider does not report usage
+    pub usage: Option<TokenUsage>,
+    pub model: String,
+    pub provider: ProviderType,
+}
+
+impl ProviderResponse {
+    pub fn new(
+        text: String,
+        usage: Option<TokenUsage>,
+        model: &str,
+        provider: ProviderType,
+    ) -> Self {
+        Self {
+            text,
+            usage,
+            model: model.to_string(),
+            provider,
+        }
+    }
+}
+
+/// Piece of a streamed response: a text delta and/or the token usage, which providers
+/// report once near the end of the stream
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StreamChunk {
+    pub text: String,
+    pub usage: Option<TokenUsage>,
+}
+
+impl StreamChunk {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            usage: None,
+        }
+    }
+
+    pub fn usage(usage: TokenUsage) -> Self {
+        Self {
+            text: String::new(),
+            usage: Some(usage),
+        }
+    }
+}
+
+/// Incremental chunks of a streamed response
+pub type TokenStream = Pin<Box<dyn Stream<Item = Result<StreamChunk>> + Send>>;
+
+/// A chat request in structured form: the system message, earlier turns from
+/// `History` and the current user message
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Conversation {
+    pub system: String,
+    pub history: Vec<ChatTurn>,
+    pub user: String,
+    /// JSON schema the reply must follow (structured output), if any
+    pub response_schema: Option<serde_json::Value>,
+}
+
+impl Conversation {
+    /// Create a conversation without prior turns
+    pub fn new(system: impl Into<String>, user: impl Into<String>) -> Self {
+        Self {
+            system: system.into(),
+            history: Vec::new(),
+            user: user.into(),
+            response_schema: None,
+        }
+    }
+
+    /// Attach earlier conversation turns
+    pub fn with_history(mut self, history: Vec<ChatTurn>) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Require the reply to be JSON matching `schema`
+    pub fn with_response_schema(mut self, schema: serde_json::Value) -> Self {
+        self.response_schema = Some(schema);
+        self
+    }
+
+    /// Earlier turns followed by the current user message. Roles are normalized to
+    /// user/assistant, consecutive turns of the same role are merged and leading
+    /// assistant turns dropped, since strict chat APIs require alternating roles
+    /// starting with the user.
+    pub fn messages(&self) -> Vec<ChatTurn> {
+        let current = ChatTurn {
+            role: "user".to_string(),
+            content: self.user.clone(),
+        };
+
+        let mut messages: Vec<ChatTurn> = Vec::new();
+        for turn in self.history.iter().chain(std::iter::once(&current)) {
+            let role = if turn.role.eq_ignore_ascii_case("assistant") {
+                "assistant"
+            } else {
+                "user"
+            };
+            match messages.last_mut() {
+                Some(last) if last.role == role => {
+                    last.content.push_str("\n\n");
+                    last.content.push_str(&turn.content);
+                }
+                None if role == "assistant" => {}
+                _ => messages.push(ChatTurn {
+                    role: role.to_string(),
+                    content: turn.content.clone(),
+                }),
+            }
+        }
+        messages
+    }
+
+    /// Messages in the OpenAI/Ollama chat format, system message first
+    pub fn to_chat_messages(&self) -> Vec<serde_json::Value> {
+        let system =
+            (!self.system.is_empty()).then(|| json!({ "role": "system", "content": self.system }));
+
+        system
+            .into_iter()
+            .chain(
+                self.messages()
+                    .into_iter()
+                    .map(|turn| json!({ "role": turn.role, "content": turn.content })),
+            )
+            .collect()
+    }
+
+    /// Flatten into a single prompt for completion-style endpoints
+    pub fn to_prompt(&self) -> String {
+        let mut prompt = self.system.clone();
+
+        if !self.history.is_empty() {
+            prompt.push_str("\n\nConversation history:\n");
+            for turn in &self.history {
+                prompt.push_str(&format!("{}: {}\n", turn.role, turn.content));
+            }
+        }
+
+        if let Some(schema) = &self.response_schema {
+            prompt.push_str(&format!(
+                "\n\nRespond ONLY with JSON matching this schema:\n{}",
+                schema
+            ));
+        }
+
+        prompt.push_str(&format!("\n\nUser: {}", self.user));
+        prompt
+    }
 }
 
 /// Common interface for all AI providers
 #[async_trait]
 pub trait AIProvider: Send + Sync {
     /// Generate a response using the provider's AI model
-    async fn generate_response(&self, prompt: &str, agent: &AgentProfile) -> Result<String>;
+    async fn generate_response(
+        &self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+    ) -> Result<ProviderResponse>;
+
+    /// Generate a response as a stream of text chunks. Providers without native
+    /// streaming yield the complete reply as a single chunk.
+    async fn generate_response_stream(
+        &self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+    ) -> Result<TokenStream> {
+        let response = self.generate_response(conversation, agent).await?;
+        let chunk = StreamChunk {
+            text: response.text,
+            usage: response.usage,
+        };
+        Ok(Box::pin(stream::once(async move { Ok(chunk) })))
+    }
 
     /// List available models for this provider
     async fn list_models(&self) -> Result<Vec<String>>;
@@ -33,6 +230,9 @@ pub trait AIProvider: Send + Sync {
 
     /// Get the provider name for logging/display purposes
     fn get_name(&self) -> &'static str;
+
+    /// Get the model this provider generates with (part of the response cache key)
+    fn get_model_name(&self) -> &str;
 }
 
 /// Circuit breaker states for provider failure handling
@@ -50,7 +250,8 @@ pub struct CircuitBreaker {
     failure_count: u32,
     failure_threshold: u32,
     recovery_timeout: Duration,
-    last_failure_time: Option<std::time::Instant>,
+    // Wall-clock time so it can be restored from the persisted health file
+    last_failure_time: Option<SystemTime>,
 }
 
 impl CircuitBreaker {
@@ -64,18 +265,20 @@ impl CircuitBreaker {
         }
     }
 
+    /// Whether the breaker would let a request through, without moving an open breaker
+    /// whose recovery window has passed to half-open
+    pub fn would_allow(&self) -> bool {
+        self.state != CircuitBreakerState::Open || self.retry_after().is_none()
+    }
+
     /// Check if the circuit breaker allows the operation
     pub fn can_execute(&mut self) -> bool {
         match self.state {
             CircuitBreakerState::Closed => true,
             CircuitBreakerState::Open => {
-                if let Some(last_failure) = self.last_failure_time {
-                    if last_failure.elapsed() >= self.recovery_timeout {
-                        self.state = CircuitBreakerState::HalfOpen;
-                        true
-                    } else {
-                        false
-                    }
+                if self.retry_after().is_none() {
+                    self.state = CircuitBreakerState::HalfOpen;
+                    true
                 } else {
                     false
                 }
@@ -94,26 +297,396 @@ impl CircuitBreaker {
     /// Record a failed operation
     pub fn record_failure(&mut self) {
         self.failure_count += 1;
-        self.last_failure_time = Some(std::time::Instant::now());
+        self.last_failure_time = Some(SystemTime::now());
 
         if self.failure_count >= self.failure_threshold {
             self.state = CircuitBreakerState::Open;
         }
     }
 
+    /// Restore state persisted by an earlier invocation
+    pub fn restore(&mut self, record: &BreakerRecord) {
+        self.failure_count = record.failure_count;
+        self.last_failure_time = record
+            .last_failure
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+        self.state = if record.opened_at.is_some() && record.failure_count >= self.failure_threshold
+        {
+            CircuitBreakerState::Open
+        } else {
+            CircuitBreakerState::Closed
+        };
+    }
+
+    /// Time left until an open breaker lets a trial request through
+    pub fn retry_after(&self) -> Option<Duration> {
+        if self.state != CircuitBreakerState::Open {
+            return None;
+        }
+        let Some(last_failure) = self.last_failure_time else {
+            return Some(self.recovery_timeout);
+        };
+        let elapsed = last_failure.elapsed().unwrap_or_default();
+        self.recovery_timeout
+            .checked_sub(elapsed)
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Number of consecutive failures that opens the breaker
+    pub fn failure_threshold(&self) -> u32 {
+        self.failure_threshold
+    }
+
     /// Get current state
     pub fn get_state(&self) -> CircuitBreakerState {
         self.state.clone()
     }
 }
 
-/// Provider manager with fallback logic and circuit breaker
+/// Turn a chunked HTTP body into a token stream. The body is split into lines and each
+/// line is handed to `parse_line`, which returns `None` for lines that carry neither
+/// text nor usage (keep-alives, SSE event names, metadata). The stream ends after the
+/// first error.
+fn lines_to_token_stream<F>(response: reqwest::Response, parse_line: F) -> TokenStream
+where
+    F: FnMut(&str) -> Option<Result<StreamChunk>> + Send + 'static,
+{
+    struct LineState<F> {
+        response: Option<reqwest::Response>,
+        buffer: Vec<u8>,
+        pending: VecDeque<Result<StreamChunk>>,
+        parse_line: F,
+    }
+
+    impl<F: FnMut(&str) -> Option<Result<StreamChunk>>> LineState<F> {
+        fn parse(&mut self, raw: &[u8]) {
+            let line = String::from_utf8_lossy(raw);
+            let line = line.trim_end_matches(['\r', '\n']);
+            if let Some(item) = (self.parse_line)(line) {
+                if item.is_err() {
+                    // Nothing useful can follow an error; stop reading the body
+                    self.response = None;
+                }
+                self.pending.push_back(item);
+            }
+        }
+    }
+
+    let state = LineState {
+        response: Some(response),
+        buffer: Vec::new(),
+        pending: VecDeque::new(),
+        parse_line,
+    };
+
+    Box::pin(stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(item) = state.pending.pop_front() {
+                return Some((item, state));
+            }
+
+            let chunk = state.response.as_mut()?.chunk().await;
+            match chunk {
+                Ok(Some(bytes)) => {
+                    state.buffer.extend_from_slice(&bytes);
+                    while let Some(pos) = state.buffer.iter().position(|b| *b == b'\n') {
+                        let line: Vec<u8> = state.buffer.drain(..=pos).collect();
+                        state.parse(&line);
+                    }
+                }
+                Ok(None) => {
+                    state.response = None;
+                    let rest = std::mem::take(&mut state.buffer);
+                    if !rest.is_empty() {
+                        state.parse(&rest);
+                    }
+                }
+                Err(e) => {
+                    state.response = None;
+                    state
+                        .pending
+                        .push_back(Err(anyhow!("Response stream interrupted: {}", e)));
+                }
+            }
+        }
+    }))
+}
+
+/// How a single streamed provider attempt failed
+enum StreamAttemptError {
+    /// Failed before any text arrived; the next provider can take over
+    BeforeFirstToken(anyhow::Error),
+    /// Failed after text was already handed to the caller
+    AfterFirstToken(anyhow::Error),
+}
+
+/// A provider stream that has delivered its first text, or ended without any
+struct StartedStream {
+    stream: TokenStream,
+    first_text: String,
+    usage: Option<TokenUsage>,
+    finished: bool,
+}
+
+/// Open a provider stream and wait for its first text, which must arrive by
+/// `first_token_deadline`. Nothing is handed to the caller yet, so a failure here
+/// leaves the request free to go to another provider.
+async fn start_stream(
+    provider: &dyn AIProvider,
+    conversation: &Conversation,
+    agent: &AgentProfile,
+    first_token_timeout: Duration,
+    first_token_deadline: tokio::time::Instant,
+) -> Result<StartedStream> {
+    let no_first_token = || {
+        anyhow!(
+            "No response from {} within {}ms (first token timeout)",
+            provider.get_name(),
+            first_token_timeout.as_millis()
+        )
+    };
+
+    let mut stream = tokio::time::timeout_at(
+        first_token_deadline,
+        provider.generate_response_stream(conversation, agent),
+    )
+    .await
+    .map_err(|_| no_first_token())??;
+
+    let mut usage = None;
+    loop {
+        match tokio::time::timeout_at(first_token_deadline, stream.next()).await {
+            Ok(Some(Ok(chunk))) => {
+                if chunk.usage.is_some() {
+                    usage = chunk.usage;
+                }
+                if !chunk.text.is_empty() {
+                    return Ok(StartedStream {
+                        stream,
+                        first_text: chunk.text,
+                        usage,
+                        finished: false,
+                    });
+                }
+            }
+            Ok(Some(Err(e))) => return Err(e),
+            Ok(None) => {
+                return Ok(StartedStream {
+                    stream,
+                    first_text: String::new(),
+                    usage,
+                    finished: true,
+                })
+            }
+            Err(_) => return Err(no_first_token()),
+        }
+    }
+}
+
+/// Forward the rest of a started stream to `on_chunk`, beginning with its first text.
+/// The response must complete by `total_deadline`.
+async fn finish_stream(
+    provider: &dyn AIProvider,
+    started: StartedStream,
+    total_timeout: Duration,
+    total_deadline: tokio::time::Instant,
+    on_chunk: &mut (dyn FnMut(&str) + Send),
+) -> Result<ProviderResponse> {
+    let StartedStream {
+        mut stream,
+        first_text: mut response,
+        mut usage,
+        mut finished,
+    } = started;
+
+    if !response.is_empty() {
+        on_chunk(&response);
+    }
+
+    while !finished {
+        match tokio::time::timeout_at(total_deadline, stream.next()).await {
+            Ok(Some(Ok(chunk))) => {
+                if chunk.usage.is_some() {
+                    usage = chunk.usage;
+                }
+                if chunk.text.is_empty() {
+                    continue;
+                }
+                on_chunk(&chunk.text);
+                response.push_str(&chunk.text);
+            }
+            Ok(Some(Err(e))) => return Err(e),
+            Ok(None) => finished = true,
+            Err(_) => {
+                return Err(anyhow!(
+                    "Provider {} timed out after {}ms while streaming",
+                    provider.get_name(),
+                    total_timeout.as_millis()
+                ))
+            }
+        }
+    }
+
+    Ok(ProviderResponse::new(
+        response,
+        usage,
+        provider.get_model_name(),
+        provider.get_provider_type(),
+    ))
+}
+
+/// Run one streamed attempt against a provider, forwarding chunks to `on_chunk`.
+/// The first chunk must arrive within `first_token_timeout`; the whole response
+/// must complete within `total_timeout`.
+async fn stream_attempt(
+    provider: &dyn AIProvider,
+    conversation: &Conversation,
+    agent: &AgentProfile,
+    first_token_timeout: Duration,
+    total_timeout: Duration,
+    on_chunk: &mut (dyn FnMut(&str) + Send),
+) -> std::result::Result<ProviderResponse, StreamAttemptError> {
+    let start = tokio::time::Instant::now();
+    let first_token_deadline = start + std::cmp::min(first_token_timeout, total_timeout);
+
+    let started = start_stream(
+        provider,
+        conversation,
+        agent,
+        first_token_timeout,
+        first_token_deadline,
+    )
+    .await
+    .map_err(StreamAttemptError::BeforeFirstToken)?;
+
+    finish_stream(
+        provider,
+        started,
+        total_timeout,
+        start + total_timeout,
+        on_chunk,
+    )
+    .await
+    .map_err(StreamAttemptError::AfterFirstToken)
+}
+
+/// Performance category of a provider type
+fn operation_type(provider_type: &ProviderType) -> OperationType {
+    match provider_type {
+        ProviderType::Local => OperationType::LocalOllama,
+        ProviderType::Cloud
+        | ProviderType::OpenAI
+        | ProviderType::Anthropic
+        | ProviderType::Gemini
+        | ProviderType::Cohere => OperationType::CloudProvider,
+    }
+}
+
+/// Instance name of an unnamed provider, also its key in the persisted health file
+fn health_key(provider_type: &ProviderType) -> String {
+    format!("{:?}", provider_type)
+}
+
+/// Circuit breaker settings for a provider type
+fn default_circuit_breaker(provider_type: &ProviderType) -> CircuitBreaker {
+    match provider_type {
+        ProviderType::Local => CircuitBreaker::new(5, Duration::from_secs(30)),
+        _ => CircuitBreaker::new(3, Duration::from_secs(15)),
+    }
+}
+
+/// How long model discovery may wait for an endpoint's model list
+const MODEL_DISCOVERY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How requests are spread over several endpoints of the same provider type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RoutingStrategy {
+    /// Endpoints in configuration order
+    #[default]
+    Priority,
+    /// Each request starts at the next endpoint
+    RoundRobin,
+    /// Fastest endpoint first, by observed response time; untried endpoints go first
+    LowestLatency,
+}
+
+/// A registered provider and the instance name its circuit breaker and health record
+/// are kept under
+struct ProviderInstance {
+    name: String,
+    provider: Box<dyn AIProvider>,
+    /// Models the endpoint serves, if known
+    models: Option<Vec<String>>,
+    /// Whether model discovery was attempted (it runs at most once per manager)
+    models_checked: bool,
+}
+
+impl ProviderInstance {
+    /// Whether the instance serves the model it is configured for; `None` if unknown
+    fn has_model(&self) -> Option<bool> {
+        self.models
+            .as_ref()
+            .map(|models| OllamaProvider::is_installed(models, self.provider.get_model_name()))
+    }
+
+    /// Display name: the provider name, plus the instance name for named endpoints
+    fn label(&self) -> String {
+        if self.name == health_key(&self.provider.get_provider_type()) {
+            self.provider.get_name().to_string()
+        } else {
+            format!("{} ({})", self.provider.get_name(), self.name)
+        }
+    }
+}
+
+/// Error returned when a paid provider is skipped because the monthly budget is used up
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetExceeded {
+    pub provider_type: ProviderType,
+    pub spent: f64,
+    pub budget: f64,
+}
+
+impl std::fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} provider skipped: monthly budget of ${:.2} reached (${:.2} spent). Raise monthly_budget_usd in the config or use the local provider",
+            self.provider_type, self.budget, self.spent
+        )
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+/// Whether an error, or any error it wraps, is a provider skipped for the monthly budget
+pub fn is_budget_exceeded(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.is::<BudgetExceeded>())
+}
+
+/// Provider manager with fallback logic and a circuit breaker per provider instance
 pub struct ProviderManager {
-    providers: Vec<Box<dyn AIProvider>>,
+    providers: Vec<ProviderInstance>,
     fallback_chain: Vec<ProviderType>,
     retry_limits: HashMap<ProviderType, u32>,
-    circuit_breakers: HashMap<ProviderType, CircuitBreaker>,
+    circuit_breakers: HashMap<String, CircuitBreaker>,
+    routing: RoutingStrategy,
+    round_robin_offset: usize,
     performance_monitor: PerformanceMonitor,
+    first_token_timeout: Duration,
+    hedge_delay: Option<Duration>,
+    cancellation: Option<CancellationToken>,
+    response_cache: Option<ResponseCache>,
+    cache_context: u64,
+    health_store: Option<ProviderHealthStore>,
+    usage_tracker: Option<UsageTracker>,
+}
+
+impl Default for ProviderManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[allow(dead_code)]
@@ -123,16 +696,34 @@ impl ProviderManager {
         let mut retry_limits = HashMap::new();
         retry_limits.insert(ProviderType::Local, 2);
         retry_limits.insert(ProviderType::Cloud, 1);
+        retry_limits.insert(ProviderType::OpenAI, 1);
+        retry_limits.insert(ProviderType::Anthropic, 1);
+        retry_limits.insert(ProviderType::Gemini, 1);
+        retry_limits.insert(ProviderType::Cohere, 1);
 
-        let mut circuit_breakers = HashMap::new();
-        circuit_breakers.insert(
+        let circuit_breakers = [
             ProviderType::Local,
-            CircuitBreaker::new(5, Duration::from_secs(30)),
-        );
-        circuit_breakers.insert(
             ProviderType::Cloud,
-            CircuitBreaker::new(3, Duration::from_secs(15)),
-        );
+            ProviderType::OpenAI,
+            ProviderType::Anthropic,
+            ProviderType::Gemini,
+            ProviderType::Cohere,
+        ]
+        .iter()
+        .map(|provider_type| {
+            (
+                health_key(provider_type),
+                default_circuit_breaker(provider_type),
+            )
+        })
+        .collect();
+
+        // Round-robin starts at a per-process offset so separate invocations spread
+        // across endpoints
+        let round_robin_offset = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos() as usize)
+            .unwrap_or(0);
 
         Self {
             providers: Vec::new(),
@@ -140,13 +731,66 @@ impl ProviderManager {
             fallback_chain: vec![ProviderType::Local, ProviderType::Cloud],
             retry_limits,
             circuit_breakers,
+            routing: RoutingStrategy::default(),
+            round_robin_offset,
             performance_monitor: PerformanceMonitor::new(),
+            first_token_timeout: Duration::from_secs(30),
+            hedge_delay: None,
+            cancellation: None,
+            response_cache: None,
+            cache_context: 0,
+            health_store: None,
+            usage_tracker: None,
         }
     }
 
-    /// Add a provider to the manager
+    /// Add a provider to the manager under its type's instance name (e.g. "Local")
     pub fn add_provider(&mut self, provider: Box<dyn AIProvider>) {
-        self.providers.push(provider);
+        let name = health_key(&provider.get_provider_type());
+        self.add_named_provider(name, provider);
+    }
+
+    /// Add a provider with its own instance name, circuit breaker and health record,
+    /// e.g. one of several Ollama endpoints
+    pub fn add_named_provider(&mut self, name: impl Into<String>, provider: Box<dyn AIProvider>) {
+        let name = name.into();
+        self.circuit_breakers
+            .entry(name.clone())
+            .or_insert_with(|| default_circuit_breaker(&provider.get_provider_type()));
+        self.providers.push(ProviderInstance {
+            name,
+            provider,
+            models: None,
+            models_checked: false,
+        });
+    }
+
+    /// Declare the models an instance serves, so routing needs no discovery request
+    pub fn set_instance_models(&mut self, name: &str, models: Vec<String>) {
+        for instance in self.providers.iter_mut().filter(|i| i.name == name) {
+            instance.models = Some(models.clone());
+            instance.models_checked = true;
+        }
+    }
+
+    /// Set how requests are spread over several instances of the same provider type
+    pub fn set_routing(&mut self, routing: RoutingStrategy) {
+        self.routing = routing;
+    }
+
+    /// Replace every registered provider with a wrapper around it (e.g. record/replay)
+    pub fn wrap_providers<F>(&mut self, mut wrap: F)
+    where
+        F: FnMut(Box<dyn AIProvider>) -> Box<dyn AIProvider>,
+    {
+        self.providers = self
+            .providers
+            .drain(..)
+            .map(|mut instance| {
+                instance.provider = wrap(instance.provider);
+                instance
+            })
+            .collect();
     }
 
     /// Set the fallback chain order
@@ -154,22 +798,130 @@ impl ProviderManager {
         self.fallback_chain = chain;
     }
 
+    /// Provider types tried for a request, in order
+    pub fn get_fallback_chain(&self) -> &[ProviderType] {
+        &self.fallback_chain
+    }
+
     /// Set retry limit for a provider type
     pub fn set_retry_limit(&mut self, provider_type: ProviderType, limit: u32) {
         self.retry_limits.insert(provider_type, limit);
     }
 
+    /// Set how long a provider may take to produce its first token before falling back
+    pub fn set_first_token_timeout(&mut self, timeout: Duration) {
+        self.first_token_timeout = timeout;
+    }
+
+    /// Race providers: start the next one when the running ones have produced no token
+    /// within `delay`. `None` tries providers one after another.
+    pub fn set_hedge_delay(&mut self, delay: Option<Duration>) {
+        self.hedge_delay = delay;
+    }
+
+    /// Stop in-flight requests when `token` is cancelled. A cancelled request returns
+    /// [`Cancelled`] and is not counted against the provider.
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancellation = Some(token);
+    }
+
+    /// Share circuit-breaker state and response-time averages with other invocations
+    /// through the health store. Breakers are restored right away, so a provider that is
+    /// still inside its recovery window is skipped without being contacted.
+    pub fn enable_health_persistence(&mut self, store: ProviderHealthStore) {
+        let records = store.load();
+        for (name, circuit_breaker) in self.circuit_breakers.iter_mut() {
+            if let Some(record) = records.get(name) {
+                circuit_breaker.restore(record);
+            }
+        }
+        for instance in &self.providers {
+            if let Some(latency_ms) = records.get(&instance.name).and_then(|r| r.latency_ms) {
+                self.performance_monitor
+                    .record_provider_latency(&instance.name, Duration::from_millis(latency_ms));
+            }
+        }
+        self.health_store = Some(store);
+    }
+
+    /// Record the token usage and cost of every answered request. With a monthly budget
+    /// set, non-local providers are skipped once the month's spending reaches it.
+    pub fn enable_usage_tracking(&mut self, tracker: UsageTracker) {
+        self.usage_tracker = Some(tracker);
+    }
+
+    /// Enable response caching
+    pub fn enable_cache(&mut self, cache: ResponseCache) {
+        self.response_cache = Some(cache);
+    }
+
+    /// Set the fingerprint of the current working directory context. Cached responses
+    /// produced under a different context are invalidated on lookup.
+    pub fn set_cache_context(&mut self, context_fingerprint: u64) {
+        self.cache_context = context_fingerprint;
+    }
+
     /// Get a response using the fallback chain with performance monitoring and timeout handling
     /// Includes response streaming and intelligent caching for faster responses
     pub async fn get_response(
         &mut self,
-        prompt: &str,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+        timeout: Duration,
+    ) -> Result<String> {
+        self.get_response_streaming(conversation, agent, timeout, &mut |_| {})
+            .await
+    }
+
+    /// Get a response using the fallback chain, handing each text chunk to `on_chunk` as
+    /// it arrives. A provider that produces no token within the first-token timeout is
+    /// abandoned for the next one in the chain; once text has been delivered a failure
+    /// is returned as-is, since the caller has already rendered part of the answer.
+    ///
+    /// Responses are cached under the whole conversation, history included, so the same
+    /// question asked in a different context is never answered from the cache.
+    pub async fn get_response_streaming(
+        &mut self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+        timeout: Duration,
+        on_chunk: &mut (dyn FnMut(&str) + Send),
+    ) -> Result<String> {
+        let Some(token) = self.cancellation.clone() else {
+            return self
+                .fetch_response(conversation, agent, timeout, on_chunk)
+                .await;
+        };
+
+        let result = token
+            .run(self.fetch_response(conversation, agent, timeout, on_chunk))
+            .await;
+        if result.as_ref().is_err_and(is_cancelled) {
+            if let Ok(logger) = get_logger() {
+                if let Ok(logger_guard) = logger.lock() {
+                    let _ = logger_guard.log_info(
+                        LogCategory::Provider,
+                        format!("Request for {} agent cancelled", agent.name),
+                        None,
+                    );
+                }
+            }
+        }
+        result
+    }
+
+    /// Cache lookup and provider fallback behind `get_response_streaming`
+    async fn fetch_response(
+        &mut self,
+        conversation: &Conversation,
         agent: &AgentProfile,
         timeout: Duration,
+        on_chunk: &mut (dyn FnMut(&str) + Send),
     ) -> Result<String> {
-        // Check cache first for identical prompts (simple hash-based cache)
-        let prompt_hash = self.hash_prompt(prompt, agent);
-        if let Some(cached_response) = self.check_cache(&prompt_hash) {
+        // Check cache first for identical requests
+        let prompt_hash = self.hash_prompt(&conversation.to_prompt(), agent);
+        if let Some(cached_response) = self.check_cache(&prompt_hash, agent) {
+            on_chunk(&cached_response);
             return Ok(cached_response);
         }
 
@@ -186,121 +938,137 @@ impl ProviderManager {
         self.performance_monitor
             .start_timer(operation_id.clone(), OperationType::TotalSystem);
 
+        if self.hedge_delay.is_some() {
+            let result = self
+                .get_response_hedged(
+                    conversation,
+                    agent,
+                    prompt_hash,
+                    individual_timeout,
+                    &timeout_handler,
+                    on_chunk,
+                )
+                .await;
+            match &result {
+                Ok(_) => {
+                    self.performance_monitor.stop_timer(&operation_id, true)?;
+                }
+                Err(e) => {
+                    self.performance_monitor
+                        .stop_timer_with_error(&operation_id, e.to_string())?;
+                }
+            }
+            return result;
+        }
+
         let mut last_error = None;
         let fallback_chain = self.fallback_chain.clone();
 
-        // Try providers sequentially with optimized timeouts
+        // Try providers sequentially; slow starters are cut off at the first-token timeout.
+        // Several instances of one type (Ollama endpoints) are tried in routing order.
         for provider_type in &fallback_chain {
-            if timeout_handler.is_expired() {
-                let measurement = self.performance_monitor.stop_timer_with_error(
-                    &operation_id,
-                    "Total system timeout exceeded".to_string(),
-                )?;
-                return Err(anyhow!(
-                    "Total system timeout exceeded after {}",
-                    measurement.format_duration()
-                ));
-            }
+            for index in self.route(provider_type).await {
+                if timeout_handler.is_expired() {
+                    let measurement = self.performance_monitor.stop_timer_with_error(
+                        &operation_id,
+                        "Total system timeout exceeded".to_string(),
+                    )?;
+                    return Err(anyhow!(
+                        "Total system timeout exceeded after {}",
+                        measurement.format_duration()
+                    ));
+                }
 
-            if let Some(circuit_breaker) = self.circuit_breakers.get_mut(provider_type) {
-                if !circuit_breaker.can_execute() {
+                let name = self.providers[index].name.clone();
+                if let Some(reason) = self.skip_reason(index) {
+                    last_error.get_or_insert(reason);
                     continue;
                 }
-            }
-
-            let op_type = match provider_type {
-                ProviderType::Local => OperationType::LocalOllama,
-                ProviderType::Cloud => OperationType::CloudProvider,
-            };
+                self.admit(&name);
 
-            let retry_limit = *self.retry_limits.get(provider_type).unwrap_or(&1);
+                let op_type = operation_type(provider_type);
 
-            for attempt in 0..retry_limit {
-                // Use shorter timeout for first provider to enable faster fallback
-                let operation_timeout =
-                    if provider_type == fallback_chain.first().unwrap() && attempt == 0 {
-                        std::cmp::min(individual_timeout, Duration::from_secs(3))
-                    } else {
-                        individual_timeout
-                    };
+                let retry_limit = *self.retry_limits.get(provider_type).unwrap_or(&1);
 
-                if operation_timeout.is_zero() {
-                    break;
-                }
+                for attempt in 0..retry_limit {
+                    let operation_timeout =
+                        std::cmp::min(individual_timeout, timeout_handler.remaining_time());
 
-                let provider_operation_id = format!(
-                    "{}_{}_attempt_{}",
-                    format!("{:?}", provider_type).to_lowercase(),
-                    chrono::Utc::now().timestamp_nanos_opt().unwrap_or(0),
-                    attempt
-                );
-                self.performance_monitor
-                    .start_timer(provider_operation_id.clone(), op_type);
+                    if operation_timeout.is_zero() {
+                        break;
+                    }
 
-                let result = if let Some(provider) = self.get_provider_by_type(provider_type) {
-                    tokio::time::timeout(
+                    let provider_operation_id = format!(
+                        "{}_{}_attempt_{}",
+                        name,
+                        chrono::Utc::now().timestamp_nanos_opt().unwrap_or(0),
+                        attempt
+                    )
+                    .to_lowercase();
+                    self.performance_monitor
+                        .start_timer(provider_operation_id.clone(), op_type);
+
+                    let result = stream_attempt(
+                        &*self.providers[index].provider,
+                        conversation,
+                        agent,
+                        self.first_token_timeout,
                         operation_timeout,
-                        provider.generate_response(prompt, agent),
+                        on_chunk,
                     )
-                    .await
-                } else {
-                    continue;
-                };
-
-                match result {
-                    Ok(Ok(response)) => {
-                        let _measurement = self
-                            .performance_monitor
-                            .stop_timer(&provider_operation_id, true)?;
-                        let _total_measurement =
-                            self.performance_monitor.stop_timer(&operation_id, true)?;
-
-                        // Cache successful response
-                        self.cache_response(&prompt_hash, &response);
-
-                        if let Some(circuit_breaker) = self.circuit_breakers.get_mut(provider_type)
-                        {
-                            circuit_breaker.record_success();
+                    .await;
+
+                    match result {
+                        Ok(response) => {
+                            let measurement = self
+                                .performance_monitor
+                                .stop_timer(&provider_operation_id, true)?;
+                            let _total_measurement =
+                                self.performance_monitor.stop_timer(&operation_id, true)?;
+
+                            // Cache successful response
+                            self.cache_response(&prompt_hash, agent, index, &response.text);
+
+                            self.record_provider_outcome(&name, true, Some(measurement.duration));
+                            self.record_usage(agent, &response);
+                            return Ok(response.text);
                         }
-                        return Ok(response);
-                    }
-                    Ok(Err(e)) => {
-                        let _measurement = self.performance_monitor.stop_timer_with_error(
-                            &provider_operation_id,
-                            format!("Provider error: {}", e),
-                        )?;
-                        last_error = Some(e);
-
-                        if attempt < retry_limit - 1 {
-                            let backoff_ms = 50 * (attempt + 1) as u64; // Reduced backoff
-                            let backoff_duration = Duration::from_millis(backoff_ms);
-
-                            if timeout_handler.has_time_for(backoff_duration) {
-                                tokio::time::sleep(backoff_duration).await;
-                            } else {
-                                break;
+                        Err(StreamAttemptError::AfterFirstToken(e)) => {
+                            let _measurement = self.performance_monitor.stop_timer_with_error(
+                                &provider_operation_id,
+                                format!("Stream failed: {}", e),
+                            )?;
+                            let _total_measurement =
+                                self.performance_monitor.stop_timer_with_error(
+                                    &operation_id,
+                                    "Stream failed".to_string(),
+                                )?;
+
+                            self.record_provider_outcome(&name, false, None);
+                            return Err(e);
+                        }
+                        Err(StreamAttemptError::BeforeFirstToken(e)) => {
+                            let _measurement = self.performance_monitor.stop_timer_with_error(
+                                &provider_operation_id,
+                                format!("Provider error: {}", e),
+                            )?;
+                            last_error = Some(e);
+
+                            if attempt < retry_limit - 1 {
+                                let backoff_ms = 50 * (attempt + 1) as u64; // Reduced backoff
+                                let backoff_duration = Duration::from_millis(backoff_ms);
+
+                                if timeout_handler.has_time_for(backoff_duration) {
+                                    tokio::time::sleep(backoff_duration).await;
+                                } else {
+                                    break;
+                                }
                             }
                         }
                     }
-                    Err(_timeout_error) => {
-                        let _measurement = self.performance_monitor.stop_timer_with_error(
-                            &provider_operation_id,
-                            format!(
-                                "Operation timeout after {}ms",
-                                operation_timeout.as_millis()
-                            ),
-                        )?;
-                        last_error = Some(anyhow!(
-                            "Provider {} timed out",
-                            format!("{:?}", provider_type)
-                        ));
-                        break;
-                    }
                 }
-            }
 
-            if let Some(circuit_breaker) = self.circuit_breakers.get_mut(provider_type) {
-                circuit_breaker.record_failure();
+                self.record_provider_outcome(&name, false, None);
             }
         }
 
@@ -311,38 +1079,370 @@ impl ProviderManager {
         Err(last_error.unwrap_or_else(|| anyhow!("No providers available")))
     }
 
-    /// Simple hash function for prompt caching
-    fn hash_prompt(&self, prompt: &str, agent: &AgentProfile) -> u64 {
-        use std::collections::hash_map::DefaultHasher;
-        use std::hash::{Hash, Hasher};
+    /// Race the providers of the fallback chain. The first candidate starts right away;
+    /// the next one joins whenever the hedge delay passes without a token, or as soon as a
+    /// running candidate fails. The first candidate to deliver text wins and streams to
+    /// `on_chunk`; the others are cancelled and count neither as success nor as failure,
+    /// while candidates that failed before that count as failures.
+    async fn get_response_hedged(
+        &mut self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+        prompt_hash: u64,
+        individual_timeout: Duration,
+        timeout_handler: &TimeoutHandler,
+        on_chunk: &mut (dyn FnMut(&str) + Send),
+    ) -> Result<String> {
+        let mut last_error = None;
+        let mut candidates = Vec::new();
+        for provider_type in self.fallback_chain.clone() {
+            for index in self.route(&provider_type).await {
+                match self.skip_reason(index) {
+                    Some(reason) => {
+                        last_error.get_or_insert(reason);
+                    }
+                    None => candidates.push(index),
+                }
+            }
+        }
+        if candidates.is_empty() {
+            return Err(last_error.unwrap_or_else(|| anyhow!("No providers available")));
+        }
+
+        let first_token_timeout = self.first_token_timeout;
+        let hedge_delay = self.hedge_delay.unwrap_or_default();
+        let mut failures = Vec::new();
+        let mut winner = None;
+        {
+            let providers = &self.providers;
+            let circuit_breakers = &mut self.circuit_breakers;
+            let mut launch = |index: usize| {
+                // Candidates were filtered without touching the breakers; only the ones
+                // actually launched count as a trial request
+                if let Some(circuit_breaker) = circuit_breakers.get_mut(&providers[index].name) {
+                    circuit_breaker.can_execute();
+                }
+                let provider = &*providers[index].provider;
+                let operation_timeout =
+                    std::cmp::min(individual_timeout, timeout_handler.remaining_time());
+                async move {
+                    let start = tokio::time::Instant::now();
+                    let first_token_deadline =
+                        start + std::cmp::min(first_token_timeout, operation_timeout);
+                    let result = start_stream(
+                        provider,
+                        conversation,
+                        agent,
+                        first_token_timeout,
+                        first_token_deadline,
+                    )
+                    .await;
+                    (index, start, result)
+                }
+            };
+
+            let mut running = stream::FuturesUnordered::new();
+            running.push(launch(candidates[0]));
+            let mut next = 1;
+            let hedge_timer = tokio::time::sleep(hedge_delay);
+            tokio::pin!(hedge_timer);
+
+            while !running.is_empty() {
+                tokio::select! {
+                    Some((index, start, result)) = running.next() => match result {
+                        Ok(started) => {
+                            winner = Some((index, start, started));
+                            break;
+                        }
+                        Err(e) => {
+                            failures.push((index, start.elapsed(), e));
+                            if next < candidates.len() {
+                                running.push(launch(candidates[next]));
+                                next += 1;
+                                hedge_timer
+                                    .as_mut()
+                                    .reset(tokio::time::Instant::now() + hedge_delay);
+                            }
+                        }
+                    },
+                    _ = &mut hedge_timer, if next < candidates.len() => {
+                        running.push(launch(candidates[next]));
+                        next += 1;
+                        hedge_timer
+                            .as_mut()
+                            .reset(tokio::time::Instant::now() + hedge_delay);
+                    }
+                }
+            }
+            // Dropping `running` cancels the candidates still waiting for a token
+        }
+
+        for (index, elapsed, error) in failures {
+            let name = self.providers[index].name.clone();
+            let op_type = operation_type(&self.providers[index].provider.get_provider_type());
+            self.performance_monitor
+                .record_measurement(op_type, elapsed, false);
+            self.record_provider_outcome(&name, false, None);
+            last_error = Some(error);
+        }
+
+        let Some((index, start, started)) = winner else {
+            return Err(last_error.unwrap_or_else(|| anyhow!("No providers available")));
+        };
 
-        let mut hasher = DefaultHasher::new();
-        prompt.hash(&mut hasher);
-        agent.name.hash(&mut hasher);
-        hasher.finish()
+        let total_deadline = std::cmp::min(
+            start + individual_timeout,
+            tokio::time::Instant::now() + timeout_handler.remaining_time(),
+        );
+        let provider = &*self.providers[index].provider;
+        let op_type = operation_type(&provider.get_provider_type());
+        let result = finish_stream(
+            provider,
+            started,
+            individual_timeout,
+            total_deadline,
+            on_chunk,
+        )
+        .await;
+
+        let name = self.providers[index].name.clone();
+        let duration = start.elapsed();
+        match result {
+            Ok(response) => {
+                self.performance_monitor
+                    .record_measurement(op_type, duration, true);
+                self.cache_response(&prompt_hash, agent, index, &response.text);
+                self.record_provider_outcome(&name, true, Some(duration));
+                self.record_usage(agent, &response);
+                Ok(response.text)
+            }
+            Err(e) => {
+                self.performance_monitor
+                    .record_measurement(op_type, duration, false);
+                self.record_provider_outcome(&name, false, None);
+                Err(e)
+            }
+        }
     }
 
-    /// Check cache for existing response (simple in-memory cache)
-    fn check_cache(&self, _prompt_hash: &u64) -> Option<String> {
-        // For now, return None (no caching)
-        // In production, implement LRU cache with TTL
-        None
+    /// Stable hash of the request for the persistent response cache. Generation
+    /// settings are included so changing them does not serve stale answers.
+    fn hash_prompt(&self, prompt: &str, agent: &AgentProfile) -> u64 {
+        stable_hash(&format!(
+            "{}\n{:?}\n{}",
+            agent.name,
+            agent.params,
+            prompt.trim()
+        ))
     }
 
-    /// Cache a successful response
-    fn cache_response(&self, _prompt_hash: &u64, _response: &str) {
-        // For now, do nothing
-        // In production, implement LRU cache with TTL
+    /// Cache key for a request answered by a specific provider and model
+    fn cache_key(prompt_hash: u64, agent: &AgentProfile, provider: &dyn AIProvider) -> String {
+        format!(
+            "{:016x}:{}:{}:{}",
+            prompt_hash,
+            agent.name,
+            provider.get_name(),
+            provider.get_model_name()
+        )
     }
 
-    /// List models from the first available provider
-    /// List models from ALL available providers (combining Local and Cloud)
-    pub async fn list_models(&self) -> Result<Vec<String>> {
+    /// Cache keys of a request for every provider in the fallback chain, in chain order
+    fn chain_cache_keys(&self, prompt_hash: u64, agent: &AgentProfile) -> Vec<String> {
+        let mut keys: Vec<String> = Vec::new();
+        for provider_type in &self.fallback_chain {
+            for provider in self.get_providers_by_type(provider_type) {
+                let key = Self::cache_key(prompt_hash, agent, provider);
+                if !keys.contains(&key) {
+                    keys.push(key);
+                }
+            }
+        }
+        keys
+    }
+
+    /// Check cache for a response from any provider in the fallback chain, in chain order
+    fn check_cache(&mut self, prompt_hash: &u64, agent: &AgentProfile) -> Option<String> {
+        self.response_cache.as_ref()?;
+
+        let keys = self.chain_cache_keys(*prompt_hash, agent);
+
+        let context = self.cache_context;
+        let cache = self.response_cache.as_mut()?;
+        keys.iter().find_map(|key| cache.get(key, context))
+    }
+
+    /// Drop cached responses for a request, e.g. after the reply turned out to be unusable
+    pub fn forget_cached_response(&mut self, conversation: &Conversation, agent: &AgentProfile) {
+        let prompt_hash = self.hash_prompt(&conversation.to_prompt(), agent);
+        let keys = self.chain_cache_keys(prompt_hash, agent);
+
+        if let Some(cache) = self.response_cache.as_mut() {
+            for key in &keys {
+                cache.remove(key);
+            }
+        }
+    }
+
+    /// Cache a successful response of the provider instance at `index`
+    fn cache_response(
+        &mut self,
+        prompt_hash: &u64,
+        agent: &AgentProfile,
+        index: usize,
+        response: &str,
+    ) {
+        if self.response_cache.is_none() || response.trim().is_empty() {
+            return;
+        }
+
+        let key = Self::cache_key(*prompt_hash, agent, &*self.providers[index].provider);
+
+        let context = self.cache_context;
+        if let Some(cache) = self.response_cache.as_mut() {
+            cache.put(&key, context, response);
+        }
+    }
+
+    /// Why a provider instance must not be contacted now: its circuit breaker is open,
+    /// or it is a paid provider and the monthly budget is used up. Leaves the breaker
+    /// as it is; [`Self::admit`] moves it once the request is actually sent.
+    fn skip_reason(&self, index: usize) -> Option<anyhow::Error> {
+        let instance = &self.providers[index];
+        let provider_type = instance.provider.get_provider_type();
+
+        if let Some(circuit_breaker) = self.circuit_breakers.get(&instance.name) {
+            if !circuit_breaker.would_allow() {
+                let retry_after = circuit_breaker.retry_after().unwrap_or_default();
+                return Some(anyhow!(
+                    "{} provider skipped after repeated failures (retrying in {}s)",
+                    instance.name,
+                    retry_after.as_secs().max(1)
+                ));
+            }
+        }
+
+        if provider_type != ProviderType::Local {
+            if let Some((spent, budget)) = self
+                .usage_tracker
+                .as_ref()
+                .and_then(|tracker| tracker.budget_exceeded())
+            {
+                return Some(
+                    BudgetExceeded {
+                        provider_type,
+                        spent,
+                        budget,
+                    }
+                    .into(),
+                );
+            }
+        }
+
+        None
+    }
+
+    /// Let the breaker of an instance know a request is about to be sent to it, so an
+    /// open breaker past its recovery window turns half-open for the trial request
+    fn admit(&mut self, name: &str) {
+        if let Some(circuit_breaker) = self.circuit_breakers.get_mut(name) {
+            circuit_breaker.can_execute();
+        }
+    }
+
+    /// Order the instances of a provider type for a request. With several instances
+    /// the routing strategy decides the order, instances known to serve the requested
+    /// model go first and those known to lack it are left out, unless none has it.
+    async fn route(&mut self, provider_type: &ProviderType) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..self.providers.len())
+            .filter(|&i| self.providers[i].provider.get_provider_type() == *provider_type)
+            .collect();
+        if indices.len() < 2 {
+            return indices;
+        }
+
+        match self.routing {
+            RoutingStrategy::Priority => {}
+            RoutingStrategy::RoundRobin => {
+                let offset = self.round_robin_offset % indices.len();
+                self.round_robin_offset = self.round_robin_offset.wrapping_add(1);
+                indices.rotate_left(offset);
+            }
+            RoutingStrategy::LowestLatency => {
+                // Stable sort: untried instances (no latency yet) keep priority order
+                indices.sort_by_key(|&i| {
+                    self.performance_monitor
+                        .get_provider_latency(&self.providers[i].name)
+                        .unwrap_or(Duration::ZERO)
+                });
+            }
+        }
+
+        self.discover_models(&indices).await;
+
+        let serving: Vec<usize> = indices
+            .iter()
+            .copied()
+            .filter(|&i| self.providers[i].has_model() == Some(true))
+            .collect();
+        let unknown = indices
+            .iter()
+            .copied()
+            .filter(|&i| self.providers[i].has_model().is_none());
+        let candidates: Vec<usize> = serving.into_iter().chain(unknown).collect();
+
+        // Nobody has the model: let the endpoints report it (with a pull hint)
+        if candidates.is_empty() {
+            indices
+        } else {
+            candidates
+        }
+    }
+
+    /// Fetch the model lists of instances not checked yet, concurrently and with a short
+    /// timeout. Instances with an open circuit breaker are not contacted.
+    async fn discover_models(&mut self, indices: &[usize]) {
+        let pending: Vec<usize> = indices
+            .iter()
+            .copied()
+            .filter(|&i| {
+                !self.providers[i].models_checked
+                    && self
+                        .circuit_breakers
+                        .get(&self.providers[i].name)
+                        .and_then(|cb| cb.retry_after())
+                        .is_none()
+            })
+            .collect();
+        if pending.is_empty() {
+            return;
+        }
+
+        let lookups = pending.iter().map(|&i| {
+            tokio::time::timeout(
+                MODEL_DISCOVERY_TIMEOUT,
+                self.providers[i].provider.list_models(),
+            )
+        });
+        let results = futures::future::join_all(lookups).await;
+
+        for (index, result) in pending.into_iter().zip(results) {
+            let instance = &mut self.providers[index];
+            instance.models_checked = true;
+            instance.models = match result {
+                Ok(Ok(models)) => Some(models),
+                _ => None,
+            };
+        }
+    }
+
+    /// List models from ALL available providers (combining Local and Cloud)
+    pub async fn list_models(&self) -> Result<Vec<String>> {
         let mut all_models = Vec::new();
         let mut any_provider_available = false;
 
         // Iterate through all registered providers
-        for provider in &self.providers {
+        for provider in self.providers.iter().map(|i| &i.provider) {
             if provider.is_available().await {
                 any_provider_available = true;
                 match provider.list_models().await {
@@ -370,7 +1470,7 @@ impl ProviderManager {
 
     /// Check if any provider is available
     pub async fn is_any_provider_available(&self) -> bool {
-        for provider in &self.providers {
+        for provider in self.providers.iter().map(|i| &i.provider) {
             if provider.is_available().await {
                 return true;
             }
@@ -380,32 +1480,65 @@ impl ProviderManager {
 
     /// Get provider by type (returns first match)
     pub fn get_provider_by_type(&self, provider_type: &ProviderType) -> Option<&dyn AIProvider> {
+        self.get_providers_by_type(provider_type).into_iter().next()
+    }
+
+    /// All instances of a provider type, in registration order
+    pub fn get_providers_by_type(&self, provider_type: &ProviderType) -> Vec<&dyn AIProvider> {
         self.providers
             .iter()
-            .find(|p| p.get_provider_type() == *provider_type)
-            .map(|b| &**b as &dyn AIProvider)
+            .filter(|i| i.provider.get_provider_type() == *provider_type)
+            .map(|i| &*i.provider)
+            .collect()
     }
 
     /// Get provider status for debugging
     pub fn get_provider_status(&self) -> Vec<(String, ProviderType, CircuitBreakerState)> {
         let mut status = Vec::new();
-        for provider in &self.providers {
-            let provider_type = provider.get_provider_type();
+        for instance in &self.providers {
             let circuit_state = self
                 .circuit_breakers
-                .get(&provider_type)
+                .get(&instance.name)
                 .map(|cb| cb.get_state())
                 .unwrap_or(CircuitBreakerState::Closed);
 
             status.push((
-                provider.get_name().to_string(),
-                provider_type,
+                instance.label(),
+                instance.provider.get_provider_type(),
                 circuit_state,
             ));
         }
         status
     }
 
+    /// Persisted health history of the configured provider instances, by instance name
+    pub fn get_provider_health(&self) -> Vec<(String, ProviderType, BreakerRecord)> {
+        let records = self
+            .health_store
+            .as_ref()
+            .map(|store| store.load())
+            .unwrap_or_default();
+
+        self.providers
+            .iter()
+            .map(|instance| {
+                let record = records.get(&instance.name).cloned().unwrap_or_default();
+                (
+                    instance.name.clone(),
+                    instance.provider.get_provider_type(),
+                    record,
+                )
+            })
+            .collect()
+    }
+
+    /// Time left until an open breaker lets the provider instance be tried again
+    pub fn get_retry_after(&self, name: &str) -> Option<Duration> {
+        self.circuit_breakers
+            .get(name)
+            .and_then(|cb| cb.retry_after())
+    }
+
     /// Reset circuit breakers (for testing or manual recovery)
     pub fn reset_circuit_breakers(&mut self) {
         for circuit_breaker in self.circuit_breakers.values_mut() {
@@ -413,6 +1546,115 @@ impl ProviderManager {
             circuit_breaker.state = CircuitBreakerState::Closed;
             circuit_breaker.last_failure_time = None;
         }
+
+        if let Some(store) = &self.health_store {
+            let _ = store.update(|records| {
+                for record in records.values_mut() {
+                    record.failure_count = 0;
+                    record.opened_at = None;
+                }
+            });
+        }
+    }
+
+    /// Update the instance's circuit breaker and response-time average and persist the
+    /// outcome. The breaker is then synced with the merged record so failures seen by
+    /// concurrent invocations count as well.
+    fn record_provider_outcome(&mut self, name: &str, success: bool, latency: Option<Duration>) {
+        if let Some(latency) = latency {
+            self.performance_monitor
+                .record_provider_latency(name, latency);
+        }
+
+        let Some(circuit_breaker) = self.circuit_breakers.get_mut(name) else {
+            return;
+        };
+        if success {
+            circuit_breaker.record_success();
+        } else {
+            circuit_breaker.record_failure();
+        }
+
+        let Some(store) = &self.health_store else {
+            return;
+        };
+        let key = name.to_string();
+        let threshold = circuit_breaker.failure_threshold();
+        let now = ProviderHealthStore::now();
+
+        let result = store.update(|records| {
+            let record = records.entry(key.clone()).or_default();
+            if success {
+                record.record_success(now);
+            } else {
+                record.record_failure(now, threshold);
+            }
+            if let Some(latency) = latency {
+                record.record_latency(latency.as_millis() as u64);
+            }
+        });
+
+        match result {
+            Ok(records) => {
+                if let Some(record) = records.get(&key) {
+                    circuit_breaker.restore(record);
+                }
+            }
+            Err(e) => {
+                if let Ok(logger) = get_logger() {
+                    if let Ok(logger_guard) = logger.lock() {
+                        let _ = logger_guard.log_warning(
+                            LogCategory::Provider,
+                            format!("Failed to persist provider health: {}", e),
+                            None,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Account a successful response: session totals in the performance monitor, a
+    /// privacy-safe log entry (counts and cost only) and the usage ledger
+    fn record_usage(&mut self, agent: &AgentProfile, response: &ProviderResponse) {
+        let usage = response.usage.unwrap_or_default();
+        let provider = format!("{:?}", response.provider);
+        self.performance_monitor.record_token_usage(
+            &provider,
+            usage.prompt_tokens,
+            usage.completion_tokens,
+        );
+
+        let cost = match &self.usage_tracker {
+            Some(tracker) => match tracker.record(agent.name, response) {
+                Ok(cost) => cost,
+                Err(e) => {
+                    if let Ok(logger) = get_logger() {
+                        if let Ok(logger_guard) = logger.lock() {
+                            let _ = logger_guard.log_warning(
+                                LogCategory::Provider,
+                                format!("Failed to record token usage: {}", e),
+                                None,
+                            );
+                        }
+                    }
+                    None
+                }
+            },
+            None => None,
+        };
+
+        if let Ok(logger) = get_logger() {
+            if let Ok(logger_guard) = logger.lock() {
+                let _ = logger_guard.log_token_usage(
+                    &provider,
+                    &response.model,
+                    agent.name,
+                    response.usage.as_ref(),
+                    cost,
+                );
+            }
+        }
     }
 
     /// Switch provider preference at runtime
@@ -443,6 +1685,49 @@ impl ProviderManager {
     }
 }
 
+/// One progress line of an Ollama model download (`/api/pull`)
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct PullProgress {
+    pub status: String,
+    #[serde(default)]
+    pub digest: Option<String>,
+    /// Size of the layer being downloaded, in bytes
+    #[serde(default)]
+    pub total: Option<u64>,
+    /// Bytes of the layer downloaded so far
+    #[serde(default)]
+    pub completed: Option<u64>,
+}
+
+/// Details of an installed Ollama model (`/api/show`)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelDetails {
+    pub name: String,
+    pub family: Option<String>,
+    pub parameter_size: Option<String>,
+    pub quantization: Option<String>,
+    pub format: Option<String>,
+    pub context_length: Option<u64>,
+    /// Size on disk in bytes
+    pub size: Option<u64>,
+    /// Default generation parameters from the Modelfile, e.g. ("num_ctx", "4096")
+    pub parameters: Vec<(String, String)>,
+}
+
+/// A model currently loaded by Ollama (`/api/ps`)
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct LoadedModel {
+    pub name: String,
+    /// Memory used in bytes
+    #[serde(default)]
+    pub size: u64,
+    /// Part of `size` held in GPU memory
+    #[serde(default)]
+    pub size_vram: u64,
+    #[serde(default)]
+    pub expires_at: Option<String>,
+}
+
 /// Local Ollama provider implementation
 pub struct OllamaProvider {
     client: Client,
@@ -455,7 +1740,7 @@ pub struct OllamaProvider {
 impl OllamaProvider {
     /// Create a new Ollama provider instance
     pub fn new(base_url: String, model: String) -> Self {
-        let client = Client::builder()
+        let client = client_builder("ollama")
             .timeout(Duration::from_secs(120)) // Increased to 120 seconds
             .connect_timeout(Duration::from_secs(15)) // Increased connection timeout
             .build()
@@ -471,7 +1756,7 @@ impl OllamaProvider {
 
     /// Create with custom timeout
     pub fn with_timeout(base_url: String, model: String, timeout: Duration) -> Self {
-        let client = Client::builder()
+        let client = client_builder("ollama")
             .timeout(timeout)
             .connect_timeout(Duration::from_secs(10))
             .build()
@@ -494,22 +1779,56 @@ impl OllamaProvider {
     pub fn get_model(&self) -> &str {
         &self.model
     }
-}
 
-#[async_trait]
-impl AIProvider for OllamaProvider {
-    async fn generate_response(&self, prompt: &str, _agent: &AgentProfile) -> Result<String> {
-        let body = json!({
+    /// Ollama `options` object for an agent's generation settings
+    fn build_options(params: &GenerationParams) -> serde_json::Value {
+        let mut options = json!({
+            "temperature": params.temperature,
+            "num_predict": params.max_tokens
+        });
+        if let Some(top_p) = params.top_p {
+            options["top_p"] = json!(top_p);
+        }
+        if !params.stop.is_empty() {
+            options["stop"] = json!(params.stop);
+        }
+        if let Some(num_ctx) = params.num_ctx {
+            options["num_ctx"] = json!(num_ctx);
+        }
+        if let Some(seed) = params.seed {
+            options["seed"] = json!(seed);
+        }
+        options
+    }
+
+    /// Token counts of a finished /api/chat response. `prompt_eval_count` is omitted
+    /// when the whole prompt was served from Ollama's cache.
+    fn usage(json: &serde_json::Value) -> Option<TokenUsage> {
+        let completion_tokens = json["eval_count"].as_u64()?;
+        Some(TokenUsage {
+            prompt_tokens: json["prompt_eval_count"].as_u64().unwrap_or(0),
+            completion_tokens,
+        })
+    }
+
+    /// POST to /api/chat and map transport and HTTP errors to actionable messages
+    async fn send_chat(
+        &self,
+        conversation: &Conversation,
+        params: &GenerationParams,
+        stream: bool,
+    ) -> Result<reqwest::Response> {
+        let mut body = json!({
             "model": self.model,
-            "prompt": prompt,
-            "stream": false,
-            "options": {
-                "temperature": 0.3,
-                "num_predict": 512
-            }
+            "messages": conversation.to_chat_messages(),
+            "stream": stream,
+            "options": Self::build_options(params)
         });
+        if let Some(schema) = &conversation.response_schema {
+            body["format"] = schema.clone();
+        }
 
-        let url = format!("{}/api/generate", self.base_url);
+        let url = format!("{}/api/chat", self.base_url);
 
         let response = self.client
             .post(&url)
@@ -521,16 +1840,16 @@ impl AIProvider for OllamaProvider {
                 if e.is_timeout() {
                     anyhow!("Request to Ollama timed out after {:?}. The model may be slow to respond or overloaded. Try again or use a different model.", self.timeout)
                 } else if e.is_connect() {
-                    anyhow!("Failed to connect to Ollama at {}: {}. Please ensure Ollama is running with: ollama serve", self.base_url, e)
+                    anyhow!("Failed to connect to Ollama at {}: {}. Please ensure Ollama is running with: ollama serve", self.base_url, describe_request_error(&e))
                 } else {
-                    anyhow!("Failed to connect to Ollama: {}", e)
+                    anyhow!("Failed to connect to Ollama: {}", describe_request_error(&e))
                 }
             })?;
 
         if !response.status().is_success() {
             let status = response.status();
             let error_text = response.text().await.unwrap_or_default();
-            return Err(anyhow!("Ollama returned error: {} - {}. Please check if the model '{}' is available. Try: ollama pull {}",
+            return Err(anyhow!("Ollama returned error: {} - {}. Please check if the model '{}' is available. Try: cliai model pull {}",
                 status,
                 error_text,
                 self.model,
@@ -538,26 +1857,318 @@ impl AIProvider for OllamaProvider {
             ));
         }
 
+        Ok(response)
+    }
+
+    /// Map a transport error of a model-management request to an actionable message
+    fn request_error(&self, e: reqwest::Error) -> anyhow::Error {
+        if e.is_timeout() {
+            anyhow!("Request to Ollama timed out after {:?}", self.timeout)
+        } else if e.is_connect() {
+            anyhow!(
+                "Failed to connect to Ollama at {}: {}. Please ensure Ollama is running with: ollama serve",
+                self.base_url,
+                describe_request_error(&e)
+            )
+        } else {
+            anyhow!(
+                "Failed to connect to Ollama: {}",
+                describe_request_error(&e)
+            )
+        }
+    }
+
+    /// Error for a failed model-management response, using Ollama's `error` field
+    async fn response_error(response: reqwest::Response, model: &str) -> anyhow::Error {
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|json| json["error"].as_str().map(str::to_string))
+            .unwrap_or(body);
+
+        if status == reqwest::StatusCode::NOT_FOUND {
+            anyhow!(
+                "Model '{}' is not installed. Try: cliai model pull {}",
+                model,
+                model
+            )
+        } else {
+            anyhow!("Ollama returned error: {} - {}", status, message)
+        }
+    }
+
+    /// Whether `model` is among the models reported by Ollama. Ollama stores untagged
+    /// models as `<name>:latest`.
+    pub fn is_installed(models: &[String], model: &str) -> bool {
+        models
+            .iter()
+            .any(|m| m == model || (!model.contains(':') && *m == format!("{}:latest", model)))
+    }
+
+    /// Download a model, reporting each progress line. Downloads can take far longer
+    /// than a chat request, so the configured timeout limits the wait for each progress
+    /// update rather than the whole transfer.
+    pub async fn pull_model<F>(&self, model: &str, mut on_progress: F) -> Result<()>
+    where
+        F: FnMut(&PullProgress),
+    {
+        let client = client_builder("ollama")
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .unwrap_or_else(|_| Client::new());
+
+        let mut response = client
+            .post(format!("{}/api/pull", self.base_url))
+            .json(&json!({ "model": model, "stream": true }))
+            .send()
+            .await
+            .map_err(|e| self.request_error(e))?;
+
+        if !response.status().is_success() {
+            return Err(Self::response_error(response, model).await);
+        }
+
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut succeeded = false;
+        loop {
+            let chunk = tokio::time::timeout(self.timeout, response.chunk())
+                .await
+                .map_err(|_| {
+                    anyhow!(
+                        "Pulling '{}' stalled: no progress from Ollama for {:?}",
+                        model,
+                        self.timeout
+                    )
+                })?
+                .map_err(|e| anyhow!("Download of '{}' was interrupted: {}", model, e))?;
+
+            let finished = chunk.is_none();
+            match chunk {
+                Some(bytes) => buffer.extend_from_slice(&bytes),
+                None => buffer.push(b'\n'),
+            }
+
+            while let Some(pos) = buffer.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=pos).collect();
+                let line = String::from_utf8_lossy(&line);
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+
+                let json: serde_json::Value = serde_json::from_str(line)
+                    .map_err(|e| anyhow!("Failed to parse Ollama pull progress: {}", e))?;
+                if let Some(error) = json["error"].as_str() {
+                    return Err(anyhow!("Failed to pull '{}': {}", model, error));
+                }
+
+                let progress: PullProgress = serde_json::from_value(json)
+                    .map_err(|e| anyhow!("Failed to parse Ollama pull progress: {}", e))?;
+                succeeded |= progress.status == "success";
+                on_progress(&progress);
+            }
+
+            if finished {
+                break;
+            }
+        }
+
+        if succeeded {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Ollama ended the download of '{}' before it completed",
+                model
+            ))
+        }
+    }
+
+    /// Remove an installed model
+    pub async fn delete_model(&self, model: &str) -> Result<()> {
+        let response = self
+            .client
+            .delete(format!("{}/api/delete", self.base_url))
+            .json(&json!({ "model": model }))
+            .send()
+            .await
+            .map_err(|e| self.request_error(e))?;
+
+        if !response.status().is_success() {
+            return Err(Self::response_error(response, model).await);
+        }
+        Ok(())
+    }
+
+    /// Details of an installed model
+    pub async fn show_model(&self, model: &str) -> Result<ModelDetails> {
+        let response = self
+            .client
+            .post(format!("{}/api/show", self.base_url))
+            .json(&json!({ "model": model }))
+            .send()
+            .await
+            .map_err(|e| self.request_error(e))?;
+
+        if !response.status().is_success() {
+            return Err(Self::response_error(response, model).await);
+        }
+
         let json: serde_json::Value = response
             .json()
             .await
             .map_err(|e| anyhow!("Failed to parse Ollama response: {}", e))?;
+        let mut details = Self::parse_model_details(model, &json);
+
+        // /api/show has no size; take it from the installed model list
+        details.size = self.model_size(model).await;
+        Ok(details)
+    }
+
+    /// Model details from an /api/show response
+    fn parse_model_details(model: &str, json: &serde_json::Value) -> ModelDetails {
+        let text = |value: &serde_json::Value| {
+            value.as_str().filter(|s| !s.is_empty()).map(str::to_string)
+        };
+
+        // Context length is stored per architecture, e.g. "llama.context_length"
+        let context_length = json["model_info"].as_object().and_then(|info| {
+            info.iter()
+                .find(|(key, _)| key.ends_with(".context_length"))
+                .and_then(|(_, value)| value.as_u64())
+        });
 
-        let reply = json["response"]
+        let parameters = json["parameters"]
             .as_str()
-            .ok_or_else(|| anyhow!("No response field in Ollama response"))?;
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let (key, value) = line.trim().split_once(char::is_whitespace)?;
+                Some((key.to_string(), value.trim().to_string()))
+            })
+            .collect();
+
+        ModelDetails {
+            name: model.to_string(),
+            family: text(&json["details"]["family"]),
+            parameter_size: text(&json["details"]["parameter_size"]),
+            quantization: text(&json["details"]["quantization_level"]),
+            format: text(&json["details"]["format"]),
+            context_length,
+            size: None,
+            parameters,
+        }
+    }
 
-        Ok(reply.to_string())
+    /// Size on disk of an installed model, from /api/tags
+    async fn model_size(&self, model: &str) -> Option<u64> {
+        let json: serde_json::Value = self
+            .client
+            .get(format!("{}/api/tags", self.base_url))
+            .send()
+            .await
+            .ok()?
+            .json()
+            .await
+            .ok()?;
+
+        json["models"].as_array()?.iter().find_map(|entry| {
+            let name = entry["name"].as_str()?;
+            Self::is_installed(&[name.to_string()], model)
+                .then(|| entry["size"].as_u64())
+                .flatten()
+        })
     }
 
-    async fn list_models(&self) -> Result<Vec<String>> {
-        let url = format!("{}/api/tags", self.base_url);
+    /// Models currently loaded into memory
+    pub async fn running_models(&self) -> Result<Vec<LoadedModel>> {
         let response = self
             .client
-            .get(&url)
+            .get(format!("{}/api/ps", self.base_url))
             .send()
             .await
-            .map_err(|e| anyhow!("Failed to connect to Ollama: {}", e))?;
+            .map_err(|e| self.request_error(e))?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!("Ollama returned error: {}", response.status()));
+        }
+
+        let json: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Failed to parse Ollama response: {}", e))?;
+
+        Ok(match json.get("models") {
+            Some(models) if !models.is_null() => serde_json::from_value(models.clone())
+                .map_err(|e| anyhow!("Failed to parse Ollama response: {}", e))?,
+            _ => Vec::new(),
+        })
+    }
+}
+
+#[async_trait]
+impl AIProvider for OllamaProvider {
+    async fn generate_response(
+        &self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+    ) -> Result<ProviderResponse> {
+        let response = self.send_chat(conversation, &agent.params, false).await?;
+
+        let json: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Failed to parse Ollama response: {}", e))?;
+
+        let reply = json["message"]["content"]
+            .as_str()
+            .ok_or_else(|| anyhow!("No message content in Ollama response"))?;
+
+        Ok(ProviderResponse::new(
+            reply.to_string(),
+            Self::usage(&json),
+            &self.model,
+            ProviderType::Local,
+        ))
+    }
+
+    async fn generate_response_stream(
+        &self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+    ) -> Result<TokenStream> {
+        let response = self.send_chat(conversation, &agent.params, true).await?;
+
+        // Ollama streams newline-delimited JSON objects:
+        // {"message": {"role": "assistant", "content": "..."}, "done": false}
+        // The final object has `"done": true` and the token counts
+        Ok(lines_to_token_stream(response, |line| {
+            if line.trim().is_empty() {
+                return None;
+            }
+            let json: serde_json::Value = match serde_json::from_str(line) {
+                Ok(json) => json,
+                Err(e) => return Some(Err(anyhow!("Failed to parse Ollama stream: {}", e))),
+            };
+            if let Some(error) = json["error"].as_str() {
+                return Some(Err(anyhow!("Ollama returned error: {}", error)));
+            }
+            let mut chunk = StreamChunk::text(json["message"]["content"].as_str().unwrap_or(""));
+            if json["done"] == true {
+                chunk.usage = Self::usage(&json);
+            }
+            (!chunk.text.is_empty() || chunk.usage.is_some()).then_some(Ok(chunk))
+        }))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let url = format!("{}/api/tags", self.base_url);
+        let response = self.client.get(&url).send().await.map_err(|e| {
+            anyhow!(
+                "Failed to connect to Ollama: {}",
+                describe_request_error(&e)
+            )
+        })?;
 
         if !response.status().is_success() {
             return Err(anyhow!("Ollama returned error: {}", response.status()));
@@ -588,7 +2199,7 @@ impl AIProvider for OllamaProvider {
         let url = format!("{}/api/tags", self.base_url);
 
         // Use a shorter timeout for availability check
-        let test_client = reqwest::Client::builder()
+        let test_client = client_builder("ollama")
             .timeout(Duration::from_secs(5))
             .connect_timeout(Duration::from_secs(3))
             .build()
@@ -605,6 +2216,10 @@ impl AIProvider for OllamaProvider {
     fn get_name(&self) -> &'static str {
         "Ollama"
     }
+
+    fn get_model_name(&self) -> &str {
+        &self.model
+    }
 }
 
 /// Cloud provider implementation (Proxy to Backend)
@@ -612,28 +2227,48 @@ pub struct CloudProvider {
     client: Client,
     backend_url: String,
     api_token: String,
+    model: String,
 }
 
+#[allow(dead_code)]
 impl CloudProvider {
-    pub fn new(backend_url: String, api_token: String) -> Self {
+    pub fn new(backend_url: String, api_token: String, model: String) -> Self {
         Self {
-            client: Client::builder()
+            client: client_builder("cloud")
                 .timeout(Duration::from_secs(30))
                 .build()
                 .unwrap_or_else(|_| Client::new()),
             backend_url,
             api_token,
+            model,
         }
     }
+
+    /// Update the model being used
+    pub fn set_model(&mut self, model: String) {
+        self.model = model;
+    }
+
+    /// Get the current model
+    pub fn get_model(&self) -> &str {
+        &self.model
+    }
 }
 
 #[async_trait]
 impl AIProvider for CloudProvider {
-    async fn generate_response(&self, prompt: &str, agent: &AgentProfile) -> Result<String> {
+    async fn generate_response(
+        &self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+    ) -> Result<ProviderResponse> {
+        // The backend proxy takes a single flattened prompt
         let body = json!({
-            "prompt": prompt,
+            "prompt": conversation.to_prompt(),
             "agent": agent.name,
-            "model": "gpt-4o-mini" // Default for cloud
+            "model": self.model,
+            "temperature": agent.params.temperature,
+            "max_tokens": agent.params.max_tokens
         });
 
         let url = format!("{}/v1/ai/chat", self.backend_url);
@@ -645,7 +2280,7 @@ impl AIProvider for CloudProvider {
             .json(&body)
             .send()
             .await
-            .map_err(|e| anyhow!("Cloud proxy failed: {}", e))?;
+            .map_err(|e| anyhow!("Cloud proxy failed: {}", describe_request_error(&e)))?;
 
         if !response.status().is_success() {
             let error_json: serde_json::Value = response.json().await.unwrap_or_default();
@@ -661,236 +2296,3181 @@ impl AIProvider for CloudProvider {
             .or_else(|| json["response"].as_str())
             .ok_or_else(|| anyhow!("Unexpected response format from cloud"))?;
 
-        Ok(reply.to_string())
+        Ok(ProviderResponse::new(
+            reply.to_string(),
+            OpenAICompatibleProvider::usage(&json["usage"]),
+            self.get_model_name(),
+            ProviderType::Cloud,
+        ))
     }
 
     async fn list_models(&self) -> Result<Vec<String>> {
-        // For now, cloud models are fixed
-        Ok(vec!["gpt-4o-mini".to_string(), "gpt-4o".to_string()])
-    }
-
-    fn get_provider_type(&self) -> ProviderType {
-        ProviderType::Cloud
-    }
-
-    async fn is_available(&self) -> bool {
-        let url = format!("{}/health", self.backend_url);
-        self.client
+        let url = format!("{}/v1/ai/models", self.backend_url);
+        let response = self
+            .client
             .get(&url)
+            .header("Authorization", format!("Bearer {}", self.api_token))
             .send()
             .await
-            .map(|r| r.status().is_success())
-            .unwrap_or(false)
+            .map_err(|e| anyhow!("Cloud proxy failed: {}", describe_request_error(&e)))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_json: serde_json::Value = response.json().await.unwrap_or_default();
+            let msg = error_json["error"].as_str().unwrap_or("Unknown error");
+            return Err(anyhow!("Cloud model listing failed ({}): {}", status, msg));
+        }
+
+        let json: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Failed to parse cloud model list: {}", e))?;
+
+        // Accept both the backend's `models` list and an OpenAI-style `data` list
+        let models = json["models"]
+            .as_array()
+            .or_else(|| json["data"].as_array())
+            .ok_or_else(|| anyhow!("Unexpected model list format from cloud"))?
+            .iter()
+            .filter_map(|entry| {
+                entry
+                    .as_str()
+                    .or_else(|| entry["id"].as_str())
+                    .or_else(|| entry["name"].as_str())
+                    .map(str::to_string)
+            })
+            .collect();
+        Ok(models)
+    }
+
+    fn get_provider_type(&self) -> ProviderType {
+        ProviderType::Cloud
+    }
+
+    async fn is_available(&self) -> bool {
+        let url = format!("{}/health", self.backend_url);
+        self.client
+            .get(&url)
+            .send()
+            .await
+            .map(|r| r.status().is_success())
+            .unwrap_or(false)
+    }
+
+    fn get_name(&self) -> &'static str {
+        "OpenAI/Anthropic"
+    }
+
+    fn get_model_name(&self) -> &str {
+        &self.model
+    }
+}
+
+/// Direct provider for any server speaking the OpenAI chat completions API
+/// (api.openai.com, llama.cpp server, vLLM, LM Studio, ...)
+pub struct OpenAICompatibleProvider {
+    client: Client,
+    base_url: String,
+    api_key: Option<String>,
+    model: String,
+    timeout: Duration,
+}
+
+#[allow(dead_code)]
+impl OpenAICompatibleProvider {
+    /// Create a new provider. `base_url` includes the version prefix, e.g. `https://api.openai.com/v1`
+    pub fn new(base_url: String, api_key: Option<String>, model: String) -> Self {
+        Self::with_timeout(base_url, api_key, model, Duration::from_secs(60))
+    }
+
+    /// Create with custom timeout
+    pub fn with_timeout(
+        base_url: String,
+        api_key: Option<String>,
+        model: String,
+        timeout: Duration,
+    ) -> Self {
+        let client = client_builder("openai")
+            .timeout(timeout)
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .unwrap_or_else(|_| Client::new());
+
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+            timeout,
+        }
+    }
+
+    /// Update the model being used
+    pub fn set_model(&mut self, model: String) {
+        self.model = model;
+    }
+
+    /// Get the current model
+    pub fn get_model(&self) -> &str {
+        &self.model
+    }
+
+    /// Attach the bearer token if one is configured (local servers usually need none)
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    /// POST to /chat/completions and map transport and HTTP errors to actionable messages
+    async fn send_chat(
+        &self,
+        conversation: &Conversation,
+        params: &GenerationParams,
+        stream: bool,
+    ) -> Result<reqwest::Response> {
+        let mut body = json!({
+            "model": self.model,
+            "messages": conversation.to_chat_messages(),
+            "temperature": params.temperature,
+            "max_tokens": params.max_tokens,
+            "stream": stream
+        });
+        if let Some(top_p) = params.top_p {
+            body["top_p"] = json!(top_p);
+        }
+        if !params.stop.is_empty() {
+            body["stop"] = json!(params.stop);
+        }
+        if let Some(seed) = params.seed {
+            body["seed"] = json!(seed);
+        }
+        if let Some(schema) = &conversation.response_schema {
+            body["response_format"] = json!({
+                "type": "json_schema",
+                "json_schema": { "name": "response", "strict": true, "schema": schema }
+            });
+        }
+        if stream {
+            body["stream_options"] = json!({ "include_usage": true });
+        }
+
+        let url = format!("{}/chat/completions", self.base_url);
+
+        let response = self
+            .authorize(self.client.post(&url))
+            .json(&body)
+            .timeout(self.timeout)
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    anyhow!(
+                        "Request to {} timed out after {:?}",
+                        self.base_url,
+                        self.timeout
+                    )
+                } else if e.is_connect() {
+                    anyhow!(
+                        "Failed to connect to {}: {}",
+                        self.base_url,
+                        describe_request_error(&e)
+                    )
+                } else {
+                    anyhow!(
+                        "OpenAI-compatible request failed: {}",
+                        describe_request_error(&e)
+                    )
+                }
+            })?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(anyhow!(Self::error_message(status, &error_text)));
+        }
+
+        Ok(response)
+    }
+
+    /// Token counts from an OpenAI-style `usage` object
+    fn usage(usage: &serde_json::Value) -> Option<TokenUsage> {
+        Some(TokenUsage {
+            prompt_tokens: usage["prompt_tokens"].as_u64()?,
+            completion_tokens: usage["completion_tokens"].as_u64().unwrap_or(0),
+        })
+    }
+
+    /// Extract a readable message from an OpenAI-style error body
+    fn error_message(status: reqwest::StatusCode, body: &str) -> String {
+        let parsed: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+        let detail = parsed["error"]["message"]
+            .as_str()
+            .or_else(|| parsed["error"].as_str())
+            .unwrap_or(body)
+            .trim()
+            .to_string();
+
+        match status.as_u16() {
+            401 | 403 => format!(
+                "OpenAI-compatible API rejected the API key ({}): {}. Check it with: cliai test-key openai",
+                status, detail
+            ),
+            404 => format!(
+                "OpenAI-compatible API returned {}: {}. Check that the model exists and the base URL ends with /v1",
+                status, detail
+            ),
+            _ => format!("OpenAI-compatible API returned error: {} - {}", status, detail),
+        }
+    }
+}
+
+#[async_trait]
+impl AIProvider for OpenAICompatibleProvider {
+    async fn generate_response(
+        &self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+    ) -> Result<ProviderResponse> {
+        let response = self.send_chat(conversation, &agent.params, false).await?;
+
+        let json: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Failed to parse chat completion response: {}", e))?;
+
+        let reply = json["choices"][0]["message"]["content"]
+            .as_str()
+            .ok_or_else(|| anyhow!("No message content in chat completion response"))?;
+
+        Ok(ProviderResponse::new(
+            reply.to_string(),
+            Self::usage(&json["usage"]),
+            &self.model,
+            ProviderType::OpenAI,
+        ))
+    }
+
+    async fn generate_response_stream(
+        &self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+    ) -> Result<TokenStream> {
+        let response = self.send_chat(conversation, &agent.params, true).await?;
+
+        // Server-sent events: `data: {chunk}` lines terminated by `data: [DONE]`; the
+        // last chunk before it carries the usage. Some compatible servers ignore
+        // `stream` and answer with a plain completion.
+        Ok(lines_to_token_stream(response, |line| {
+            let Some(data) = line.strip_prefix("data:").map(str::trim) else {
+                let json: serde_json::Value = serde_json::from_str(line).ok()?;
+                return json["choices"][0]["message"]["content"]
+                    .as_str()
+                    .map(|text| {
+                        Ok(StreamChunk {
+                            text: text.to_string(),
+                            usage: Self::usage(&json["usage"]),
+                        })
+                    });
+            };
+            if data == "[DONE]" {
+                return None;
+            }
+            let json: serde_json::Value = match serde_json::from_str(data) {
+                Ok(json) => json,
+                Err(e) => {
+                    return Some(Err(anyhow!(
+                        "Failed to parse chat completion stream: {}",
+                        e
+                    )))
+                }
+            };
+            if let Some(message) = json["error"]["message"].as_str() {
+                return Some(Err(anyhow!(
+                    "OpenAI-compatible API stream error: {}",
+                    message
+                )));
+            }
+            let chunk = StreamChunk {
+                text: json["choices"][0]["delta"]["content"]
+                    .as_str()
+                    .unwrap_or("")
+                    .to_string(),
+                usage: Self::usage(&json["usage"]),
+            };
+            (!chunk.text.is_empty() || chunk.usage.is_some()).then_some(Ok(chunk))
+        }))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let url = format!("{}/models", self.base_url);
+        let response = self
+            .authorize(self.client.get(&url))
+            .send()
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to connect to {}: {}",
+                    self.base_url,
+                    describe_request_error(&e)
+                )
+            })?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(anyhow!(Self::error_message(status, &error_text)));
+        }
+
+        let json: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Failed to parse model list: {}", e))?;
+
+        let mut models = Vec::new();
+        if let Some(data) = json["data"].as_array() {
+            for model in data {
+                if let Some(id) = model["id"].as_str() {
+                    models.push(id.to_string());
+                }
+            }
+        }
+
+        Ok(models)
+    }
+
+    fn get_provider_type(&self) -> ProviderType {
+        ProviderType::OpenAI
+    }
+
+    async fn is_available(&self) -> bool {
+        let url = format!("{}/models", self.base_url);
+        self.authorize(self.client.get(&url))
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .map(|r| r.status().is_success())
+            .unwrap_or(false)
+    }
+
+    fn get_name(&self) -> &'static str {
+        "OpenAI"
+    }
+
+    fn get_model_name(&self) -> &str {
+        &self.model
+    }
+}
+
+/// Anthropic API version header sent with every Messages API request
+const ANTHROPIC_VERSION: &str = "2023-06-01";
+
+/// Tool the model is forced to call when a response schema is requested; its input is the reply
+const ANTHROPIC_RESPONSE_TOOL: &str = "respond";
+
+/// Direct provider for the Anthropic Messages API
+pub struct AnthropicProvider {
+    client: Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+    timeout: Duration,
+}
+
+#[allow(dead_code)]
+impl AnthropicProvider {
+    /// Create a new provider. `base_url` includes the version prefix, e.g. `https://api.anthropic.com/v1`
+    pub fn new(base_url: String, api_key: String, model: String) -> Self {
+        Self::with_timeout(base_url, api_key, model, Duration::from_secs(60))
+    }
+
+    /// Create with custom timeout
+    pub fn with_timeout(
+        base_url: String,
+        api_key: String,
+        model: String,
+        timeout: Duration,
+    ) -> Self {
+        let client = client_builder("anthropic")
+            .timeout(timeout)
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .unwrap_or_else(|_| Client::new());
+
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+            timeout,
+        }
+    }
+
+    /// Update the model being used
+    pub fn set_model(&mut self, model: String) {
+        self.model = model;
+    }
+
+    /// Get the current model
+    pub fn get_model(&self) -> &str {
+        &self.model
+    }
+
+    /// Build the Messages API request body. The system message goes into the
+    /// top-level `system` field; history and the user prompt become alternating turns.
+    /// Anthropic has no seed or context-size setting and caps temperature at 1.0.
+    fn build_request_body(
+        &self,
+        conversation: &Conversation,
+        params: &GenerationParams,
+    ) -> serde_json::Value {
+        let messages: Vec<serde_json::Value> = conversation
+            .messages()
+            .into_iter()
+            .map(|turn| json!({ "role": turn.role, "content": turn.content }))
+            .collect();
+
+        let mut body = json!({
+            "model": self.model,
+            "system": conversation.system,
+            "messages": messages,
+            "max_tokens": params.max_tokens,
+            "temperature": params.temperature.min(1.0)
+        });
+        if let Some(top_p) = params.top_p {
+            body["top_p"] = json!(top_p);
+        }
+        if !params.stop.is_empty() {
+            body["stop_sequences"] = json!(params.stop);
+        }
+        // Structured output goes through a forced tool call whose input follows the schema
+        if let Some(schema) = &conversation.response_schema {
+            body["tools"] = json!([{
+                "name": ANTHROPIC_RESPONSE_TOOL,
+                "description": "Return the response as structured JSON",
+                "input_schema": schema
+            }]);
+            body["tool_choice"] = json!({ "type": "tool", "name": ANTHROPIC_RESPONSE_TOOL });
+        }
+        body
+    }
+
+    /// Turn an Anthropic error body (`{"type":"error","error":{"type":..,"message":..}}`)
+    /// into an actionable message
+    fn error_message(status: impl std::fmt::Display, body: &str) -> String {
+        let parsed: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+        let error_type = parsed["error"]["type"].as_str().unwrap_or("unknown_error");
+        let detail = parsed["error"]["message"]
+            .as_str()
+            .unwrap_or(body)
+            .trim()
+            .to_string();
+
+        match error_type {
+            "authentication_error" | "permission_error" => format!(
+                "Anthropic API rejected the API key ({}): {}. Check it with: cliai test-key anthropic",
+                status, detail
+            ),
+            "not_found_error" => format!(
+                "Anthropic API returned {}: {}. Check that the configured model exists",
+                status, detail
+            ),
+            "rate_limit_error" => format!("Anthropic API rate limit exceeded ({}): {}", status, detail),
+            "overloaded_error" => format!("Anthropic API is overloaded ({}): {}", status, detail),
+            _ => format!("Anthropic API returned error: {} ({}) - {}", status, error_type, detail),
+        }
+    }
+
+    /// POST to /messages and map transport and HTTP errors to actionable messages
+    async fn send_messages(&self, body: &serde_json::Value) -> Result<reqwest::Response> {
+        let url = format!("{}/messages", self.base_url);
+
+        let response = self
+            .client
+            .post(&url)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .json(body)
+            .timeout(self.timeout)
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    anyhow!("Request to Anthropic timed out after {:?}", self.timeout)
+                } else if e.is_connect() {
+                    anyhow!(
+                        "Failed to connect to Anthropic at {}: {}",
+                        self.base_url,
+                        describe_request_error(&e)
+                    )
+                } else {
+                    anyhow!("Anthropic request failed: {}", describe_request_error(&e))
+                }
+            })?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(anyhow!(Self::error_message(status, &error_text)));
+        }
+
+        Ok(response)
+    }
+
+    /// Wrap a reply with the token counts of the Messages API `usage` object
+    fn respond(&self, text: String, usage: &serde_json::Value) -> ProviderResponse {
+        let usage = usage["input_tokens"]
+            .as_u64()
+            .map(|input_tokens| TokenUsage {
+                prompt_tokens: input_tokens,
+                completion_tokens: usage["output_tokens"].as_u64().unwrap_or(0),
+            });
+        ProviderResponse::new(text, usage, &self.model, ProviderType::Anthropic)
+    }
+}
+
+#[async_trait]
+impl AIProvider for AnthropicProvider {
+    async fn generate_response(
+        &self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+    ) -> Result<ProviderResponse> {
+        let body = self.build_request_body(conversation, &agent.params);
+        let response = self.send_messages(&body).await?;
+
+        let json: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Failed to parse Anthropic response: {}", e))?;
+
+        let content = json["content"]
+            .as_array()
+            .ok_or_else(|| anyhow!("No content in Anthropic response"))?;
+
+        // A structured reply arrives as the input of the forced tool call
+        if let Some(tool_use) = content.iter().find(|block| block["type"] == "tool_use") {
+            return Ok(self.respond(tool_use["input"].to_string(), &json["usage"]));
+        }
+
+        let reply: String = content
+            .iter()
+            .filter(|block| block["type"] == "text")
+            .filter_map(|block| block["text"].as_str())
+            .collect();
+
+        if reply.is_empty() {
+            return Err(anyhow!("Anthropic response contained no text"));
+        }
+
+        Ok(self.respond(reply, &json["usage"]))
+    }
+
+    async fn generate_response_stream(
+        &self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+    ) -> Result<TokenStream> {
+        let mut body = self.build_request_body(conversation, &agent.params);
+        body["stream"] = json!(true);
+        let response = self.send_messages(&body).await?;
+
+        // Server-sent events; text (or tool input JSON for structured replies) arrives in
+        // content_block_delta events, usage in
+        // message_start (input) and message_delta (output)
+        let mut input_tokens = 0;
+        Ok(lines_to_token_stream(response, move |line| {
+            let data = line.strip_prefix("data:")?.trim();
+            let json: serde_json::Value = match serde_json::from_str(data) {
+                Ok(json) => json,
+                Err(e) => return Some(Err(anyhow!("Failed to parse Anthropic stream: {}", e))),
+            };
+            match json["type"].as_str() {
+                Some("content_block_delta") => json["delta"]["text"]
+                    .as_str()
+                    .or_else(|| json["delta"]["partial_json"].as_str())
+                    .filter(|text| !text.is_empty())
+                    .map(|text| Ok(StreamChunk::text(text))),
+                Some("message_start") => {
+                    input_tokens = json["message"]["usage"]["input_tokens"]
+                        .as_u64()
+                        .unwrap_or(0);
+                    None
+                }
+                Some("message_delta") => Some(Ok(StreamChunk::usage(TokenUsage {
+                    prompt_tokens: input_tokens,
+                    completion_tokens: json["usage"]["output_tokens"].as_u64().unwrap_or(0),
+                }))),
+                Some("error") => Some(Err(anyhow!(Self::error_message("mid-stream", data)))),
+                _ => None,
+            }
+        }))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let url = format!("{}/models", self.base_url);
+        let response = self
+            .client
+            .get(&url)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .send()
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to connect to Anthropic: {}",
+                    describe_request_error(&e)
+                )
+            })?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(anyhow!(Self::error_message(status, &error_text)));
+        }
+
+        let json: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Failed to parse Anthropic model list: {}", e))?;
+
+        let mut models = Vec::new();
+        if let Some(data) = json["data"].as_array() {
+            for model in data {
+                if let Some(id) = model["id"].as_str() {
+                    models.push(id.to_string());
+                }
+            }
+        }
+
+        Ok(models)
+    }
+
+    fn get_provider_type(&self) -> ProviderType {
+        ProviderType::Anthropic
+    }
+
+    async fn is_available(&self) -> bool {
+        let url = format!("{}/models", self.base_url);
+        self.client
+            .get(&url)
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", ANTHROPIC_VERSION)
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .map(|r| r.status().is_success())
+            .unwrap_or(false)
+    }
+
+    fn get_name(&self) -> &'static str {
+        "Anthropic"
+    }
+
+    fn get_model_name(&self) -> &str {
+        &self.model
+    }
+}
+
+/// Direct provider for the Google Gemini API (generateContent)
+pub struct GeminiProvider {
+    client: Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+    timeout: Duration,
+}
+
+#[allow(dead_code)]
+impl GeminiProvider {
+    /// Create a new provider. `base_url` includes the version prefix, e.g.
+    /// `https://generativelanguage.googleapis.com/v1beta`
+    pub fn new(base_url: String, api_key: String, model: String) -> Self {
+        Self::with_timeout(base_url, api_key, model, Duration::from_secs(60))
+    }
+
+    /// Create with custom timeout
+    pub fn with_timeout(
+        base_url: String,
+        api_key: String,
+        model: String,
+        timeout: Duration,
+    ) -> Self {
+        let client = client_builder("gemini")
+            .timeout(timeout)
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .unwrap_or_else(|_| Client::new());
+
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+            timeout,
+        }
+    }
+
+    /// Update the model being used
+    pub fn set_model(&mut self, model: String) {
+        self.model = model;
+    }
+
+    /// Get the current model
+    pub fn get_model(&self) -> &str {
+        &self.model
+    }
+
+    /// Build the generateContent request body. The system message goes into
+    /// `systemInstruction` and assistant turns use Gemini's `model` role.
+    /// Gemini has no context-size setting.
+    fn build_request_body(
+        &self,
+        conversation: &Conversation,
+        params: &GenerationParams,
+    ) -> serde_json::Value {
+        let contents: Vec<serde_json::Value> = conversation
+            .messages()
+            .into_iter()
+            .map(|turn| {
+                let role = if turn.role == "assistant" {
+                    "model"
+                } else {
+                    "user"
+                };
+                json!({ "role": role, "parts": [{ "text": turn.content }] })
+            })
+            .collect();
+
+        let mut generation_config = json!({
+            "temperature": params.temperature,
+            "maxOutputTokens": params.max_tokens
+        });
+        if let Some(top_p) = params.top_p {
+            generation_config["topP"] = json!(top_p);
+        }
+        if !params.stop.is_empty() {
+            generation_config["stopSequences"] = json!(params.stop);
+        }
+        if let Some(seed) = params.seed {
+            generation_config["seed"] = json!(seed);
+        }
+        if let Some(schema) = &conversation.response_schema {
+            generation_config["responseMimeType"] = json!("application/json");
+            generation_config["responseJsonSchema"] = schema.clone();
+        }
+
+        let mut body = json!({
+            "contents": contents,
+            "generationConfig": generation_config
+        });
+        if !conversation.system.is_empty() {
+            body["systemInstruction"] = json!({ "parts": [{ "text": conversation.system }] });
+        }
+        body
+    }
+
+    /// Turn a Google API error body (`{"error":{"code":..,"message":..,"status":..}}`)
+    /// into an actionable message
+    fn error_message(status: impl std::fmt::Display, body: &str) -> String {
+        let parsed: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+        let error_status = parsed["error"]["status"].as_str().unwrap_or("UNKNOWN");
+        let detail = parsed["error"]["message"]
+            .as_str()
+            .unwrap_or(body)
+            .trim()
+            .to_string();
+
+        // Invalid keys are reported as INVALID_ARGUMENT rather than 401
+        if matches!(error_status, "UNAUTHENTICATED" | "PERMISSION_DENIED")
+            || detail.contains("API key")
+        {
+            return format!(
+                "Gemini API rejected the API key ({}): {}. Check it with: cliai test-key google",
+                status, detail
+            );
+        }
+
+        match error_status {
+            "NOT_FOUND" => format!(
+                "Gemini API returned {}: {}. Check that the configured model exists",
+                status, detail
+            ),
+            "RESOURCE_EXHAUSTED" => {
+                format!("Gemini API rate limit exceeded ({}): {}", status, detail)
+            }
+            _ => format!(
+                "Gemini API returned error: {} ({}) - {}",
+                status, error_status, detail
+            ),
+        }
+    }
+
+    /// POST to generateContent (or its SSE streaming variant) and map transport and
+    /// HTTP errors to actionable messages
+    async fn send_generate(
+        &self,
+        body: &serde_json::Value,
+        stream: bool,
+    ) -> Result<reqwest::Response> {
+        let url = if stream {
+            format!(
+                "{}/models/{}:streamGenerateContent?alt=sse",
+                self.base_url, self.model
+            )
+        } else {
+            format!("{}/models/{}:generateContent", self.base_url, self.model)
+        };
+
+        let response = self
+            .client
+            .post(&url)
+            .header("x-goog-api-key", &self.api_key)
+            .json(body)
+            .timeout(self.timeout)
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    anyhow!("Request to Gemini timed out after {:?}", self.timeout)
+                } else if e.is_connect() {
+                    anyhow!(
+                        "Failed to connect to Gemini at {}: {}",
+                        self.base_url,
+                        describe_request_error(&e)
+                    )
+                } else {
+                    anyhow!("Gemini request failed: {}", describe_request_error(&e))
+                }
+            })?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(anyhow!(Self::error_message(status, &error_text)));
+        }
+
+        Ok(response)
+    }
+
+    /// Concatenated text parts of the first candidate, if any
+    fn candidate_text(json: &serde_json::Value) -> Option<String> {
+        let parts = json["candidates"][0]["content"]["parts"].as_array()?;
+        let text: String = parts
+            .iter()
+            .filter_map(|part| part["text"].as_str())
+            .collect();
+        (!text.is_empty()).then_some(text)
+    }
+
+    /// Token counts from a `usageMetadata` object
+    fn usage(usage: &serde_json::Value) -> Option<TokenUsage> {
+        Some(TokenUsage {
+            prompt_tokens: usage["promptTokenCount"].as_u64()?,
+            completion_tokens: usage["candidatesTokenCount"].as_u64().unwrap_or(0),
+        })
+    }
+}
+
+#[async_trait]
+impl AIProvider for GeminiProvider {
+    async fn generate_response(
+        &self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+    ) -> Result<ProviderResponse> {
+        let body = self.build_request_body(conversation, &agent.params);
+        let response = self.send_generate(&body, false).await?;
+
+        let json: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Failed to parse Gemini response: {}", e))?;
+
+        if let Some(reason) = json["promptFeedback"]["blockReason"].as_str() {
+            return Err(anyhow!("Gemini blocked the request ({})", reason));
+        }
+
+        let reply = Self::candidate_text(&json).ok_or_else(|| {
+            anyhow!(
+                "Gemini response contained no text (finish reason: {})",
+                json["candidates"][0]["finishReason"]
+                    .as_str()
+                    .unwrap_or("unknown")
+            )
+        })?;
+
+        Ok(ProviderResponse::new(
+            reply,
+            Self::usage(&json["usageMetadata"]),
+            &self.model,
+            ProviderType::Gemini,
+        ))
+    }
+
+    async fn generate_response_stream(
+        &self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+    ) -> Result<TokenStream> {
+        let body = self.build_request_body(conversation, &agent.params);
+        let response = self.send_generate(&body, true).await?;
+
+        // Server-sent events; every `data:` line is a partial GenerateContentResponse and
+        // the one carrying the finish reason also has the final usage
+        Ok(lines_to_token_stream(response, move |line| {
+            let data = line.strip_prefix("data:")?.trim();
+            let json: serde_json::Value = match serde_json::from_str(data) {
+                Ok(json) => json,
+                Err(e) => return Some(Err(anyhow!("Failed to parse Gemini stream: {}", e))),
+            };
+            if json.get("error").is_some() {
+                return Some(Err(anyhow!(Self::error_message("mid-stream", data))));
+            }
+            if let Some(reason) = json["promptFeedback"]["blockReason"].as_str() {
+                return Some(Err(anyhow!("Gemini blocked the request ({})", reason)));
+            }
+            let mut chunk = StreamChunk::text(Self::candidate_text(&json).unwrap_or_default());
+            if json["candidates"][0]["finishReason"].is_string() {
+                chunk.usage = Self::usage(&json["usageMetadata"]);
+            }
+            (!chunk.text.is_empty() || chunk.usage.is_some()).then_some(Ok(chunk))
+        }))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let url = format!("{}/models?pageSize=1000", self.base_url);
+        let response = self
+            .client
+            .get(&url)
+            .header("x-goog-api-key", &self.api_key)
+            .send()
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to connect to Gemini: {}",
+                    describe_request_error(&e)
+                )
+            })?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(anyhow!(Self::error_message(status, &error_text)));
+        }
+
+        let json: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Failed to parse Gemini model list: {}", e))?;
+
+        // Only models that can chat; names come as `models/<id>`
+        let mut models = Vec::new();
+        if let Some(list) = json["models"].as_array() {
+            for model in list {
+                let can_generate = model["supportedGenerationMethods"]
+                    .as_array()
+                    .is_some_and(|methods| methods.iter().any(|m| m == "generateContent"));
+                if let Some(name) = model["name"].as_str().filter(|_| can_generate) {
+                    models.push(name.trim_start_matches("models/").to_string());
+                }
+            }
+        }
+
+        Ok(models)
+    }
+
+    fn get_provider_type(&self) -> ProviderType {
+        ProviderType::Gemini
+    }
+
+    async fn is_available(&self) -> bool {
+        let url = format!("{}/models?pageSize=1", self.base_url);
+        self.client
+            .get(&url)
+            .header("x-goog-api-key", &self.api_key)
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .map(|r| r.status().is_success())
+            .unwrap_or(false)
+    }
+
+    fn get_name(&self) -> &'static str {
+        "Gemini"
+    }
+
+    fn get_model_name(&self) -> &str {
+        &self.model
+    }
+}
+
+/// Direct provider for the Cohere Chat API (v2)
+pub struct CohereProvider {
+    client: Client,
+    base_url: String,
+    api_key: String,
+    model: String,
+    timeout: Duration,
+}
+
+#[allow(dead_code)]
+impl CohereProvider {
+    /// Create a new provider. `base_url` has no version prefix (`https://api.cohere.com`)
+    /// because chat lives under /v2 and the model list under /v1.
+    pub fn new(base_url: String, api_key: String, model: String) -> Self {
+        Self::with_timeout(base_url, api_key, model, Duration::from_secs(60))
+    }
+
+    /// Create with custom timeout
+    pub fn with_timeout(
+        base_url: String,
+        api_key: String,
+        model: String,
+        timeout: Duration,
+    ) -> Self {
+        let client = client_builder("cohere")
+            .timeout(timeout)
+            .connect_timeout(Duration::from_secs(10))
+            .build()
+            .unwrap_or_else(|_| Client::new());
+
+        Self {
+            client,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+            timeout,
+        }
+    }
+
+    /// Update the model being used
+    pub fn set_model(&mut self, model: String) {
+        self.model = model;
+    }
+
+    /// Get the current model
+    pub fn get_model(&self) -> &str {
+        &self.model
+    }
+
+    /// Build the chat request body. Cohere caps temperature at 1.0, accepts top-p only
+    /// within 0.01..=0.99 and has no context-size setting.
+    fn build_request_body(
+        &self,
+        conversation: &Conversation,
+        params: &GenerationParams,
+        stream: bool,
+    ) -> serde_json::Value {
+        let mut body = json!({
+            "model": self.model,
+            "messages": conversation.to_chat_messages(),
+            "temperature": params.temperature.min(1.0),
+            "max_tokens": params.max_tokens,
+            "stream": stream
+        });
+        if let Some(top_p) = params.top_p {
+            body["p"] = json!(top_p.clamp(0.01, 0.99));
+        }
+        if !params.stop.is_empty() {
+            body["stop_sequences"] = json!(params.stop);
+        }
+        if let Some(seed) = params.seed {
+            body["seed"] = json!(seed);
+        }
+        if let Some(schema) = &conversation.response_schema {
+            body["response_format"] = json!({ "type": "json_object", "json_schema": schema });
+        }
+        body
+    }
+
+    /// Turn a Cohere error body (`{"message": ..}`) into an actionable message
+    fn error_message(status: reqwest::StatusCode, body: &str) -> String {
+        let parsed: serde_json::Value = serde_json::from_str(body).unwrap_or_default();
+        let detail = parsed["message"]
+            .as_str()
+            .unwrap_or(body)
+            .trim()
+            .to_string();
+
+        match status.as_u16() {
+            401 | 403 => format!(
+                "Cohere API rejected the API key ({}): {}. Check it with: cliai test-key cohere",
+                status, detail
+            ),
+            404 => format!(
+                "Cohere API returned {}: {}. Check that the configured model exists",
+                status, detail
+            ),
+            429 => format!("Cohere API rate limit exceeded ({}): {}", status, detail),
+            _ => format!("Cohere API returned error: {} - {}", status, detail),
+        }
+    }
+
+    /// POST to /v2/chat and map transport and HTTP errors to actionable messages
+    async fn send_chat(&self, body: &serde_json::Value) -> Result<reqwest::Response> {
+        let url = format!("{}/v2/chat", self.base_url);
+
+        let response = self
+            .client
+            .post(&url)
+            .bearer_auth(&self.api_key)
+            .json(body)
+            .timeout(self.timeout)
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    anyhow!("Request to Cohere timed out after {:?}", self.timeout)
+                } else if e.is_connect() {
+                    anyhow!(
+                        "Failed to connect to Cohere at {}: {}",
+                        self.base_url,
+                        describe_request_error(&e)
+                    )
+                } else {
+                    anyhow!("Cohere request failed: {}", describe_request_error(&e))
+                }
+            })?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(anyhow!(Self::error_message(status, &error_text)));
+        }
+
+        Ok(response)
+    }
+
+    /// Billed token counts of a chat call
+    fn usage(usage: &serde_json::Value) -> Option<TokenUsage> {
+        // Billed units are JSON numbers that may carry a fraction (e.g. 12.0)
+        let billed = &usage["billed_units"];
+        Some(TokenUsage {
+            prompt_tokens: billed["input_tokens"].as_f64()? as u64,
+            completion_tokens: billed["output_tokens"].as_f64().unwrap_or(0.0) as u64,
+        })
+    }
+}
+
+#[async_trait]
+impl AIProvider for CohereProvider {
+    async fn generate_response(
+        &self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+    ) -> Result<ProviderResponse> {
+        let body = self.build_request_body(conversation, &agent.params, false);
+        let response = self.send_chat(&body).await?;
+
+        let json: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Failed to parse Cohere response: {}", e))?;
+
+        let reply: String = json["message"]["content"]
+            .as_array()
+            .ok_or_else(|| anyhow!("No message content in Cohere response"))?
+            .iter()
+            .filter(|block| block["type"] == "text")
+            .filter_map(|block| block["text"].as_str())
+            .collect();
+
+        if reply.is_empty() {
+            return Err(anyhow!("Cohere response contained no text"));
+        }
+
+        Ok(ProviderResponse::new(
+            reply,
+            Self::usage(&json["usage"]),
+            &self.model,
+            ProviderType::Cohere,
+        ))
+    }
+
+    async fn generate_response_stream(
+        &self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+    ) -> Result<TokenStream> {
+        let body = self.build_request_body(conversation, &agent.params, true);
+        let response = self.send_chat(&body).await?;
+
+        // Server-sent events; text arrives in content-delta events and the usage in
+        // the closing message-end event
+        Ok(lines_to_token_stream(response, move |line| {
+            let data = line.strip_prefix("data:")?.trim();
+            let json: serde_json::Value = match serde_json::from_str(data) {
+                Ok(json) => json,
+                Err(e) => return Some(Err(anyhow!("Failed to parse Cohere stream: {}", e))),
+            };
+            match json["type"].as_str() {
+                Some("content-delta") => json["delta"]["message"]["content"]["text"]
+                    .as_str()
+                    .filter(|text| !text.is_empty())
+                    .map(|text| Ok(StreamChunk::text(text))),
+                Some("message-end") => match json["delta"]["finish_reason"].as_str() {
+                    Some("ERROR") => Some(Err(anyhow!("Cohere stream ended with an error"))),
+                    _ => Self::usage(&json["delta"]["usage"])
+                        .map(|usage| Ok(StreamChunk::usage(usage))),
+                },
+                _ => None,
+            }
+        }))
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        let url = format!("{}/v1/models?endpoint=chat&page_size=1000", self.base_url);
+        let response = self
+            .client
+            .get(&url)
+            .bearer_auth(&self.api_key)
+            .send()
+            .await
+            .map_err(|e| {
+                anyhow!(
+                    "Failed to connect to Cohere: {}",
+                    describe_request_error(&e)
+                )
+            })?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            return Err(anyhow!(Self::error_message(status, &error_text)));
+        }
+
+        let json: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| anyhow!("Failed to parse Cohere model list: {}", e))?;
+
+        let mut models = Vec::new();
+        if let Some(list) = json["models"].as_array() {
+            for model in list {
+                if let Some(name) = model["name"].as_str() {
+                    models.push(name.to_string());
+                }
+            }
+        }
+
+        Ok(models)
+    }
+
+    fn get_provider_type(&self) -> ProviderType {
+        ProviderType::Cohere
+    }
+
+    async fn is_available(&self) -> bool {
+        let url = format!("{}/v1/models?page_size=1", self.base_url);
+        self.client
+            .get(&url)
+            .bearer_auth(&self.api_key)
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await
+            .map(|r| r.status().is_success())
+            .unwrap_or(false)
+    }
+
+    fn get_name(&self) -> &'static str {
+        "Cohere"
+    }
+
+    fn get_model_name(&self) -> &str {
+        &self.model
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_provider_type_equality() {
+        assert_eq!(ProviderType::Local, ProviderType::Local);
+        assert_eq!(ProviderType::Cloud, ProviderType::Cloud);
+        assert_ne!(ProviderType::Local, ProviderType::Cloud);
+    }
+
+    #[test]
+    fn test_circuit_breaker_creation() {
+        let cb = CircuitBreaker::new(3, Duration::from_secs(60));
+        assert_eq!(cb.get_state(), CircuitBreakerState::Closed);
+        assert_eq!(cb.failure_count, 0);
+    }
+
+    #[test]
+    fn test_circuit_breaker_can_execute() {
+        let mut cb = CircuitBreaker::new(3, Duration::from_secs(60));
+
+        // Initially should allow execution
+        assert!(cb.can_execute());
+
+        // After failures below threshold, should still allow
+        cb.record_failure();
+        cb.record_failure();
+        assert!(cb.can_execute());
+
+        // After reaching threshold, should open
+        cb.record_failure();
+        assert_eq!(cb.get_state(), CircuitBreakerState::Open);
+        assert!(!cb.can_execute());
+    }
+
+    #[test]
+    fn test_circuit_breaker_would_allow_is_read_only() {
+        let mut cb = CircuitBreaker::new(1, Duration::ZERO);
+        assert!(cb.would_allow());
+
+        // The recovery window has passed: allowed, but still open until a request is sent
+        cb.record_failure();
+        assert!(cb.would_allow());
+        assert_eq!(cb.get_state(), CircuitBreakerState::Open);
+        assert!(cb.can_execute());
+        assert_eq!(cb.get_state(), CircuitBreakerState::HalfOpen);
+
+        let mut cb = CircuitBreaker::new(1, Duration::from_secs(60));
+        cb.record_failure();
+        assert!(!cb.would_allow());
+        assert_eq!(cb.get_state(), CircuitBreakerState::Open);
+    }
+
+    #[test]
+    fn test_circuit_breaker_success_reset() {
+        let mut cb = CircuitBreaker::new(3, Duration::from_secs(60));
+
+        // Record some failures
+        cb.record_failure();
+        cb.record_failure();
+        assert_eq!(cb.failure_count, 2);
+
+        // Success should reset
+        cb.record_success();
+        assert_eq!(cb.failure_count, 0);
+        assert_eq!(cb.get_state(), CircuitBreakerState::Closed);
+    }
+
+    #[test]
+    fn test_circuit_breaker_restore() {
+        let now = ProviderHealthStore::now();
+        let mut record = BreakerRecord::default();
+        for _ in 0..3 {
+            record.record_failure(now, 3);
+        }
+
+        // Opened moments ago by another invocation: still inside the recovery window
+        let mut cb = CircuitBreaker::new(3, Duration::from_secs(60));
+        cb.restore(&record);
+        assert_eq!(cb.get_state(), CircuitBreakerState::Open);
+        assert!(!cb.can_execute());
+        assert!(cb.retry_after().is_some());
+
+        // Recovery window already elapsed: a trial request is allowed
+        record.last_failure = Some(now - 120);
+        let mut cb = CircuitBreaker::new(3, Duration::from_secs(60));
+        cb.restore(&record);
+        assert!(cb.can_execute());
+        assert_eq!(cb.get_state(), CircuitBreakerState::HalfOpen);
+
+        // A later success closes it again
+        record.record_success(now);
+        cb.restore(&record);
+        assert_eq!(cb.get_state(), CircuitBreakerState::Closed);
+    }
+
+    #[test]
+    fn test_provider_manager_creation() {
+        let manager = ProviderManager::new();
+
+        assert_eq!(
+            manager.fallback_chain,
+            vec![ProviderType::Local, ProviderType::Cloud]
+        );
+        assert_eq!(manager.retry_limits.get(&ProviderType::Cloud), Some(&1));
+        assert_eq!(manager.retry_limits.get(&ProviderType::Local), Some(&2));
+        assert!(manager.circuit_breakers.contains_key("Cloud"));
+        assert!(manager.circuit_breakers.contains_key("Local"));
+    }
+
+    #[test]
+    fn test_provider_manager_set_fallback_chain() {
+        let mut manager = ProviderManager::new();
+
+        manager.set_fallback_chain(vec![ProviderType::Local, ProviderType::Cloud]);
+        assert_eq!(
+            manager.fallback_chain,
+            vec![ProviderType::Local, ProviderType::Cloud]
+        );
+    }
+
+    #[test]
+    fn test_provider_manager_set_retry_limit() {
+        let mut manager = ProviderManager::new();
+
+        manager.set_retry_limit(ProviderType::Cloud, 5);
+        assert_eq!(manager.retry_limits.get(&ProviderType::Cloud), Some(&5));
+    }
+
+    #[test]
+    fn test_provider_manager_switch_preference() {
+        let mut manager = ProviderManager::new();
+
+        // Initially Local first
+        assert_eq!(manager.fallback_chain[0], ProviderType::Local);
+
+        // Switch to Cloud first
+        manager.switch_provider_preference(ProviderType::Cloud);
+        assert_eq!(manager.fallback_chain[0], ProviderType::Cloud);
+        assert_eq!(manager.fallback_chain[1], ProviderType::Local);
+    }
+
+    #[test]
+    fn test_provider_manager_reset_circuit_breakers() {
+        let mut manager = ProviderManager::new();
+
+        // Trigger some failures
+        if let Some(cb) = manager.circuit_breakers.get_mut("Cloud") {
+            cb.record_failure();
+            cb.record_failure();
+            cb.record_failure();
+            assert_eq!(cb.get_state(), CircuitBreakerState::Open);
+        }
+
+        // Reset should clear failures
+        manager.reset_circuit_breakers();
+        if let Some(cb) = manager.circuit_breakers.get("Cloud") {
+            assert_eq!(cb.get_state(), CircuitBreakerState::Closed);
+            assert_eq!(cb.failure_count, 0);
+        }
+    }
+
+    #[test]
+    fn test_ollama_provider_creation() {
+        let provider =
+            OllamaProvider::new("http://localhost:11434".to_string(), "mistral".to_string());
+
+        assert_eq!(provider.get_provider_type(), ProviderType::Local);
+        assert_eq!(provider.get_name(), "Ollama");
+        assert_eq!(provider.get_model(), "mistral");
+    }
+
+    #[test]
+    fn test_ollama_provider_with_timeout() {
+        let provider = OllamaProvider::with_timeout(
+            "http://localhost:11434".to_string(),
+            "mistral".to_string(),
+            Duration::from_secs(60),
+        );
+
+        assert_eq!(provider.timeout, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_ollama_provider_set_model() {
+        let mut provider =
+            OllamaProvider::new("http://localhost:11434".to_string(), "mistral".to_string());
+
+        provider.set_model("llama2".to_string());
+        assert_eq!(provider.get_model(), "llama2");
+    }
+
+    #[test]
+    fn test_cloud_provider_creation() {
+        let provider = CloudProvider::new(
+            "https://api.openai.com/v1/chat/completions".to_string(),
+            "test-key".to_string(),
+            "gpt-4o-mini".to_string(),
+        );
+
+        assert_eq!(provider.get_provider_type(), ProviderType::Cloud);
+        assert_eq!(provider.get_name(), "OpenAI/Anthropic");
+    }
+
+    #[test]
+    fn test_cloud_provider_set_model() {
+        let mut provider = CloudProvider::new(
+            "https://api.openai.com/v1/chat/completions".to_string(),
+            "test-key".to_string(),
+            "gpt-4o-mini".to_string(),
+        );
+
+        provider.set_model("gpt-4o".to_string());
+        assert_eq!(provider.get_model(), "gpt-4o");
+        assert_eq!(provider.get_model_name(), "gpt-4o");
+    }
+
+    #[tokio::test]
+    async fn test_cloud_provider_list_models() {
+        let (url, mut requests) = spawn_stub_server(vec![
+            (
+                200,
+                r#"{"models":["gpt-4o","claude-3-5-sonnet"]}"#.to_string(),
+            ),
+            (200, r#"{"data":[{"id":"gpt-4o-mini"}]}"#.to_string()),
+        ])
+        .await;
+
+        let provider = CloudProvider::new(url, "test-key".to_string(), "gpt-4o".to_string());
+        assert_eq!(
+            provider.list_models().await.unwrap(),
+            vec!["gpt-4o".to_string(), "claude-3-5-sonnet".to_string()]
+        );
+        let request = requests.recv().await.unwrap();
+        assert!(request.starts_with("GET /v1/ai/models"));
+        assert!(request
+            .to_lowercase()
+            .contains("authorization: bearer test-key"));
+
+        assert_eq!(
+            provider.list_models().await.unwrap(),
+            vec!["gpt-4o-mini".to_string()]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cloud_provider_sends_configured_model() {
+        let (url, mut requests) =
+            spawn_stub_server(vec![(200, r#"{"response":"Command: uptime"}"#.to_string())]).await;
+
+        let provider = CloudProvider::new(url, "test-key".to_string(), "gpt-4o".to_string());
+        let reply = provider
+            .generate_response(
+                &Conversation::new("", "how long has this machine been up"),
+                &crate::agents::profiles::SHELL_EXPERT,
+            )
+            .await
+            .unwrap();
+        assert_eq!(reply.text, "Command: uptime");
+        assert_eq!(reply.model, "gpt-4o");
+        assert!(requests
+            .recv()
+            .await
+            .unwrap()
+            .contains(r#""model":"gpt-4o""#));
+    }
+
+    #[tokio::test]
+    async fn test_provider_manager_empty_list_models() {
+        let manager = ProviderManager::new();
+
+        // Should fail when no providers are added
+        let result = manager.list_models().await;
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("No providers available"));
+    }
+
+    #[tokio::test]
+    async fn test_provider_manager_empty_is_available() {
+        let manager = ProviderManager::new();
+
+        // Should return false when no providers are added
+        assert!(!manager.is_any_provider_available().await);
+    }
+
+    /// Minimal local HTTP server for provider tests. Each connection is answered with
+    /// the next `(status, body)` pair (the last one repeats); raw requests are sent back
+    /// over the channel so tests can assert on paths, headers and payloads.
+    async fn spawn_stub_server(
+        responses: Vec<(u16, String)>,
+    ) -> (String, tokio::sync::mpsc::UnboundedReceiver<String>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let mut index = 0;
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut raw = Vec::new();
+                let mut buf = [0u8; 4096];
+                loop {
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    if n == 0 {
+                        break;
+                    }
+                    raw.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&raw).to_string();
+                    if let Some(header_end) = text.find("\r\n\r\n") {
+                        let content_length = text[..header_end]
+                            .lines()
+                            .find_map(|l| {
+                                let lower = l.to_lowercase();
+                                lower
+                                    .strip_prefix("content-length:")
+                                    .map(|v| v.trim().parse::<usize>().unwrap_or(0))
+                            })
+                            .unwrap_or(0);
+                        if raw.len() >= header_end + 4 + content_length {
+                            break;
+                        }
+                    }
+                }
+                let _ = tx.send(String::from_utf8_lossy(&raw).to_string());
+
+                let (status, body) = responses[index.min(responses.len() - 1)].clone();
+                index += 1;
+                let reply = format!(
+                    "HTTP/1.1 {} Stub\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(reply.as_bytes()).await;
+                let _ = socket.shutdown().await;
+            }
+        });
+
+        (format!("http://{}", addr), rx)
+    }
+
+    #[test]
+    fn test_openai_provider_creation() {
+        let provider = OpenAICompatibleProvider::new(
+            "http://localhost:8080/v1/".to_string(),
+            None,
+            "llama-3".to_string(),
+        );
+
+        assert_eq!(provider.get_provider_type(), ProviderType::OpenAI);
+        assert_eq!(provider.get_name(), "OpenAI");
+        assert_eq!(provider.get_model(), "llama-3");
+        assert_eq!(provider.base_url, "http://localhost:8080/v1");
+    }
+
+    #[tokio::test]
+    async fn test_openai_provider_chat_completion() {
+        let (url, mut requests) = spawn_stub_server(vec![(
+            200,
+            r#"{"choices":[{"message":{"role":"assistant","content":"Command: free -h"}}],"usage":{"prompt_tokens":21,"completion_tokens":4,"total_tokens":25}}"#
+                .to_string(),
+        )])
+        .await;
+
+        let provider = OpenAICompatibleProvider::new(
+            format!("{}/v1", url),
+            Some("sk-test".to_string()),
+            "gpt-4o-mini".to_string(),
+        );
+        let reply = provider
+            .generate_response(
+                &Conversation::new("", "show free RAM"),
+                &crate::agents::profiles::SHELL_EXPERT,
+            )
+            .await
+            .unwrap();
+        assert_eq!(reply.text, "Command: free -h");
+        assert_eq!(
+            reply.usage,
+            Some(TokenUsage {
+                prompt_tokens: 21,
+                completion_tokens: 4
+            })
+        );
+        assert_eq!(reply.model, "gpt-4o-mini");
+        assert_eq!(reply.provider, ProviderType::OpenAI);
+
+        let request = requests.recv().await.unwrap();
+        assert!(request.starts_with("POST /v1/chat/completions"));
+        assert!(request
+            .to_lowercase()
+            .contains("authorization: bearer sk-test"));
+        assert!(request.contains(r#""model":"gpt-4o-mini""#));
+        assert!(request.contains("show free RAM"));
+    }
+
+    #[tokio::test]
+    async fn test_openai_provider_without_key_sends_no_auth() {
+        let (url, mut requests) = spawn_stub_server(vec![(
+            200,
+            r#"{"choices":[{"message":{"content":"ok"}}]}"#.to_string(),
+        )])
+        .await;
+
+        let provider = OpenAICompatibleProvider::new(url, None, "local".to_string());
+        provider
+            .generate_response(
+                &Conversation::new("", "hi"),
+                &crate::agents::profiles::GENERAL_CLIAI,
+            )
+            .await
+            .unwrap();
+
+        let request = requests.recv().await.unwrap();
+        assert!(!request.to_lowercase().contains("authorization:"));
+    }
+
+    #[tokio::test]
+    async fn test_openai_provider_error_body() {
+        let (url, _requests) = spawn_stub_server(vec![(
+            401,
+            r#"{"error":{"message":"Incorrect API key provided","type":"invalid_request_error"}}"#
+                .to_string(),
+        )])
+        .await;
+
+        let provider =
+            OpenAICompatibleProvider::new(url, Some("bad".to_string()), "gpt-4o".to_string());
+        let err = provider
+            .generate_response(
+                &Conversation::new("", "hi"),
+                &crate::agents::profiles::GENERAL_CLIAI,
+            )
+            .await
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("Incorrect API key provided"));
+        assert!(err.contains("401"));
+    }
+
+    #[tokio::test]
+    async fn test_openai_provider_list_models() {
+        let (url, mut requests) = spawn_stub_server(vec![(
+            200,
+            r#"{"object":"list","data":[{"id":"gpt-4o"},{"id":"gpt-4o-mini"}]}"#.to_string(),
+        )])
+        .await;
+
+        let provider = OpenAICompatibleProvider::new(url, None, "gpt-4o".to_string());
+        let models = provider.list_models().await.unwrap();
+        assert_eq!(
+            models,
+            vec!["gpt-4o".to_string(), "gpt-4o-mini".to_string()]
+        );
+        assert!(requests.recv().await.unwrap().starts_with("GET /models"));
+    }
+
+    #[tokio::test]
+    async fn test_provider_manager_falls_back_to_openai() {
+        let (url, _requests) = spawn_stub_server(vec![(
+            200,
+            r#"{"choices":[{"message":{"content":"from openai"}}]}"#.to_string(),
+        )])
+        .await;
+
+        let mut manager = ProviderManager::new();
+        // Nothing listens on port 9, so the local provider fails fast
+        manager.add_provider(Box::new(OllamaProvider::new(
+            "http://127.0.0.1:9".to_string(),
+            "mistral".to_string(),
+        )));
+        manager.add_provider(Box::new(OpenAICompatibleProvider::new(
+            url,
+            None,
+            "gpt-4o-mini".to_string(),
+        )));
+        manager.set_fallback_chain(vec![ProviderType::Local, ProviderType::OpenAI]);
+
+        let reply = manager
+            .get_response(
+                &Conversation::new("", "hello"),
+                &crate::agents::profiles::GENERAL_CLIAI,
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+        assert_eq!(reply, "from openai");
+    }
+
+    #[tokio::test]
+    async fn test_cached_response_skips_provider() {
+        let (url, mut requests) = spawn_stub_server(vec![(
+            200,
+            r#"{"choices":[{"message":{"content":"Command: free -h"}}]}"#.to_string(),
+        )])
+        .await;
+
+        let mut manager = ProviderManager::new();
+        manager.add_provider(Box::new(OpenAICompatibleProvider::new(
+            url,
+            None,
+            "gpt-4o-mini".to_string(),
+        )));
+        manager.set_fallback_chain(vec![ProviderType::OpenAI]);
+        manager.enable_cache(ResponseCache::in_memory(10, Duration::from_secs(60)));
+        manager.set_cache_context(1);
+
+        let agent = &crate::agents::profiles::SHELL_EXPERT;
+        for _ in 0..2 {
+            let reply = manager
+                .get_response(
+                    &Conversation::new("", "show free RAM"),
+                    agent,
+                    Duration::from_secs(5),
+                )
+                .await
+                .unwrap();
+            assert_eq!(reply, "Command: free -h");
+        }
+        assert!(requests.recv().await.is_some());
+        assert!(requests.try_recv().is_err());
+
+        // A different working directory context must not reuse the cached answer
+        manager.set_cache_context(2);
+        manager
+            .get_response(
+                &Conversation::new("", "show free RAM"),
+                agent,
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+        assert!(requests.recv().await.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_cache_key_includes_history() {
+        let (url, mut requests) = spawn_stub_server(vec![
+            (
+                200,
+                r#"{"choices":[{"message":{"content":"Command: du -sh build"}}]}"#.to_string(),
+            ),
+            (
+                200,
+                r#"{"choices":[{"message":{"content":"Command: du -sh dist"}}]}"#.to_string(),
+            ),
+        ])
+        .await;
+
+        let mut manager = ProviderManager::new();
+        manager.add_provider(Box::new(OpenAICompatibleProvider::new(
+            url,
+            None,
+            "gpt-4o-mini".to_string(),
+        )));
+        manager.set_fallback_chain(vec![ProviderType::OpenAI]);
+        manager.enable_cache(ResponseCache::in_memory(10, Duration::from_secs(60)));
+
+        let agent = &crate::agents::profiles::SHELL_EXPERT;
+        let about = |dir: &str| {
+            Conversation::new("", "how big is it?").with_history(vec![ChatTurn {
+                role: "user".to_string(),
+                content: format!("what is in {}?", dir),
+            }])
+        };
+        // The same question after different history is a different request, while
+        // repeating the first conversation is answered from the cache
+        for (dir, expected, from_cache) in [
+            ("build", "Command: du -sh build", false),
+            ("dist", "Command: du -sh dist", false),
+            ("build", "Command: du -sh build", true),
+        ] {
+            let reply = manager
+                .get_response_streaming(&about(dir), agent, Duration::from_secs(5), &mut |_| {})
+                .await
+                .unwrap();
+            assert_eq!(reply, expected);
+            assert_eq!(requests.try_recv().is_err(), from_cache);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_breaker_state_persists_across_managers() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = ProviderHealthStore::at(dir.path().join("provider_health.json"));
+        let (url, mut requests) =
+            spawn_stub_server(vec![(500, r#"{"error":"down"}"#.to_string())]).await;
+        let agent = &crate::agents::profiles::SHELL_EXPERT;
+
+        let new_manager = |store: &ProviderHealthStore| {
+            let mut manager = ProviderManager::new();
+            manager.add_provider(Box::new(OpenAICompatibleProvider::new(
+                url.clone(),
+                None,
+                "gpt-4o-mini".to_string(),
+            )));
+            manager.set_fallback_chain(vec![ProviderType::OpenAI]);
+            manager.enable_health_persistence(store.clone());
+            manager
+        };
+
+        // Each failing invocation adds to the persisted failure count
+        let mut manager = new_manager(&store);
+        assert!(manager
+            .get_response(&Conversation::new("", "hi"), agent, Duration::from_secs(5))
+            .await
+            .is_err());
+        assert!(requests.recv().await.is_some());
+        assert_eq!(store.load()["OpenAI"].failure_count, 1);
+
+        // Once another invocation opens the breaker, a fresh manager skips the provider
+        store
+            .update(|records| {
+                let record = records.get_mut("OpenAI").unwrap();
+                record.record_failure(ProviderHealthStore::now(), 3);
+                record.record_failure(ProviderHealthStore::now(), 3);
+            })
+            .unwrap();
+        let mut manager = new_manager(&store);
+        let err = manager
+            .get_response(&Conversation::new("", "hi"), agent, Duration::from_secs(5))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("skipped"));
+        assert!(requests.try_recv().is_err());
+
+        let health = manager.get_provider_health();
+        assert_eq!(health[0].2.total_failures, 3);
+        assert!(manager.get_retry_after("OpenAI").is_some());
+    }
+
+    fn turn(role: &str, content: &str) -> ChatTurn {
+        ChatTurn {
+            role: role.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_conversation_messages_alternate_roles() {
+        let conversation = Conversation::new("system", "and now?").with_history(vec![
+            turn("assistant", "orphaned reply"),
+            turn("user", "list files"),
+            turn("assistant", "Command: ls"),
+            turn("user", "only hidden ones"),
+        ]);
+
+        let messages = conversation.messages();
+        let roles: Vec<&str> = messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["user", "assistant", "user"]);
+        assert_eq!(messages[0].content, "list files");
+        assert_eq!(messages[2].content, "only hidden ones\n\nand now?");
+    }
+
+    #[test]
+    fn test_conversation_chat_messages_start_with_system() {
+        let conversation = Conversation::new("You are helpful", "hi")
+            .with_history(vec![turn("user", "hello"), turn("assistant", "hey")]);
+
+        let messages = conversation.to_chat_messages();
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[0]["content"], "You are helpful");
+        assert_eq!(messages[3]["role"], "user");
+        assert_eq!(messages[3]["content"], "hi");
+
+        // No empty system message is sent
+        assert_eq!(Conversation::new("", "hi").to_chat_messages().len(), 1);
+    }
+
+    #[test]
+    fn test_conversation_to_prompt_flattens_roles() {
+        let conversation = Conversation::new("System", "second")
+            .with_history(vec![turn("user", "first"), turn("assistant", "answer")]);
+
+        let prompt = conversation.to_prompt();
+        assert!(prompt.starts_with("System"));
+        assert!(prompt.contains("user: first\nassistant: answer"));
+        assert!(prompt.ends_with("User: second"));
+    }
+
+    #[tokio::test]
+    async fn test_ollama_pull_model_reports_progress() {
+        let body = [
+            r#"{"status":"pulling manifest"}"#,
+            r#"{"status":"pulling 8eeb52df","digest":"sha256:8eeb52df","total":2000,"completed":500}"#,
+            r#"{"status":"pulling 8eeb52df","digest":"sha256:8eeb52df","total":2000,"completed":2000}"#,
+            r#"{"status":"verifying sha256 digest"}"#,
+            r#"{"status":"success"}"#,
+        ]
+        .join("\n");
+        let (url, mut requests) = spawn_stub_server(vec![
+            (200, body),
+            (
+                200,
+                r#"{"error":"pull model manifest: file does not exist"}"#.to_string(),
+            ),
+            (200, r#"{"status":"pulling manifest"}"#.to_string()),
+        ])
+        .await;
+        let provider = OllamaProvider::new(url, "mistral".to_string());
+
+        let mut updates = Vec::new();
+        provider
+            .pull_model("llama3", |progress| updates.push(progress.clone()))
+            .await
+            .unwrap();
+        assert_eq!(updates.len(), 5);
+        assert_eq!(updates[1].total, Some(2000));
+        assert_eq!(updates[2].completed, Some(2000));
+        assert_eq!(updates[4].status, "success");
+
+        let request = requests.recv().await.unwrap();
+        assert!(request.starts_with("POST /api/pull"));
+        assert!(request.contains(r#""model":"llama3""#));
+
+        let error = provider
+            .pull_model("nope", |_| {})
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("file does not exist"));
+
+        // A stream that ends without "success" is an incomplete download
+        assert!(provider.pull_model("llama3", |_| {}).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ollama_model_management_endpoints() {
+        let show = r#"{
+            "parameters": "num_ctx                        4096\nstop                           \"<|eot_id|>\"",
+            "details": {"format":"gguf","family":"llama","parameter_size":"8.0B","quantization_level":"Q4_0"},
+            "model_info": {"general.architecture":"llama","llama.context_length":8192}
+        }"#;
+        let (url, mut requests) = spawn_stub_server(vec![
+            (200, show.to_string()),
+            (
+                200,
+                r#"{"models":[{"name":"llama3:latest","size":4661224676}]}"#.to_string(),
+            ),
+            (
+                200,
+                r#"{"models":[{"name":"llama3:latest","size":6000000000,"size_vram":3000000000,"expires_at":"2024-06-04T14:38:31-07:00"}]}"#
+                    .to_string(),
+            ),
+            (404, r#"{"error":"model 'ghost' not found"}"#.to_string()),
+        ])
+        .await;
+        let provider = OllamaProvider::new(url, "mistral".to_string());
+
+        let details = provider.show_model("llama3").await.unwrap();
+        assert_eq!(details.family.as_deref(), Some("llama"));
+        assert_eq!(details.parameter_size.as_deref(), Some("8.0B"));
+        assert_eq!(details.quantization.as_deref(), Some("Q4_0"));
+        assert_eq!(details.context_length, Some(8192));
+        assert_eq!(details.size, Some(4661224676));
+        assert_eq!(
+            details.parameters,
+            vec![
+                ("num_ctx".to_string(), "4096".to_string()),
+                ("stop".to_string(), "\"<|eot_id|>\"".to_string()),
+            ]
+        );
+        assert!(requests.recv().await.unwrap().starts_with("POST /api/show"));
+        assert!(requests.recv().await.unwrap().starts_with("GET /api/tags"));
+
+        let loaded = provider.running_models().await.unwrap();
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(loaded[0].name, "llama3:latest");
+        assert_eq!(loaded[0].size_vram, 3000000000);
+        assert!(requests.recv().await.unwrap().starts_with("GET /api/ps"));
+
+        let error = provider
+            .delete_model("ghost")
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("not installed"));
+        assert!(requests
+            .recv()
+            .await
+            .unwrap()
+            .starts_with("DELETE /api/delete"));
+    }
+
+    #[tokio::test]
+    async fn test_ollama_provider_uses_chat_endpoint() {
+        let (url, mut requests) = spawn_stub_server(vec![(
+            200,
+            r#"{"model":"mistral","message":{"role":"assistant","content":"Command: free -h"},"done":true,"prompt_eval_count":31,"eval_count":6}"#
+                .to_string(),
+        )])
+        .await;
+
+        let provider = OllamaProvider::new(url, "mistral".to_string());
+        let conversation =
+            Conversation::new("You are ShellExpert", "show free RAM").with_history(vec![
+                turn("user", "list files"),
+                turn("assistant", "Command: ls"),
+            ]);
+        let reply = provider
+            .generate_response(&conversation, &crate::agents::profiles::SHELL_EXPERT)
+            .await
+            .unwrap();
+        assert_eq!(reply.text, "Command: free -h");
+        assert_eq!(
+            reply.usage,
+            Some(TokenUsage {
+                prompt_tokens: 31,
+                completion_tokens: 6
+            })
+        );
+
+        let request = requests.recv().await.unwrap();
+        assert!(request.starts_with("POST /api/chat"));
+        let body: serde_json::Value =
+            serde_json::from_str(&request[request.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+        let roles: Vec<&str> = body["messages"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|m| m["role"].as_str().unwrap())
+            .collect();
+        assert_eq!(roles, vec!["system", "user", "assistant", "user"]);
+        assert_eq!(body["messages"][3]["content"], "show free RAM");
+        assert_eq!(body["stream"], false);
+    }
+
+    #[test]
+    fn test_anthropic_request_body_uses_system_field() {
+        let provider = AnthropicProvider::new(
+            "https://api.anthropic.com/v1".to_string(),
+            "key".to_string(),
+            "claude-3-5-haiku-latest".to_string(),
+        );
+        let conversation = Conversation::new("You are ShellExpert", "list hidden files")
+            .with_history(vec![
+                turn("user", "list files"),
+                turn("assistant", "Command: ls"),
+            ]);
+
+        let params = GenerationParams {
+            temperature: 1.5,
+            max_tokens: 300,
+            top_p: None,
+            stop: vec!["\n\n".to_string()],
+            num_ctx: Some(8192),
+            seed: Some(7),
+        };
+
+        let body = provider.build_request_body(&conversation, &params);
+        assert_eq!(body["system"], "You are ShellExpert");
+        assert_eq!(body["model"], "claude-3-5-haiku-latest");
+        assert_eq!(body["messages"].as_array().unwrap().len(), 3);
+        assert_eq!(body["messages"][0]["role"], "user");
+        assert_eq!(body["messages"][1]["role"], "assistant");
+        assert_eq!(body["messages"][2]["content"], "list hidden files");
+
+        // Generation settings map onto the Messages API fields it supports
+        assert_eq!(body["max_tokens"], 300);
+        assert_eq!(body["temperature"], 1.0);
+        assert_eq!(body["stop_sequences"][0], "\n\n");
+        assert!(body.get("seed").is_none());
+        assert!(body.get("top_p").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_response_schema_is_sent_to_ollama_and_openai() {
+        let schema = json!({"type": "object", "properties": {"category": {"type": "string"}}});
+        let conversation =
+            Conversation::new("Planner", "list files").with_response_schema(schema.clone());
+        let agent = &crate::agents::profiles::PLANNER_AGENT;
+
+        let (url, mut requests) = spawn_stub_server(vec![(
+            200,
+            r#"{"message":{"role":"assistant","content":"{\"category\":\"SHELL\"}"},"done":true}"#
+                .to_string(),
+        )])
+        .await;
+        let ollama = OllamaProvider::new(url, "mistral".to_string());
+        ollama
+            .generate_response(&conversation, agent)
+            .await
+            .unwrap();
+        let request = requests.recv().await.unwrap();
+        let body: serde_json::Value =
+            serde_json::from_str(&request[request.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+        assert_eq!(body["format"], schema);
+
+        let (url, mut requests) = spawn_stub_server(vec![(
+            200,
+            r#"{"choices":[{"message":{"content":"{\"category\":\"SHELL\"}"}}]}"#.to_string(),
+        )])
+        .await;
+        let openai = OpenAICompatibleProvider::new(url, None, "gpt-4o-mini".to_string());
+        openai
+            .generate_response(&conversation, agent)
+            .await
+            .unwrap();
+        let request = requests.recv().await.unwrap();
+        let body: serde_json::Value =
+            serde_json::from_str(&request[request.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+        assert_eq!(body["response_format"]["type"], "json_schema");
+        assert_eq!(body["response_format"]["json_schema"]["schema"], schema);
+    }
+
+    #[tokio::test]
+    async fn test_planner_schema_request_is_accepted_by_strict_mode() {
+        let ids = vec!["ls-current".to_string(), "git-status".to_string()];
+        let schema = crate::agents::planner::PlannerDecision::json_schema(&ids);
+        let conversation =
+            Conversation::new("Planner", "list files").with_response_schema(schema.clone());
+
+        let (url, mut requests) = spawn_stub_server(vec![(
+            200,
+            r#"{"choices":[{"message":{"content":"{\"category\":\"SHELL\",\"commands\":[]}"}}]}"#
+                .to_string(),
+        )])
+        .await;
+        let openai = OpenAICompatibleProvider::new(url, None, "gpt-4o-mini".to_string());
+        openai
+            .generate_response(&conversation, &crate::agents::profiles::PLANNER_AGENT)
+            .await
+            .unwrap();
+
+        let request = requests.recv().await.unwrap();
+        let raw_body = &request[request.find("\r\n\r\n").unwrap() + 4..];
+        let body: serde_json::Value = serde_json::from_str(raw_body).unwrap();
+        assert_eq!(body["response_format"]["json_schema"]["strict"], true);
+        assert_eq!(body["response_format"]["json_schema"]["schema"], schema);
+        // Strict structured outputs answer 400 to validation keywords such as maxItems
+        assert!(!raw_body.contains("maxItems"));
+    }
+
+    #[tokio::test]
+    async fn test_anthropic_structured_reply_uses_forced_tool() {
+        let (url, mut requests) = spawn_stub_server(vec![(
+            200,
+            r#"{"type":"message","content":[{"type":"tool_use","id":"t1","name":"respond","input":{"category":"SHELL","commands":[]}}],"usage":{"input_tokens":10,"output_tokens":5}}"#
+                .to_string(),
+        )])
+        .await;
+
+        let provider = AnthropicProvider::new(
+            url,
+            "key".to_string(),
+            "claude-3-5-haiku-latest".to_string(),
+        );
+        let conversation = Conversation::new("Planner", "list files")
+            .with_response_schema(json!({"type": "object"}));
+        let reply = provider
+            .generate_response(&conversation, &crate::agents::profiles::PLANNER_AGENT)
+            .await
+            .unwrap();
+
+        let decision: serde_json::Value = serde_json::from_str(&reply.text).unwrap();
+        assert_eq!(decision["category"], "SHELL");
+
+        let request = requests.recv().await.unwrap();
+        let body: serde_json::Value =
+            serde_json::from_str(&request[request.find("\r\n\r\n").unwrap() + 4..]).unwrap();
+        assert_eq!(body["tools"][0]["name"], ANTHROPIC_RESPONSE_TOOL);
+        assert_eq!(body["tool_choice"]["name"], ANTHROPIC_RESPONSE_TOOL);
     }
 
-    fn get_name(&self) -> &'static str {
-        "OpenAI/Anthropic"
+    #[test]
+    fn test_ollama_options_follow_agent_params() {
+        let options = OllamaProvider::build_options(&crate::agents::profiles::PLANNER_AGENT.params);
+        assert_eq!(options["temperature"], 0.0);
+        assert_eq!(options["num_predict"], 256);
+        assert_eq!(options["seed"], 0);
+        assert!(options.get("stop").is_none());
+
+        let params = GenerationParams {
+            temperature: 0.5,
+            max_tokens: 4096,
+            top_p: Some(0.5),
+            stop: vec!["User:".to_string()],
+            num_ctx: Some(8192),
+            seed: None,
+        };
+        let options = OllamaProvider::build_options(&params);
+        assert_eq!(options["num_predict"], 4096);
+        assert_eq!(options["top_p"], 0.5);
+        assert_eq!(options["stop"][0], "User:");
+        assert_eq!(options["num_ctx"], 8192);
+        assert!(options.get("seed").is_none());
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[tokio::test]
+    async fn test_anthropic_provider_messages_call() {
+        let (url, mut requests) = spawn_stub_server(vec![(
+            200,
+            r#"{"id":"msg_1","type":"message","role":"assistant","content":[{"type":"text","text":"Command: df -h"}],"stop_reason":"end_turn","usage":{"input_tokens":42,"output_tokens":7}}"#
+                .to_string(),
+        )])
+        .await;
+
+        let provider = AnthropicProvider::new(
+            format!("{}/v1", url),
+            "sk-ant-test".to_string(),
+            "claude-3-5-haiku-latest".to_string(),
+        );
+        let reply = provider
+            .generate_response(
+                &Conversation::new("", "disk usage"),
+                &crate::agents::profiles::SHELL_EXPERT,
+            )
+            .await
+            .unwrap();
+        assert_eq!(reply.text, "Command: df -h");
+        assert_eq!(
+            reply.usage,
+            Some(TokenUsage {
+                prompt_tokens: 42,
+                completion_tokens: 7
+            })
+        );
 
-    #[test]
-    fn test_provider_type_equality() {
-        assert_eq!(ProviderType::Local, ProviderType::Local);
-        assert_eq!(ProviderType::Cloud, ProviderType::Cloud);
-        assert_ne!(ProviderType::Local, ProviderType::Cloud);
+        let request = requests.recv().await.unwrap();
+        let lower = request.to_lowercase();
+        assert!(request.starts_with("POST /v1/messages"));
+        assert!(lower.contains("x-api-key: sk-ant-test"));
+        assert!(lower.contains("anthropic-version: 2023-06-01"));
+        assert!(request.contains(r#""max_tokens":512"#));
     }
 
-    #[test]
-    fn test_circuit_breaker_creation() {
-        let cb = CircuitBreaker::new(3, Duration::from_secs(60));
-        assert_eq!(cb.get_state(), CircuitBreakerState::Closed);
-        assert_eq!(cb.failure_count, 0);
+    #[tokio::test]
+    async fn test_anthropic_provider_error_body() {
+        let (url, _requests) = spawn_stub_server(vec![(
+            529,
+            r#"{"type":"error","error":{"type":"overloaded_error","message":"Overloaded"}}"#
+                .to_string(),
+        )])
+        .await;
+
+        let provider = AnthropicProvider::new(url, "key".to_string(), "claude".to_string());
+        let err = provider
+            .generate_response(
+                &Conversation::new("", "hi"),
+                &crate::agents::profiles::GENERAL_CLIAI,
+            )
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("overloaded"));
+        assert!(err.contains("Overloaded"));
+
+        let auth = AnthropicProvider::error_message(
+            reqwest::StatusCode::UNAUTHORIZED,
+            r#"{"type":"error","error":{"type":"authentication_error","message":"invalid x-api-key"}}"#,
+        );
+        assert!(auth.contains("invalid x-api-key"));
+        assert!(auth.contains("cliai test-key anthropic"));
     }
 
-    #[test]
-    fn test_circuit_breaker_can_execute() {
-        let mut cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    /// Scripted provider: waits `delay` before each chunk, optionally failing after them
+    struct ScriptedProvider {
+        provider_type: ProviderType,
+        chunks: Vec<&'static str>,
+        delay: Duration,
+        fail_after: bool,
+    }
 
-        // Initially should allow execution
-        assert!(cb.can_execute());
+    #[async_trait]
+    impl AIProvider for ScriptedProvider {
+        async fn generate_response(
+            &self,
+            _conversation: &Conversation,
+            _agent: &AgentProfile,
+        ) -> Result<ProviderResponse> {
+            Ok(ProviderResponse::new(
+                self.chunks.concat(),
+                None,
+                "scripted",
+                self.provider_type.clone(),
+            ))
+        }
 
-        // After failures below threshold, should still allow
-        cb.record_failure();
-        cb.record_failure();
-        assert!(cb.can_execute());
+        async fn generate_response_stream(
+            &self,
+            _conversation: &Conversation,
+            _agent: &AgentProfile,
+        ) -> Result<TokenStream> {
+            let delay = self.delay;
+            let mut items: Vec<Result<StreamChunk>> = self
+                .chunks
+                .iter()
+                .map(|c| Ok(StreamChunk::text(*c)))
+                .collect();
+            if self.fail_after {
+                items.push(Err(anyhow!("connection reset")));
+            }
+            Ok(Box::pin(stream::iter(items).then(move |item| async move {
+                tokio::time::sleep(delay).await;
+                item
+            })))
+        }
 
-        // After reaching threshold, should open
-        cb.record_failure();
-        assert_eq!(cb.get_state(), CircuitBreakerState::Open);
-        assert!(!cb.can_execute());
+        async fn list_models(&self) -> Result<Vec<String>> {
+            Ok(Vec::new())
+        }
+
+        fn get_provider_type(&self) -> ProviderType {
+            self.provider_type.clone()
+        }
+
+        async fn is_available(&self) -> bool {
+            true
+        }
+
+        fn get_name(&self) -> &'static str {
+            "Scripted"
+        }
+
+        fn get_model_name(&self) -> &str {
+            "scripted"
+        }
     }
 
-    #[test]
-    fn test_circuit_breaker_success_reset() {
-        let mut cb = CircuitBreaker::new(3, Duration::from_secs(60));
+    fn scripted(chunks: Vec<&'static str>) -> Box<dyn AIProvider> {
+        Box::new(ScriptedProvider {
+            provider_type: ProviderType::Local,
+            chunks,
+            delay: Duration::ZERO,
+            fail_after: false,
+        })
+    }
 
-        // Record some failures
-        cb.record_failure();
-        cb.record_failure();
-        assert_eq!(cb.failure_count, 2);
+    #[tokio::test]
+    async fn test_routing_strategies_order_endpoints() {
+        let mut manager = ProviderManager::new();
+        for name in ["workstation", "laptop", "spare"] {
+            manager.add_named_provider(name, scripted(vec![name]));
+            manager.set_instance_models(name, vec!["scripted".to_string()]);
+        }
 
-        // Success should reset
-        cb.record_success();
-        assert_eq!(cb.failure_count, 0);
-        assert_eq!(cb.get_state(), CircuitBreakerState::Closed);
+        assert_eq!(manager.route(&ProviderType::Local).await, vec![0, 1, 2]);
+        assert!(manager.route(&ProviderType::Cloud).await.is_empty());
+
+        manager.set_routing(RoutingStrategy::RoundRobin);
+        manager.round_robin_offset = 0;
+        assert_eq!(manager.route(&ProviderType::Local).await, vec![0, 1, 2]);
+        assert_eq!(manager.route(&ProviderType::Local).await, vec![1, 2, 0]);
+        assert_eq!(manager.route(&ProviderType::Local).await, vec![2, 0, 1]);
+
+        // Fastest first; the untried endpoint is probed before the measured ones
+        manager.set_routing(RoutingStrategy::LowestLatency);
+        let monitor = manager.get_performance_monitor_mut();
+        monitor.record_provider_latency("workstation", Duration::from_millis(900));
+        monitor.record_provider_latency("spare", Duration::from_millis(200));
+        assert_eq!(manager.route(&ProviderType::Local).await, vec![1, 2, 0]);
     }
 
-    #[test]
-    fn test_provider_manager_creation() {
-        let manager = ProviderManager::new();
+    #[tokio::test]
+    async fn test_routing_prefers_endpoints_with_the_model() {
+        let mut manager = ProviderManager::new();
+        manager.add_named_provider("workstation", scripted(vec!["workstation"]));
+        manager.add_named_provider("laptop", scripted(vec!["laptop"]));
+        manager.add_named_provider("spare", scripted(vec!["spare"]));
+        manager.set_instance_models("workstation", vec!["llama3:70b".to_string()]);
+        manager.set_instance_models("laptop", vec!["scripted:latest".to_string()]);
+        manager.set_fallback_chain(vec![ProviderType::Local]);
+
+        // "spare" reports an empty model list when discovered, so only "laptop" is left
+        assert_eq!(manager.route(&ProviderType::Local).await, vec![1]);
+        let reply = manager
+            .get_response(
+                &Conversation::new("", "hi"),
+                &crate::agents::profiles::SHELL_EXPERT,
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+        assert_eq!(reply, "laptop");
 
-        assert_eq!(
-            manager.fallback_chain,
-            vec![ProviderType::Local, ProviderType::Cloud]
+        // No endpoint has the model: all are tried so they can report it
+        manager.set_instance_models("laptop", Vec::new());
+        manager.set_instance_models("spare", Vec::new());
+        assert_eq!(manager.route(&ProviderType::Local).await, vec![0, 1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_ollama_endpoints_fail_over_with_separate_breakers() {
+        let (down_url, _down_requests) =
+            spawn_stub_server(vec![(500, r#"{"error":"out of memory"}"#.to_string())]).await;
+        let (up_url, mut up_requests) = spawn_stub_server(vec![
+            (
+                200,
+                r#"{"models":[{"name":"mistral:latest"}]}"#.to_string(),
+            ),
+            (
+                200,
+                r#"{"message":{"role":"assistant","content":"Command: uptime"},"done":true,"eval_count":3}"#
+                    .to_string(),
+            ),
+        ])
+        .await;
+
+        let mut manager = ProviderManager::new();
+        manager.add_named_provider(
+            "workstation",
+            Box::new(OllamaProvider::new(down_url, "mistral".to_string())),
         );
-        assert_eq!(manager.retry_limits.get(&ProviderType::Cloud), Some(&1));
-        assert_eq!(manager.retry_limits.get(&ProviderType::Local), Some(&2));
-        assert!(manager.circuit_breakers.contains_key(&ProviderType::Cloud));
-        assert!(manager.circuit_breakers.contains_key(&ProviderType::Local));
+        manager.add_named_provider(
+            "laptop",
+            Box::new(OllamaProvider::new(up_url, "mistral".to_string())),
+        );
+        manager.set_instance_models("workstation", vec!["mistral:latest".to_string()]);
+        manager.set_retry_limit(ProviderType::Local, 1);
+        manager.set_fallback_chain(vec![ProviderType::Local]);
+
+        let reply = manager
+            .get_response(
+                &Conversation::new("", "how long has this machine been up"),
+                &crate::agents::profiles::SHELL_EXPERT,
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+        assert_eq!(reply, "Command: uptime");
+        assert!(up_requests
+            .recv()
+            .await
+            .unwrap()
+            .starts_with("GET /api/tags"));
+        assert!(up_requests
+            .recv()
+            .await
+            .unwrap()
+            .starts_with("POST /api/chat"));
+
+        // Only the failing endpoint's breaker saw the failure
+        assert_eq!(manager.circuit_breakers["workstation"].failure_count, 1);
+        assert_eq!(manager.circuit_breakers["laptop"].failure_count, 0);
+        assert_eq!(manager.circuit_breakers["Local"].failure_count, 0);
+        assert!(manager
+            .get_performance_monitor()
+            .get_provider_latency("laptop")
+            .is_some());
+
+        let status = manager.get_provider_status();
+        assert_eq!(status[0].0, "Ollama (workstation)");
+        assert_eq!(status[1].0, "Ollama (laptop)");
     }
 
-    #[test]
-    fn test_provider_manager_set_fallback_chain() {
+    #[tokio::test]
+    async fn test_streaming_delivers_chunks_in_order() {
         let mut manager = ProviderManager::new();
+        manager.add_provider(Box::new(ScriptedProvider {
+            provider_type: ProviderType::Local,
+            chunks: vec!["Command: ", "free -h", "\n\nShows memory"],
+            delay: Duration::from_millis(1),
+            fail_after: false,
+        }));
+        manager.set_fallback_chain(vec![ProviderType::Local]);
+
+        let mut seen = Vec::new();
+        let response = manager
+            .get_response_streaming(
+                &Conversation::new("", "ram"),
+                &crate::agents::profiles::SHELL_EXPERT,
+                Duration::from_secs(5),
+                &mut |chunk| seen.push(chunk.to_string()),
+            )
+            .await
+            .unwrap();
 
-        manager.set_fallback_chain(vec![ProviderType::Local, ProviderType::Cloud]);
-        assert_eq!(
-            manager.fallback_chain,
-            vec![ProviderType::Local, ProviderType::Cloud]
-        );
+        assert_eq!(seen, vec!["Command: ", "free -h", "\n\nShows memory"]);
+        assert_eq!(response, "Command: free -h\n\nShows memory");
     }
 
-    #[test]
-    fn test_provider_manager_set_retry_limit() {
+    #[tokio::test]
+    async fn test_first_token_timeout_falls_back() {
         let mut manager = ProviderManager::new();
+        manager.add_provider(Box::new(ScriptedProvider {
+            provider_type: ProviderType::Local,
+            chunks: vec!["too late"],
+            delay: Duration::from_secs(10),
+            fail_after: false,
+        }));
+        manager.add_provider(Box::new(ScriptedProvider {
+            provider_type: ProviderType::OpenAI,
+            chunks: vec!["fast"],
+            delay: Duration::from_millis(1),
+            fail_after: false,
+        }));
+        manager.set_fallback_chain(vec![ProviderType::Local, ProviderType::OpenAI]);
+        manager.set_retry_limit(ProviderType::Local, 1);
+        manager.set_first_token_timeout(Duration::from_millis(100));
+
+        let started = std::time::Instant::now();
+        let response = manager
+            .get_response(
+                &Conversation::new("", "hi"),
+                &crate::agents::profiles::GENERAL_CLIAI,
+                Duration::from_secs(20),
+            )
+            .await
+            .unwrap();
 
-        manager.set_retry_limit(ProviderType::Cloud, 5);
-        assert_eq!(manager.retry_limits.get(&ProviderType::Cloud), Some(&5));
+        assert_eq!(response, "fast");
+        assert!(started.elapsed() < Duration::from_secs(5));
     }
 
-    #[test]
-    fn test_provider_manager_switch_preference() {
+    #[tokio::test]
+    async fn test_mid_stream_failure_does_not_fall_back() {
         let mut manager = ProviderManager::new();
+        manager.add_provider(Box::new(ScriptedProvider {
+            provider_type: ProviderType::Local,
+            chunks: vec!["partial"],
+            delay: Duration::from_millis(1),
+            fail_after: true,
+        }));
+        manager.add_provider(Box::new(ScriptedProvider {
+            provider_type: ProviderType::OpenAI,
+            chunks: vec!["other"],
+            delay: Duration::from_millis(1),
+            fail_after: false,
+        }));
+        manager.set_fallback_chain(vec![ProviderType::Local, ProviderType::OpenAI]);
+
+        let mut seen = String::new();
+        let result = manager
+            .get_response_streaming(
+                &Conversation::new("", "hi"),
+                &crate::agents::profiles::GENERAL_CLIAI,
+                Duration::from_secs(5),
+                &mut |chunk| seen.push_str(chunk),
+            )
+            .await;
+
+        assert!(result.unwrap_err().to_string().contains("connection reset"));
+        assert_eq!(seen, "partial");
+    }
 
-        // Initially Local first
-        assert_eq!(manager.fallback_chain[0], ProviderType::Local);
+    #[tokio::test]
+    async fn test_hedged_request_takes_the_faster_provider() {
+        let mut manager = ProviderManager::new();
+        manager.add_provider(Box::new(ScriptedProvider {
+            provider_type: ProviderType::Local,
+            chunks: vec!["slow ", "local"],
+            delay: Duration::from_secs(2),
+            fail_after: false,
+        }));
+        manager.add_provider(Box::new(ScriptedProvider {
+            provider_type: ProviderType::OpenAI,
+            chunks: vec!["fast ", "cloud"],
+            delay: Duration::from_millis(5),
+            fail_after: false,
+        }));
+        manager.set_fallback_chain(vec![ProviderType::Local, ProviderType::OpenAI]);
+        manager.set_hedge_delay(Some(Duration::from_millis(50)));
+
+        let started = std::time::Instant::now();
+        let mut seen = String::new();
+        let response = manager
+            .get_response_streaming(
+                &Conversation::new("", "hi"),
+                &crate::agents::profiles::GENERAL_CLIAI,
+                Duration::from_secs(10),
+                &mut |chunk| seen.push_str(chunk),
+            )
+            .await
+            .unwrap();
 
-        // Switch to Cloud first
-        manager.switch_provider_preference(ProviderType::Cloud);
-        assert_eq!(manager.fallback_chain[0], ProviderType::Cloud);
-        assert_eq!(manager.fallback_chain[1], ProviderType::Local);
+        assert_eq!(response, "fast cloud");
+        assert_eq!(seen, "fast cloud");
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        // The cancelled local request is neither a success nor a failure
+        assert_eq!(manager.circuit_breakers["Local"].failure_count, 0);
+        let monitor = manager.get_performance_monitor();
+        assert!(monitor.get_provider_latency("Local").is_none());
+        assert!(monitor.get_provider_latency("OpenAI").is_some());
+        assert_eq!(
+            monitor
+                .get_performance_stats(&OperationType::LocalOllama)
+                .total_operations,
+            0
+        );
+        assert_eq!(
+            monitor
+                .get_performance_stats(&OperationType::CloudProvider)
+                .successful_operations,
+            1
+        );
     }
 
-    #[test]
-    fn test_provider_manager_reset_circuit_breakers() {
+    #[tokio::test]
+    async fn test_hedged_request_moves_on_when_primary_fails() {
+        let mut manager = ProviderManager::new();
+        manager.add_provider(Box::new(ScriptedProvider {
+            provider_type: ProviderType::Local,
+            chunks: Vec::new(),
+            delay: Duration::from_millis(1),
+            fail_after: true,
+        }));
+        manager.add_provider(Box::new(ScriptedProvider {
+            provider_type: ProviderType::OpenAI,
+            chunks: vec!["cloud"],
+            delay: Duration::from_millis(1),
+            fail_after: false,
+        }));
+        manager.set_fallback_chain(vec![ProviderType::Local, ProviderType::OpenAI]);
+        // Far longer than the test may take: the failure must start the next provider
+        manager.set_hedge_delay(Some(Duration::from_secs(30)));
+
+        let started = std::time::Instant::now();
+        let response = manager
+            .get_response(
+                &Conversation::new("", "hi"),
+                &crate::agents::profiles::GENERAL_CLIAI,
+                Duration::from_secs(10),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response, "cloud");
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(manager.circuit_breakers["Local"].failure_count, 1);
+        assert_eq!(
+            manager
+                .get_performance_monitor()
+                .get_performance_stats(&OperationType::LocalOllama)
+                .successful_operations,
+            0
+        );
+
+        // With every provider failing, the last error is reported
         let mut manager = ProviderManager::new();
+        manager.add_provider(Box::new(ScriptedProvider {
+            provider_type: ProviderType::Local,
+            chunks: Vec::new(),
+            delay: Duration::from_millis(1),
+            fail_after: true,
+        }));
+        manager.set_fallback_chain(vec![ProviderType::Local]);
+        manager.set_hedge_delay(Some(Duration::from_millis(10)));
+        let error = manager
+            .get_response(
+                &Conversation::new("", "hi"),
+                &crate::agents::profiles::GENERAL_CLIAI,
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("connection reset"));
+    }
 
-        // Trigger some failures
-        if let Some(cb) = manager.circuit_breakers.get_mut(&ProviderType::Cloud) {
-            cb.record_failure();
-            cb.record_failure();
-            cb.record_failure();
-            assert_eq!(cb.get_state(), CircuitBreakerState::Open);
-        }
+    #[tokio::test]
+    async fn test_usage_recorded_and_budget_enforced() {
+        use crate::usage::{PriceTable, UsageLedger, UsageTracker};
 
-        // Reset should clear failures
-        manager.reset_circuit_breakers();
-        if let Some(cb) = manager.circuit_breakers.get(&ProviderType::Cloud) {
-            assert_eq!(cb.get_state(), CircuitBreakerState::Closed);
-            assert_eq!(cb.failure_count, 0);
-        }
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = UsageLedger::at(dir.path().join("usage.json"));
+        let mut manager = ProviderManager::new();
+        manager.add_provider(Box::new(ScriptedProvider {
+            provider_type: ProviderType::OpenAI,
+            chunks: vec!["cloud"],
+            delay: Duration::from_millis(1),
+            fail_after: false,
+        }));
+        manager.add_provider(Box::new(ScriptedProvider {
+            provider_type: ProviderType::Local,
+            chunks: vec!["local"],
+            delay: Duration::from_millis(1),
+            fail_after: false,
+        }));
+        manager.set_fallback_chain(vec![ProviderType::OpenAI, ProviderType::Local]);
+        // A zero budget is exhausted from the start
+        manager.enable_usage_tracking(UsageTracker::new(
+            ledger.clone(),
+            PriceTable::new(&HashMap::new()),
+            Some(0.0),
+        ));
+
+        let response = manager
+            .get_response(
+                &Conversation::new("", "hi"),
+                &crate::agents::profiles::GENERAL_CLIAI,
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response, "local");
+
+        let records = ledger.load();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].provider, "Local");
+        assert_eq!(records[0].agent, "CLIAI");
+        assert_eq!(records[0].requests, 1);
+        assert_eq!(
+            manager.get_performance_monitor().get_token_totals()["Local"].requests,
+            1
+        );
+
+        manager.set_fallback_chain(vec![ProviderType::OpenAI]);
+        let err = manager
+            .get_response(
+                &Conversation::new("", "hello"),
+                &crate::agents::profiles::GENERAL_CLIAI,
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("monthly budget"));
+        assert!(is_budget_exceeded(&err));
     }
 
-    #[test]
-    fn test_ollama_provider_creation() {
-        let provider =
-            OllamaProvider::new("http://localhost:11434".to_string(), "mistral".to_string());
+    #[tokio::test]
+    async fn test_ollama_provider_stream_parsing() {
+        let body = concat!(
+            r#"{"model":"mistral","message":{"role":"assistant","content":"Command:"},"done":false}"#,
+            "\n",
+            r#"{"model":"mistral","message":{"role":"assistant","content":" uptime"},"done":false}"#,
+            "\n",
+            r#"{"model":"mistral","message":{"role":"assistant","content":""},"done":true,"prompt_eval_count":12,"eval_count":2}"#,
+            "\n"
+        );
+        let (url, mut requests) = spawn_stub_server(vec![(200, body.to_string())]).await;
+
+        let provider = OllamaProvider::new(url, "mistral".to_string());
+        let chunks: Vec<StreamChunk> = provider
+            .generate_response_stream(
+                &Conversation::new("", "load"),
+                &crate::agents::profiles::SHELL_EXPERT,
+            )
+            .await
+            .unwrap()
+            .map(|c| c.unwrap())
+            .collect()
+            .await;
 
-        assert_eq!(provider.get_provider_type(), ProviderType::Local);
-        assert_eq!(provider.get_name(), "Ollama");
-        assert_eq!(provider.get_model(), "mistral");
+        assert_eq!(
+            chunks,
+            vec![
+                StreamChunk::text("Command:"),
+                StreamChunk::text(" uptime"),
+                StreamChunk::usage(TokenUsage {
+                    prompt_tokens: 12,
+                    completion_tokens: 2
+                })
+            ]
+        );
+        let request = requests.recv().await.unwrap();
+        assert!(request.starts_with("POST /api/chat"));
+        assert!(request.contains(r#""stream":true"#));
     }
 
-    #[test]
-    fn test_ollama_provider_with_timeout() {
-        let provider = OllamaProvider::with_timeout(
-            "http://localhost:11434".to_string(),
-            "mistral".to_string(),
-            Duration::from_secs(60),
+    #[tokio::test]
+    async fn test_openai_provider_stream_parsing() {
+        let body = concat!(
+            "data: {\"choices\":[{\"delta\":{\"role\":\"assistant\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hel\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"lo\"}}]}\n\n",
+            "data: {\"choices\":[],\"usage\":{\"prompt_tokens\":9,\"completion_tokens\":2}}\n\n",
+            "data: [DONE]\n\n"
         );
+        let (url, mut requests) = spawn_stub_server(vec![(200, body.to_string())]).await;
+
+        let provider = OpenAICompatibleProvider::new(url, None, "gpt-4o-mini".to_string());
+        let chunks: Vec<StreamChunk> = provider
+            .generate_response_stream(
+                &Conversation::new("", "hi"),
+                &crate::agents::profiles::GENERAL_CLIAI,
+            )
+            .await
+            .unwrap()
+            .map(|c| c.unwrap())
+            .collect()
+            .await;
 
-        assert_eq!(provider.timeout, Duration::from_secs(60));
+        assert_eq!(
+            chunks,
+            vec![
+                StreamChunk::text("Hel"),
+                StreamChunk::text("lo"),
+                StreamChunk::usage(TokenUsage {
+                    prompt_tokens: 9,
+                    completion_tokens: 2
+                })
+            ]
+        );
+        assert!(requests
+            .recv()
+            .await
+            .unwrap()
+            .contains(r#""stream_options":{"include_usage":true}"#));
     }
 
-    #[test]
-    fn test_ollama_provider_set_model() {
-        let mut provider =
-            OllamaProvider::new("http://localhost:11434".to_string(), "mistral".to_string());
+    #[tokio::test]
+    async fn test_anthropic_provider_stream_parsing() {
+        let body = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":12}}}\n\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hi\"}}\n\n",
+            "event: error\n",
+            "data: {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}\n\n"
+        );
+        let (url, _requests) = spawn_stub_server(vec![(200, body.to_string())]).await;
+
+        let provider = AnthropicProvider::new(url, "key".to_string(), "claude".to_string());
+        let items: Vec<Result<StreamChunk>> = provider
+            .generate_response_stream(
+                &Conversation::new("", "hi"),
+                &crate::agents::profiles::GENERAL_CLIAI,
+            )
+            .await
+            .unwrap()
+            .collect()
+            .await;
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_ref().unwrap().text, "Hi");
+        assert!(items[1]
+            .as_ref()
+            .unwrap_err()
+            .to_string()
+            .contains("overloaded"));
+    }
 
-        provider.set_model("llama2".to_string());
-        assert_eq!(provider.get_model(), "llama2");
+    /// Responses recorded from the real APIs, served by the stub server
+    fn fixture(path: &str) -> String {
+        let full = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), path);
+        std::fs::read_to_string(&full).unwrap_or_else(|e| panic!("{}: {}", full, e))
     }
 
     #[test]
-    fn test_cloud_provider_creation() {
-        let provider = CloudProvider::new(
-            "https://api.openai.com/v1/chat/completions".to_string(),
-            "test-key".to_string(),
+    fn test_gemini_request_body() {
+        let provider = GeminiProvider::new(
+            "http://localhost".to_string(),
+            "key".to_string(),
+            "m".to_string(),
+        );
+        let conversation = Conversation::new("You are ShellExpert.", "and in MB?")
+            .with_history(vec![turn("user", "disk usage"), turn("assistant", "df -h")]);
+        let body = provider.build_request_body(
+            &conversation,
+            &crate::agents::profiles::PLANNER_AGENT.params,
         );
 
-        assert_eq!(provider.get_provider_type(), ProviderType::Cloud);
-        assert_eq!(provider.get_name(), "OpenAI/Anthropic");
+        assert_eq!(
+            body["systemInstruction"]["parts"][0]["text"],
+            "You are ShellExpert."
+        );
+        let roles: Vec<&str> = body["contents"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["role"].as_str().unwrap())
+            .collect();
+        assert_eq!(roles, vec!["user", "model", "user"]);
+        assert_eq!(body["generationConfig"]["temperature"], 0.0);
+        assert_eq!(body["generationConfig"]["maxOutputTokens"], 256);
+        assert_eq!(body["generationConfig"]["seed"], 0);
+
+        // Structured output uses the JSON schema response fields
+        let schema = json!({ "type": "object" });
+        let body = provider.build_request_body(
+            &Conversation::new("", "hi").with_response_schema(schema.clone()),
+            &crate::agents::profiles::PLANNER_AGENT.params,
+        );
+        assert!(body.get("systemInstruction").is_none());
+        assert_eq!(
+            body["generationConfig"]["responseMimeType"],
+            "application/json"
+        );
+        assert_eq!(body["generationConfig"]["responseJsonSchema"], schema);
+    }
+
+    #[tokio::test]
+    async fn test_gemini_provider_generate_content() {
+        let (url, mut requests) =
+            spawn_stub_server(vec![(200, fixture("gemini/generate_content.json"))]).await;
+
+        let provider = GeminiProvider::new(
+            format!("{}/v1beta", url),
+            "AIza-test".to_string(),
+            "gemini-2.0-flash".to_string(),
+        );
+        let reply = provider
+            .generate_response(
+                &Conversation::new("", "disk usage"),
+                &crate::agents::profiles::SHELL_EXPERT,
+            )
+            .await
+            .unwrap();
+        assert!(reply.text.starts_with("Command: df -h"));
+        assert_eq!(
+            reply.usage,
+            Some(TokenUsage {
+                prompt_tokens: 58,
+                completion_tokens: 16
+            })
+        );
+
+        let request = requests.recv().await.unwrap();
+        assert!(request.starts_with("POST /v1beta/models/gemini-2.0-flash:generateContent"));
+        assert!(request.to_lowercase().contains("x-goog-api-key: aiza-test"));
+        assert!(request.contains(r#""maxOutputTokens":512"#));
+    }
+
+    #[tokio::test]
+    async fn test_gemini_provider_stream_and_errors() {
+        let (url, mut requests) =
+            spawn_stub_server(vec![(200, fixture("gemini/stream.sse"))]).await;
+        let provider = GeminiProvider::new(url, "key".to_string(), "gemini-2.0-flash".to_string());
+        let chunks: Vec<StreamChunk> = provider
+            .generate_response_stream(
+                &Conversation::new("", "disk usage"),
+                &crate::agents::profiles::SHELL_EXPERT,
+            )
+            .await
+            .unwrap()
+            .map(|item| item.unwrap())
+            .collect()
+            .await;
+        assert_eq!(
+            chunks,
+            vec![
+                StreamChunk::text("Command: "),
+                StreamChunk::text("df -h"),
+                StreamChunk::usage(TokenUsage {
+                    prompt_tokens: 58,
+                    completion_tokens: 5
+                })
+            ]
+        );
+        assert!(requests
+            .recv()
+            .await
+            .unwrap()
+            .contains(":streamGenerateContent?alt=sse"));
+
+        let (url, _requests) =
+            spawn_stub_server(vec![(400, fixture("gemini/error_invalid_key.json"))]).await;
+        let provider = GeminiProvider::new(url, "bad".to_string(), "gemini-2.0-flash".to_string());
+        let err = provider
+            .generate_response(
+                &Conversation::new("", "hi"),
+                &crate::agents::profiles::GENERAL_CLIAI,
+            )
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("API key not valid"));
+        assert!(err.contains("cliai test-key google"));
+    }
+
+    #[tokio::test]
+    async fn test_gemini_provider_list_models() {
+        let (url, mut requests) =
+            spawn_stub_server(vec![(200, fixture("gemini/models.json"))]).await;
+
+        let provider = GeminiProvider::new(url, "key".to_string(), "gemini-2.0-flash".to_string());
+        assert_eq!(provider.get_provider_type(), ProviderType::Gemini);
+        // Embedding-only models are left out
+        assert_eq!(
+            provider.list_models().await.unwrap(),
+            vec!["gemini-2.0-flash".to_string(), "gemini-1.5-pro".to_string()]
+        );
+        assert!(requests.recv().await.unwrap().starts_with("GET /models"));
     }
 
     #[test]
-    fn test_cloud_provider_set_model() {
-        let provider = CloudProvider::new(
-            "https://api.openai.com/v1/chat/completions".to_string(),
-            "test-key".to_string(),
+    fn test_cohere_request_body() {
+        let provider = CohereProvider::new(
+            "http://localhost".to_string(),
+            "key".to_string(),
+            "m".to_string(),
+        );
+        let mut params = crate::agents::profiles::GENERAL_CLIAI.params.clone();
+        params.temperature = 1.5;
+        params.top_p = Some(1.0);
+        params.stop = vec!["User:".to_string()];
+
+        let schema = json!({ "type": "object" });
+        let body = provider.build_request_body(
+            &Conversation::new("You are CLIAI.", "hi").with_response_schema(schema.clone()),
+            &params,
+            false,
         );
 
-        // CloudProvider doesn't have set_model/get_model methods
-        // Just test that it was created successfully
-        assert_eq!(provider.get_provider_type(), ProviderType::Cloud);
+        assert_eq!(body["messages"][0]["role"], "system");
+        assert_eq!(body["messages"][1]["content"], "hi");
+        // Clamped to Cohere's accepted ranges
+        assert_eq!(body["temperature"], 1.0);
+        assert_eq!(body["p"].as_f64().unwrap(), 0.99f32 as f64);
+        assert_eq!(body["stop_sequences"][0], "User:");
+        assert_eq!(body["response_format"]["type"], "json_object");
+        assert_eq!(body["response_format"]["json_schema"], schema);
     }
 
     #[tokio::test]
-    async fn test_cloud_provider_list_models() {
-        let provider = CloudProvider::new(
-            "https://api.openai.com/v1/chat/completions".to_string(),
-            "test-key".to_string(),
+    async fn test_cohere_provider_chat() {
+        let (url, mut requests) = spawn_stub_server(vec![(200, fixture("cohere/chat.json"))]).await;
+
+        let provider = CohereProvider::new(url, "co-test".to_string(), "command-r".to_string());
+        let reply = provider
+            .generate_response(
+                &Conversation::new("", "disk usage"),
+                &crate::agents::profiles::SHELL_EXPERT,
+            )
+            .await
+            .unwrap();
+        assert!(reply.text.starts_with("Command: df -h"));
+        // Billed units, not the raw token counts
+        assert_eq!(
+            reply.usage,
+            Some(TokenUsage {
+                prompt_tokens: 58,
+                completion_tokens: 16
+            })
         );
 
-        // This will fail in tests since we don't have a real API key
-        // but we can test that the method exists
-        let result = provider.list_models().await;
-        // Just ensure the method can be called
-        assert!(result.is_err() || result.is_ok());
+        let request = requests.recv().await.unwrap();
+        assert!(request.starts_with("POST /v2/chat"));
+        assert!(request
+            .to_lowercase()
+            .contains("authorization: bearer co-test"));
+        assert!(request.contains(r#""model":"command-r""#));
     }
 
     #[tokio::test]
-    async fn test_provider_manager_empty_list_models() {
-        let manager = ProviderManager::new();
+    async fn test_cohere_provider_stream_and_errors() {
+        let (url, _requests) = spawn_stub_server(vec![(200, fixture("cohere/stream.sse"))]).await;
+        let provider = CohereProvider::new(url, "key".to_string(), "command-r".to_string());
+        let chunks: Vec<StreamChunk> = provider
+            .generate_response_stream(
+                &Conversation::new("", "disk usage"),
+                &crate::agents::profiles::SHELL_EXPERT,
+            )
+            .await
+            .unwrap()
+            .map(|item| item.unwrap())
+            .collect()
+            .await;
+        assert_eq!(
+            chunks,
+            vec![
+                StreamChunk::text("Command: "),
+                StreamChunk::text("df -h"),
+                StreamChunk::usage(TokenUsage {
+                    prompt_tokens: 58,
+                    completion_tokens: 5
+                })
+            ]
+        );
 
-        // Should fail when no providers are added
-        let result = manager.list_models().await;
-        assert!(result.is_err());
-        assert!(result
+        let (url, _requests) =
+            spawn_stub_server(vec![(401, fixture("cohere/error_unauthorized.json"))]).await;
+        let provider = CohereProvider::new(url, "bad".to_string(), "command-r".to_string());
+        let err = provider
+            .generate_response(
+                &Conversation::new("", "hi"),
+                &crate::agents::profiles::GENERAL_CLIAI,
+            )
+            .await
             .unwrap_err()
-            .to_string()
-            .contains("No providers available"));
+            .to_string();
+        assert!(err.contains("invalid api token"));
+        assert!(err.contains("cliai test-key cohere"));
     }
 
     #[tokio::test]
-    async fn test_provider_manager_empty_is_available() {
-        let manager = ProviderManager::new();
+    async fn test_cohere_provider_list_models() {
+        let (url, mut requests) =
+            spawn_stub_server(vec![(200, fixture("cohere/models.json"))]).await;
 
-        // Should return false when no providers are added
-        assert!(!manager.is_any_provider_available().await);
+        let provider = CohereProvider::new(url, "key".to_string(), "command-r".to_string());
+        assert_eq!(provider.get_provider_type(), ProviderType::Cohere);
+        assert_eq!(
+            provider.list_models().await.unwrap(),
+            vec!["command-r-plus".to_string(), "command-r".to_string()]
+        );
+        assert!(requests
+            .recv()
+            .await
+            .unwrap()
+            .starts_with("GET /v1/models?endpoint=chat"));
     }
 
     // Note: Integration tests for actual API calls would require running services
@@ -0,0 +1,692 @@
+use crate::agents::profiles::AgentProfile;
+use crate::config::Config;
+use crate::logging::{get_logger, LogCategory};
+use crate::providers::{
+    AIProvider, Conversation, ProviderResponse, ProviderType, StreamChunk, TokenStream, TokenUsage,
+};
+use crate::response_cache::stable_hash;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Environment variable selecting the provider mode (passthrough, record or replay)
+pub const PROVIDER_MODE_ENV: &str = "CLIAI_PROVIDER_MODE";
+
+/// Environment variable with the fixture file used for recording and replaying
+pub const REPLAY_FIXTURE_ENV: &str = "CLIAI_REPLAY_FIXTURE";
+
+/// How provider calls are routed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReplayMode {
+    /// Call the providers directly
+    #[default]
+    Passthrough,
+    /// Call the providers and save every response to the fixture file
+    Record,
+    /// Answer from the fixture file without contacting any provider
+    Replay,
+}
+
+impl ReplayMode {
+    pub fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "passthrough" | "off" | "" => Ok(ReplayMode::Passthrough),
+            "record" => Ok(ReplayMode::Record),
+            "replay" => Ok(ReplayMode::Replay),
+            other => Err(anyhow!(
+                "unknown provider mode '{}' (expected passthrough, record or replay)",
+                other
+            )),
+        }
+    }
+}
+
+/// Effective record/replay settings: the environment overrides the config
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplaySettings {
+    pub mode: ReplayMode,
+    pub fixture_path: PathBuf,
+}
+
+#[allow(dead_code)]
+impl ReplaySettings {
+    /// Settings from the config and the `CLIAI_PROVIDER_MODE` / `CLIAI_REPLAY_FIXTURE`
+    /// environment variables
+    pub fn from_config(config: &Config) -> Result<Self> {
+        Self::resolve(
+            config.provider_mode,
+            config.replay_fixture.as_deref(),
+            std::env::var(PROVIDER_MODE_ENV).ok().as_deref(),
+            std::env::var(REPLAY_FIXTURE_ENV).ok().as_deref(),
+        )
+    }
+
+    /// Combine configured and environment values; environment values win
+    pub fn resolve(
+        configured_mode: ReplayMode,
+        configured_fixture: Option<&str>,
+        env_mode: Option<&str>,
+        env_fixture: Option<&str>,
+    ) -> Result<Self> {
+        let mode = match env_mode {
+            Some(value) => ReplayMode::parse(value)?,
+            None => configured_mode,
+        };
+
+        let fixture_path = match env_fixture.or(configured_fixture) {
+            Some(path) if !path.trim().is_empty() => PathBuf::from(path),
+            _ => Self::default_fixture_path()?,
+        };
+
+        Ok(Self { mode, fixture_path })
+    }
+
+    /// Default fixture file in the data directory
+    pub fn default_fixture_path() -> Result<PathBuf> {
+        let data_dir = dirs::data_dir()
+            .or_else(dirs::config_dir)
+            .ok_or_else(|| anyhow!("Could not find data directory"))?;
+        Ok(data_dir.join("cliai").join("replay.json"))
+    }
+}
+
+/// A recorded response
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReplayEntry {
+    pub agent: String,
+    /// The user message of the request, kept readable so fixtures can be reviewed
+    pub question: String,
+    pub response: String,
+    pub model: String,
+    #[serde(default)]
+    pub usage: Option<TokenUsage>,
+}
+
+/// Recorded responses keyed by agent and prompt hash, stored as JSON. Recording merges
+/// into the file under an exclusive lock so parallel test runs do not drop entries.
+#[derive(Debug)]
+pub struct ReplayFixtures {
+    path: PathBuf,
+    entries: BTreeMap<String, ReplayEntry>,
+}
+
+#[allow(dead_code)]
+impl ReplayFixtures {
+    /// Load fixtures from a file; a missing file yields no entries
+    pub fn load(path: &Path) -> Result<Self> {
+        let entries = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| anyhow!("Invalid replay fixture {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(anyhow!("Failed to read {}: {}", path.display(), e)),
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    /// Fixture key of a request: the agent and a hash of the complete prompt
+    pub fn key(agent: &AgentProfile, conversation: &Conversation) -> String {
+        let schema = conversation
+            .response_schema
+            .as_ref()
+            .map(|schema| schema.to_string())
+            .unwrap_or_default();
+        format!(
+            "{}:{:016x}",
+            agent.name,
+            stable_hash(&format!("{}\n{}", conversation.to_prompt(), schema))
+        )
+    }
+
+    /// Recorded response for a request. The exact prompt is preferred; otherwise an
+    /// entry of the same agent with the same user message is used, since system prompts
+    /// carry machine-specific context (working directory, OS, file listings).
+    pub fn lookup(
+        &self,
+        agent: &AgentProfile,
+        conversation: &Conversation,
+    ) -> Option<&ReplayEntry> {
+        self.entries
+            .get(&Self::key(agent, conversation))
+            .or_else(|| {
+                self.entries
+                    .values()
+                    .find(|entry| entry.agent == agent.name && entry.question == conversation.user)
+            })
+    }
+
+    /// Save a response for a request, merging with entries recorded by other processes
+    pub fn record(
+        &mut self,
+        agent: &AgentProfile,
+        conversation: &Conversation,
+        response: &ProviderResponse,
+    ) -> Result<()> {
+        let key = Self::key(agent, conversation);
+        let entry = ReplayEntry {
+            agent: agent.name.to_string(),
+            question: conversation.user.clone(),
+            response: response.text.clone(),
+            model: response.model.clone(),
+            usage: response.usage,
+        };
+
+        if let Some(parent) = self.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)?;
+        file.lock()
+            .map_err(|e| anyhow!("Failed to lock replay fixture: {}", e))?;
+
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        let mut entries: BTreeMap<String, ReplayEntry> =
+            serde_json::from_str(&content).unwrap_or_default();
+        entries.insert(key, entry);
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(serde_json::to_string_pretty(&entries)?.as_bytes())?;
+        file.flush()?;
+        file.unlock()?;
+
+        self.entries = entries;
+        Ok(())
+    }
+
+    /// Models that appear in the recorded responses
+    pub fn models(&self) -> Vec<String> {
+        let mut models: Vec<String> = self.entries.values().map(|e| e.model.clone()).collect();
+        models.sort();
+        models.dedup();
+        models
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Fixtures shared by the replay providers of one orchestrator
+pub type SharedFixtures = Arc<std::sync::Mutex<ReplayFixtures>>;
+
+/// Provider wrapper that records responses of the wrapped provider to a fixture file,
+/// or answers from that file without contacting the provider at all
+pub struct ReplayProvider {
+    inner: Box<dyn AIProvider>,
+    mode: ReplayMode,
+    fixtures: SharedFixtures,
+}
+
+#[allow(dead_code)]
+impl ReplayProvider {
+    pub fn new(inner: Box<dyn AIProvider>, mode: ReplayMode, fixtures: SharedFixtures) -> Self {
+        Self {
+            inner,
+            mode,
+            fixtures,
+        }
+    }
+
+    /// Answer a request from the fixtures
+    fn replay(
+        &self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+    ) -> Result<ProviderResponse> {
+        let fixtures = self
+            .fixtures
+            .lock()
+            .map_err(|_| anyhow!("Replay fixtures are unavailable"))?;
+
+        let entry = fixtures.lookup(agent, conversation).ok_or_else(|| {
+            let question: String = conversation.user.chars().take(80).collect();
+            anyhow!(
+                "No recorded response for {} (\"{}\") in {}. Record it with {}=record",
+                agent.name,
+                question,
+                fixtures.path().display(),
+                PROVIDER_MODE_ENV
+            )
+        })?;
+
+        Ok(ProviderResponse::new(
+            entry.response.clone(),
+            entry.usage,
+            &entry.model,
+            self.inner.get_provider_type(),
+        ))
+    }
+
+    /// Save a live response; failures are logged so recording never breaks a request
+    fn save(
+        fixtures: &SharedFixtures,
+        agent: &AgentProfile,
+        conversation: &Conversation,
+        response: &ProviderResponse,
+    ) {
+        let result = match fixtures.lock() {
+            Ok(mut fixtures) => fixtures.record(agent, conversation, response),
+            Err(_) => Err(anyhow!("Replay fixtures are unavailable")),
+        };
+
+        if let Err(e) = result {
+            if let Ok(logger) = get_logger() {
+                if let Ok(logger_guard) = logger.lock() {
+                    let _ = logger_guard.log_warning(
+                        LogCategory::Provider,
+                        format!("Failed to record response: {}", e),
+                        None,
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl AIProvider for ReplayProvider {
+    async fn generate_response(
+        &self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+    ) -> Result<ProviderResponse> {
+        match self.mode {
+            ReplayMode::Passthrough => self.inner.generate_response(conversation, agent).await,
+            ReplayMode::Replay => self.replay(conversation, agent),
+            ReplayMode::Record => {
+                let response = self.inner.generate_response(conversation, agent).await?;
+                Self::save(&self.fixtures, agent, conversation, &response);
+                Ok(response)
+            }
+        }
+    }
+
+    async fn generate_response_stream(
+        &self,
+        conversation: &Conversation,
+        agent: &AgentProfile,
+    ) -> Result<TokenStream> {
+        match self.mode {
+            ReplayMode::Passthrough => {
+                self.inner
+                    .generate_response_stream(conversation, agent)
+                    .await
+            }
+            ReplayMode::Replay => {
+                let response = self.replay(conversation, agent)?;
+                let chunk = StreamChunk {
+                    text: response.text,
+                    usage: response.usage,
+                };
+                Ok(Box::pin(stream::once(async move { Ok(chunk) })))
+            }
+            ReplayMode::Record => {
+                let inner_stream = self
+                    .inner
+                    .generate_response_stream(conversation, agent)
+                    .await?;
+
+                // Pass chunks through unchanged and save the response once the stream
+                // completes without error
+                struct Recording {
+                    inner: TokenStream,
+                    text: String,
+                    usage: Option<TokenUsage>,
+                    failed: bool,
+                    fixtures: SharedFixtures,
+                    agent: AgentProfile,
+                    conversation: Conversation,
+                    model: String,
+                    provider: ProviderType,
+                }
+
+                let recording = Recording {
+                    inner: inner_stream,
+                    text: String::new(),
+                    usage: None,
+                    failed: false,
+                    fixtures: self.fixtures.clone(),
+                    agent: agent.clone(),
+                    conversation: conversation.clone(),
+                    model: self.inner.get_model_name().to_string(),
+                    provider: self.inner.get_provider_type(),
+                };
+
+                Ok(Box::pin(stream::unfold(
+                    recording,
+                    |mut recording| async move {
+                        match recording.inner.next().await {
+                            Some(item) => {
+                                match &item {
+                                    Ok(chunk) => {
+                                        recording.text.push_str(&chunk.text);
+                                        if chunk.usage.is_some() {
+                                            recording.usage = chunk.usage;
+                                        }
+                                    }
+                                    Err(_) => recording.failed = true,
+                                }
+                                Some((item, recording))
+                            }
+                            None => {
+                                if !recording.failed && !recording.text.is_empty() {
+                                    let response = ProviderResponse::new(
+                                        std::mem::take(&mut recording.text),
+                                        recording.usage,
+                                        &recording.model,
+                                        recording.provider.clone(),
+                                    );
+                                    Self::save(
+                                        &recording.fixtures,
+                                        &recording.agent,
+                                        &recording.conversation,
+                                        &response,
+                                    );
+                                }
+                                None
+                            }
+                        }
+                    },
+                )))
+            }
+        }
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>> {
+        match self.mode {
+            ReplayMode::Replay => Ok(self
+                .fixtures
+                .lock()
+                .map(|fixtures| fixtures.models())
+                .unwrap_or_default()),
+            _ => self.inner.list_models().await,
+        }
+    }
+
+    fn get_provider_type(&self) -> ProviderType {
+        self.inner.get_provider_type()
+    }
+
+    async fn is_available(&self) -> bool {
+        match self.mode {
+            ReplayMode::Replay => true,
+            _ => self.inner.is_available().await,
+        }
+    }
+
+    fn get_name(&self) -> &'static str {
+        self.inner.get_name()
+    }
+
+    fn get_model_name(&self) -> &str {
+        self.inner.get_model_name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::profiles::{GENERAL_CLIAI, PLANNER_AGENT, SHELL_EXPERT};
+    use crate::agents::Orchestrator;
+    use crate::history::History;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use tempfile::TempDir;
+
+    /// Inner provider answering with fixed text and counting its calls
+    struct CountingProvider {
+        answer: &'static str,
+        calls: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl AIProvider for CountingProvider {
+        async fn generate_response(
+            &self,
+            _conversation: &Conversation,
+            _agent: &AgentProfile,
+        ) -> Result<ProviderResponse> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(ProviderResponse::new(
+                self.answer.to_string(),
+                Some(TokenUsage {
+                    prompt_tokens: 12,
+                    completion_tokens: 3,
+                }),
+                "counting",
+                ProviderType::Local,
+            ))
+        }
+
+        async fn list_models(&self) -> Result<Vec<String>> {
+            Ok(vec!["counting".to_string()])
+        }
+
+        fn get_provider_type(&self) -> ProviderType {
+            ProviderType::Local
+        }
+
+        async fn is_available(&self) -> bool {
+            false
+        }
+
+        fn get_name(&self) -> &'static str {
+            "Counting"
+        }
+
+        fn get_model_name(&self) -> &str {
+            "counting"
+        }
+    }
+
+    fn replay_provider(mode: ReplayMode, path: &Path, calls: &Arc<AtomicUsize>) -> ReplayProvider {
+        let fixtures = ReplayFixtures::load(path).unwrap();
+        ReplayProvider::new(
+            Box::new(CountingProvider {
+                answer: "Command: ls -la",
+                calls: calls.clone(),
+            }),
+            mode,
+            Arc::new(Mutex::new(fixtures)),
+        )
+    }
+
+    #[test]
+    fn test_mode_resolution() {
+        let settings =
+            ReplaySettings::resolve(ReplayMode::Record, Some("fixture.json"), None, None).unwrap();
+        assert_eq!(settings.mode, ReplayMode::Record);
+        assert_eq!(settings.fixture_path, PathBuf::from("fixture.json"));
+
+        // Environment values win over the config
+        let settings = ReplaySettings::resolve(
+            ReplayMode::Record,
+            Some("fixture.json"),
+            Some("Replay"),
+            Some("ci.json"),
+        )
+        .unwrap();
+        assert_eq!(settings.mode, ReplayMode::Replay);
+        assert_eq!(settings.fixture_path, PathBuf::from("ci.json"));
+
+        let settings = ReplaySettings::resolve(ReplayMode::Passthrough, None, None, None).unwrap();
+        assert!(settings.fixture_path.ends_with("cliai/replay.json"));
+
+        assert!(
+            ReplaySettings::resolve(ReplayMode::Passthrough, None, Some("rewind"), None).is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("fixtures").join("replay.json");
+        let conversation = Conversation::new("system prompt", "list files");
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let recorder = replay_provider(ReplayMode::Record, &path, &calls);
+        let recorded = recorder
+            .generate_response(&conversation, &SHELL_EXPERT)
+            .await
+            .unwrap();
+        assert_eq!(recorded.text, "Command: ls -la");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        let fixtures = ReplayFixtures::load(&path).unwrap();
+        assert_eq!(fixtures.len(), 1);
+        assert_eq!(fixtures.models(), vec!["counting".to_string()]);
+
+        let replayer = replay_provider(ReplayMode::Replay, &path, &calls);
+        assert!(replayer.is_available().await);
+        let replayed = replayer
+            .generate_response(&conversation, &SHELL_EXPERT)
+            .await
+            .unwrap();
+        assert_eq!(replayed.text, recorded.text);
+        assert_eq!(replayed.usage, recorded.usage);
+        assert_eq!(replayed.model, "counting");
+        // The inner provider is not contacted when replaying
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_replay_falls_back_to_question_and_reports_misses() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("replay.json");
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let recorder = replay_provider(ReplayMode::Record, &path, &calls);
+        recorder
+            .generate_response(
+                &Conversation::new("cwd: /home/alice", "list files"),
+                &SHELL_EXPERT,
+            )
+            .await
+            .unwrap();
+
+        let replayer = replay_provider(ReplayMode::Replay, &path, &calls);
+
+        // A different system prompt still finds the recording for the same question
+        let replayed = replayer
+            .generate_response(
+                &Conversation::new("cwd: /home/bob", "list files"),
+                &SHELL_EXPERT,
+            )
+            .await
+            .unwrap();
+        assert_eq!(replayed.text, "Command: ls -la");
+
+        // Other agents and questions are misses
+        let error = replayer
+            .generate_response(&Conversation::new("", "list files"), &PLANNER_AGENT)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("No recorded response for Planner"));
+        assert!(error.contains(PROVIDER_MODE_ENV));
+        assert!(replayer
+            .generate_response(&Conversation::new("", "show disk usage"), &SHELL_EXPERT)
+            .await
+            .is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_streaming_is_recorded() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("replay.json");
+        let conversation = Conversation::new("system prompt", "list files");
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let recorder = replay_provider(ReplayMode::Record, &path, &calls);
+        let mut stream = recorder
+            .generate_response_stream(&conversation, &GENERAL_CLIAI)
+            .await
+            .unwrap();
+        let mut text = String::new();
+        while let Some(chunk) = stream.next().await {
+            text.push_str(&chunk.unwrap().text);
+        }
+        assert_eq!(text, "Command: ls -la");
+
+        let replayer = replay_provider(ReplayMode::Replay, &path, &calls);
+        let mut stream = replayer
+            .generate_response_stream(&conversation, &GENERAL_CLIAI)
+            .await
+            .unwrap();
+        let chunk = stream.next().await.unwrap().unwrap();
+        assert_eq!(chunk.text, "Command: ls -la");
+        assert_eq!(chunk.usage.map(|u| u.completion_tokens), Some(3));
+        assert!(stream.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_orchestrator_pipeline_replays_offline() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("replay.json");
+        let question = "explain what a symlink is";
+        let answer = "A symlink is a file that points to another path.";
+
+        let mut entries = BTreeMap::new();
+        for (agent, response) in [
+            ("Planner", r#"{"category":"GENERAL","commands":[]}"#),
+            ("CLIAI", answer),
+        ] {
+            entries.insert(
+                format!("{}:recorded", agent),
+                ReplayEntry {
+                    agent: agent.to_string(),
+                    question: question.to_string(),
+                    response: response.to_string(),
+                    model: "recorded-model".to_string(),
+                    usage: None,
+                },
+            );
+        }
+        fs::write(&path, serde_json::to_string_pretty(&entries).unwrap()).unwrap();
+
+        // Nothing listens on this port; replay mode never connects
+        let config: Config = serde_json::from_value(serde_json::json!({
+            "model": "recorded-model",
+            "ollama_url": "http://127.0.0.1:9",
+            "provider_mode": "replay",
+            "replay_fixture": path.display().to_string(),
+        }))
+        .unwrap();
+
+        let mut orchestrator = Orchestrator::new(config, History { turns: vec![] });
+        let response = orchestrator.process(question).await.unwrap();
+        assert!(
+            response.contains(answer),
+            "unexpected response: {}",
+            response
+        );
+    }
+}
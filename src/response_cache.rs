@@ -0,0 +1,281 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Stable 64-bit FNV-1a hash. Unlike `DefaultHasher` the result does not change
+/// between Rust releases, so it is safe to persist in the on-disk cache.
+pub fn stable_hash(text: &str) -> u64 {
+    const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+    const FNV_PRIME: u64 = 0x100000001b3;
+
+    text.bytes().fold(FNV_OFFSET, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// A cached provider response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    response: String,
+    /// Fingerprint of the working directory context the response was generated in
+    context_fingerprint: u64,
+    /// Unix timestamp (seconds) of when the entry was stored
+    created_at: u64,
+    /// Logical clock value of the last access, used for LRU eviction
+    last_used: u64,
+}
+
+/// On-disk representation of the cache
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CacheFile {
+    clock: u64,
+    entries: HashMap<String, CacheEntry>,
+}
+
+/// Size-bounded LRU cache with a TTL for AI responses, persisted in the cache
+/// directory so repeated questions survive across CLI invocations
+#[derive(Debug)]
+pub struct ResponseCache {
+    path: Option<PathBuf>,
+    data: CacheFile,
+    max_entries: usize,
+    ttl: Duration,
+}
+
+#[allow(dead_code)]
+impl ResponseCache {
+    /// Create an in-memory cache (nothing is written to disk)
+    pub fn in_memory(max_entries: usize, ttl: Duration) -> Self {
+        Self {
+            path: None,
+            data: CacheFile::default(),
+            max_entries,
+            ttl,
+        }
+    }
+
+    /// Load the cache from the default location, starting empty if the file is
+    /// missing or unreadable
+    pub fn load(max_entries: usize, ttl: Duration) -> Self {
+        match Self::get_cache_path() {
+            Ok(path) => Self::load_from(path, max_entries, ttl),
+            Err(_) => Self::in_memory(max_entries, ttl),
+        }
+    }
+
+    /// Load the cache from a specific file
+    pub fn load_from(path: PathBuf, max_entries: usize, ttl: Duration) -> Self {
+        let data = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        Self {
+            path: Some(path),
+            data,
+            max_entries,
+            ttl,
+        }
+    }
+
+    /// Look up a response. Expired entries and entries generated under a different
+    /// working directory context are removed instead of returned.
+    pub fn get(&mut self, key: &str, context_fingerprint: u64) -> Option<String> {
+        self.get_at(key, context_fingerprint, Self::now())
+    }
+
+    fn get_at(&mut self, key: &str, context_fingerprint: u64, now: u64) -> Option<String> {
+        let entry = self.data.entries.get(key)?;
+
+        let expired = now.saturating_sub(entry.created_at) >= self.ttl.as_secs();
+        if expired || entry.context_fingerprint != context_fingerprint {
+            self.data.entries.remove(key);
+            let _ = self.save();
+            return None;
+        }
+
+        self.data.clock += 1;
+        let clock = self.data.clock;
+        let response = self.data.entries.get_mut(key).map(|entry| {
+            entry.last_used = clock;
+            entry.response.clone()
+        });
+        let _ = self.save();
+        response
+    }
+
+    /// Store a response, evicting the least recently used entries when full
+    pub fn put(&mut self, key: &str, context_fingerprint: u64, response: &str) {
+        self.put_at(key, context_fingerprint, response, Self::now());
+    }
+
+    fn put_at(&mut self, key: &str, context_fingerprint: u64, response: &str, now: u64) {
+        if self.max_entries == 0 {
+            return;
+        }
+
+        // Drop expired entries first so they never push out live ones
+        let ttl = self.ttl.as_secs();
+        self.data
+            .entries
+            .retain(|_, entry| now.saturating_sub(entry.created_at) < ttl);
+
+        self.data.clock += 1;
+        self.data.entries.insert(
+            key.to_string(),
+            CacheEntry {
+                response: response.to_string(),
+                context_fingerprint,
+                created_at: now,
+                last_used: self.data.clock,
+            },
+        );
+
+        while self.data.entries.len() > self.max_entries {
+            let oldest = self
+                .data
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            match oldest {
+                Some(oldest) => {
+                    self.data.entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+
+        let _ = self.save();
+    }
+
+    /// Remove a single entry
+    pub fn remove(&mut self, key: &str) {
+        if self.data.entries.remove(key).is_some() {
+            let _ = self.save();
+        }
+    }
+
+    /// Remove all entries
+    pub fn clear(&mut self) -> Result<()> {
+        self.data = CacheFile::default();
+        self.save()
+    }
+
+    /// Number of cached entries
+    pub fn len(&self) -> usize {
+        self.data.entries.len()
+    }
+
+    /// Whether the cache holds no entries
+    pub fn is_empty(&self) -> bool {
+        self.data.entries.is_empty()
+    }
+
+    /// Write the cache to disk (no-op for in-memory caches)
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(&self.data)?)?;
+        Ok(())
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+
+    /// Get cache file path
+    pub fn get_cache_path() -> Result<PathBuf> {
+        let cache_dir = dirs::cache_dir()
+            .or_else(dirs::config_dir)
+            .ok_or_else(|| anyhow!("Could not find cache directory"))?;
+        Ok(cache_dir.join("cliai").join("response_cache.json"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stable_hash_is_deterministic() {
+        assert_eq!(stable_hash("show free RAM"), stable_hash("show free RAM"));
+        assert_ne!(stable_hash("show free RAM"), stable_hash("show free ram"));
+        // FNV-1a reference value for the empty string
+        assert_eq!(stable_hash(""), 0xcbf29ce484222325);
+    }
+
+    #[test]
+    fn test_cache_hit_and_miss() {
+        let mut cache = ResponseCache::in_memory(10, Duration::from_secs(60));
+        assert!(cache.get("key", 1).is_none());
+
+        cache.put("key", 1, "Command: free -h");
+        assert_eq!(cache.get("key", 1), Some("Command: free -h".to_string()));
+    }
+
+    #[test]
+    fn test_cache_ttl_expiry() {
+        let mut cache = ResponseCache::in_memory(10, Duration::from_secs(60));
+        cache.put_at("key", 1, "value", 1000);
+
+        assert!(cache.get_at("key", 1, 1059).is_some());
+        assert!(cache.get_at("key", 1, 1060).is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_cache_context_change_invalidates() {
+        let mut cache = ResponseCache::in_memory(10, Duration::from_secs(60));
+        cache.put("key", 1, "value");
+
+        assert!(cache.get("key", 2).is_none());
+        // The stale entry is gone even when the old context comes back
+        assert!(cache.get("key", 1).is_none());
+    }
+
+    #[test]
+    fn test_cache_lru_eviction() {
+        let mut cache = ResponseCache::in_memory(2, Duration::from_secs(60));
+        cache.put("a", 0, "1");
+        cache.put("b", 0, "2");
+
+        // Touch "a" so "b" becomes the least recently used entry
+        assert!(cache.get("a", 0).is_some());
+        cache.put("c", 0, "3");
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get("a", 0).is_some());
+        assert!(cache.get("b", 0).is_none());
+        assert!(cache.get("c", 0).is_some());
+    }
+
+    #[test]
+    fn test_cache_persists_to_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("response_cache.json");
+
+        let mut cache = ResponseCache::load_from(path.clone(), 10, Duration::from_secs(60));
+        cache.put("key", 7, "persisted");
+
+        let mut reloaded = ResponseCache::load_from(path, 10, Duration::from_secs(60));
+        assert_eq!(reloaded.get("key", 7), Some("persisted".to_string()));
+    }
+
+    #[test]
+    fn test_cache_zero_capacity_stores_nothing() {
+        let mut cache = ResponseCache::in_memory(0, Duration::from_secs(60));
+        cache.put("key", 0, "value");
+        assert!(cache.is_empty());
+    }
+}
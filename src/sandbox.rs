@@ -0,0 +1,482 @@
+use crate::cancellation::CancellationToken;
+use crate::execution::CommandOutcome;
+use crate::os_context::ExecutionShell;
+use anyhow::{anyhow, bail, Context, Result};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::SystemTime;
+use tokio::process::Command;
+
+/// Largest working directory (total file size) copied into a sandbox
+pub const SANDBOX_COPY_BYTES_LIMIT: u64 = 256 * 1024 * 1024;
+
+/// Most files and directories copied into a sandbox
+pub const SANDBOX_COPY_ENTRIES_LIMIT: usize = 20_000;
+
+/// Exit code of the setup script when the sandbox itself could not be built
+const SETUP_FAILED_EXIT_CODE: i32 = 125;
+const SETUP_ERROR_PREFIX: &str = "cliai-sandbox:";
+
+/// System paths made read-only inside an `unshare` sandbox; the working directory copy
+/// and a fresh /tmp are the only writable places
+const READ_ONLY_PATHS: &[&str] = &[
+    "/usr", "/bin", "/sbin", "/lib", "/lib32", "/lib64", "/libx32", "/etc", "/opt", "/srv", "/var",
+    "/home", "/root", "/mnt", "/media", "/boot",
+];
+
+/// Runs inside the new namespaces with the copy, the real working directory, the command
+/// and the shell to run it with as `$1` to `$4`
+const UNSHARE_SETUP_SCRIPT: &str = r#"
+fail() { echo "cliai-sandbox: $1" >&2; exit 125; }
+for d in $CLIAI_READ_ONLY_PATHS; do
+  if [ -d "$d" ] && [ ! -L "$d" ]; then
+    mount --bind "$d" "$d" 2>/dev/null && mount -o remount,bind,ro "$d" "$d" 2>/dev/null
+  fi
+done
+mount -o remount,bind,ro / 2>/dev/null
+mount -t tmpfs tmpfs /tmp || fail "could not mount a private /tmp"
+mkdir -p "$2" && mount --bind "$1" "$2" && mount -o remount,bind,rw "$2" "$2" \
+  || fail "could not mount the working directory copy"
+cd "$2" || fail "could not enter the working directory"
+exec "$4" -c "$3"
+"#;
+
+/// Tool used to isolate a preview run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxBackend {
+    Bubblewrap,
+    Unshare,
+}
+
+impl fmt::Display for SandboxBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SandboxBackend::Bubblewrap => write!(f, "bubblewrap"),
+            SandboxBackend::Unshare => write!(f, "unshare"),
+        }
+    }
+}
+
+/// A change a previewed command made to the working directory, relative to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileChange {
+    Created(PathBuf),
+    Modified(PathBuf),
+    Deleted(PathBuf),
+}
+
+impl fmt::Display for FileChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileChange::Created(path) => write!(f, "+ {}", path.display()),
+            FileChange::Modified(path) => write!(f, "~ {}", path.display()),
+            FileChange::Deleted(path) => write!(f, "- {}", path.display()),
+        }
+    }
+}
+
+/// What a command did when run in the sandbox
+#[derive(Debug, Clone)]
+pub struct SandboxReport {
+    pub backend: SandboxBackend,
+    pub outcome: CommandOutcome,
+    pub changes: Vec<FileChange>,
+}
+
+/// Metadata compared before and after a preview run
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntryState {
+    Dir,
+    File {
+        len: u64,
+        modified: Option<SystemTime>,
+        mode: u32,
+    },
+    Symlink(PathBuf),
+}
+
+/// Find a sandbox tool that works on this machine. Fails with a readable reason when
+/// neither bubblewrap nor user namespaces are available.
+pub async fn detect_backend() -> Result<SandboxBackend> {
+    if !cfg!(target_os = "linux") {
+        bail!("sandbox previews need Linux namespaces");
+    }
+
+    let bwrap = probe(
+        "bwrap",
+        &[
+            "--ro-bind",
+            "/",
+            "/",
+            "--unshare-all",
+            "--die-with-parent",
+            "true",
+        ],
+    )
+    .await;
+    if bwrap.is_ok() {
+        return Ok(SandboxBackend::Bubblewrap);
+    }
+
+    match probe(
+        "unshare",
+        &["--user", "--map-root-user", "--mount", "--net", "true"],
+    )
+    .await
+    {
+        Ok(()) => Ok(SandboxBackend::Unshare),
+        Err(unshare) => Err(anyhow!(
+            "neither bubblewrap nor user namespaces are usable (bwrap: {}; unshare: {})",
+            bwrap.unwrap_err(),
+            unshare
+        )),
+    }
+}
+
+async fn probe(program: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => anyhow!("not installed"),
+            _ => anyhow!(e),
+        })?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(anyhow!(
+            "{}",
+            stderr.lines().next().unwrap_or("probe failed").trim()
+        ))
+    }
+}
+
+/// Run `command` against a throwaway copy of `cwd` with read-only system paths, a private
+/// /tmp and no network, and report its output and what it changed in the copy
+pub async fn preview_command(
+    command: &str,
+    shell: &ExecutionShell,
+    cwd: &Path,
+    cancellation: &CancellationToken,
+) -> Result<SandboxReport> {
+    let backend = detect_backend().await?;
+    let staging = StagingDir::create()?;
+    copy_tree(cwd, &staging.path)
+        .with_context(|| format!("Could not copy {} into the sandbox", cwd.display()))?;
+    let before = snapshot(&staging.path)?;
+
+    let mut child = match backend {
+        SandboxBackend::Bubblewrap => {
+            let mut child = Command::new("bwrap");
+            child
+                .args(["--ro-bind", "/", "/", "--dev", "/dev", "--proc", "/proc"])
+                .args(["--tmpfs", "/tmp", "--bind"])
+                .arg(&staging.path)
+                .arg(cwd)
+                .arg("--chdir")
+                .arg(cwd)
+                .args(["--unshare-all", "--die-with-parent"])
+                .arg(&shell.program)
+                .args(["-c", command]);
+            child
+        }
+        SandboxBackend::Unshare => {
+            let mut child = Command::new("unshare");
+            child
+                .args(["--user", "--map-root-user", "--mount", "--net"])
+                .args(["--pid", "--fork", "--mount-proc", "--kill-child"])
+                .args(["sh", "-c", UNSHARE_SETUP_SCRIPT, "sh"])
+                .arg(&staging.path)
+                .arg(cwd)
+                .arg(command)
+                .arg(&shell.program)
+                .env("CLIAI_READ_ONLY_PATHS", READ_ONLY_PATHS.join(" "));
+            child
+        }
+    };
+    let child = child
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Could not start the {} sandbox", backend))?;
+
+    let output = cancellation
+        .run(async { Ok(child.wait_with_output().await?) })
+        .await?;
+    let outcome = CommandOutcome {
+        exit_code: output.status.code(),
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+    };
+    if outcome.exit_code == Some(SETUP_FAILED_EXIT_CODE) {
+        if let Some(line) = outcome
+            .stderr
+            .lines()
+            .find(|line| line.starts_with(SETUP_ERROR_PREFIX))
+        {
+            bail!(
+                "Sandbox setup failed: {}",
+                line[SETUP_ERROR_PREFIX.len()..].trim()
+            );
+        }
+    }
+
+    let after = snapshot(&staging.path)?;
+    Ok(SandboxReport {
+        backend,
+        outcome,
+        changes: diff_snapshots(&before, &after),
+    })
+}
+
+/// Temporary directory for the working directory copy, removed on drop. It lives in the
+/// user's cache directory rather than /tmp, which is replaced inside the sandbox.
+struct StagingDir {
+    path: PathBuf,
+}
+
+impl StagingDir {
+    fn create() -> Result<Self> {
+        let base = dirs::cache_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("cliai")
+            .join("sandbox");
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let path = base.join(format!("{}-{}", std::process::id(), nanos));
+        fs::create_dir_all(&path)
+            .with_context(|| format!("Could not create {}", path.display()))?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for StagingDir {
+    fn drop(&mut self) {
+        let _ = make_writable(&self.path);
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Commands may leave read-only directories behind, which would block removal
+fn make_writable(dir: &Path) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                make_writable(&entry.path())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Copy a directory tree, keeping symlinks as links, within the sandbox size limits
+pub fn copy_tree(src: &Path, dst: &Path) -> Result<()> {
+    let mut budget = (SANDBOX_COPY_BYTES_LIMIT, SANDBOX_COPY_ENTRIES_LIMIT);
+    copy_dir(src, dst, &mut budget)
+}
+
+fn copy_dir(src: &Path, dst: &Path, budget: &mut (u64, usize)) -> Result<()> {
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let target = dst.join(entry.file_name());
+
+        if budget.1 == 0 {
+            bail!(
+                "more than {} files; too large for a sandbox preview",
+                SANDBOX_COPY_ENTRIES_LIMIT
+            );
+        }
+        budget.1 -= 1;
+
+        if file_type.is_symlink() {
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(fs::read_link(entry.path())?, &target)?;
+        } else if file_type.is_dir() {
+            fs::create_dir(&target)?;
+            copy_dir(&entry.path(), &target, budget)?;
+        } else if file_type.is_file() {
+            let len = entry.metadata()?.len();
+            if len > budget.0 {
+                bail!(
+                    "more than {} of files; too large for a sandbox preview",
+                    indicatif::HumanBytes(SANDBOX_COPY_BYTES_LIMIT)
+                );
+            }
+            budget.0 -= len;
+            fs::copy(entry.path(), &target)?;
+        }
+        // Sockets, FIFOs and devices are left out
+    }
+    Ok(())
+}
+
+/// Record the state of every entry below `root`, keyed by relative path
+pub fn snapshot(root: &Path) -> Result<BTreeMap<PathBuf, EntryState>> {
+    let mut entries = BTreeMap::new();
+    snapshot_dir(root, Path::new(""), &mut entries)?;
+    Ok(entries)
+}
+
+fn snapshot_dir(
+    root: &Path,
+    relative: &Path,
+    entries: &mut BTreeMap<PathBuf, EntryState>,
+) -> Result<()> {
+    let dir = root.join(relative);
+    let listing = match fs::read_dir(&dir) {
+        Ok(listing) => listing,
+        // Unreadable directories (e.g. chmod 000 by the command) are recorded as-is
+        Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    for entry in listing {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        let metadata = fs::symlink_metadata(entry.path())?;
+        let state = if metadata.file_type().is_symlink() {
+            EntryState::Symlink(fs::read_link(entry.path())?)
+        } else if metadata.is_dir() {
+            snapshot_dir(root, &path, entries)?;
+            EntryState::Dir
+        } else {
+            EntryState::File {
+                len: metadata.len(),
+                modified: metadata.modified().ok(),
+                mode: file_mode(&metadata),
+            }
+        };
+        entries.insert(path, state);
+    }
+    Ok(())
+}
+
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode()
+    }
+    #[cfg(not(unix))]
+    {
+        u32::from(metadata.permissions().readonly())
+    }
+}
+
+/// Compare two snapshots. Entries whose kind changed count as modified.
+pub fn diff_snapshots(
+    before: &BTreeMap<PathBuf, EntryState>,
+    after: &BTreeMap<PathBuf, EntryState>,
+) -> Vec<FileChange> {
+    let mut changes = Vec::new();
+    for (path, old) in before {
+        match after.get(path) {
+            None => changes.push(FileChange::Deleted(path.clone())),
+            Some(new) if new != old => changes.push(FileChange::Modified(path.clone())),
+            Some(_) => {}
+        }
+    }
+    for path in after.keys() {
+        if !before.contains_key(path) {
+            changes.push(FileChange::Created(path.clone()));
+        }
+    }
+    changes.sort_by(|a, b| change_path(a).cmp(change_path(b)));
+    changes
+}
+
+fn change_path(change: &FileChange) -> &Path {
+    match change {
+        FileChange::Created(path) | FileChange::Modified(path) | FileChange::Deleted(path) => path,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_copy_and_diff() {
+        let src = TempDir::new().unwrap();
+        fs::write(src.path().join("keep.txt"), "same").unwrap();
+        fs::write(src.path().join("edit.txt"), "old").unwrap();
+        fs::write(src.path().join("gone.txt"), "bye").unwrap();
+        fs::create_dir(src.path().join("sub")).unwrap();
+        fs::write(src.path().join("sub/inner.txt"), "x").unwrap();
+
+        let copy = TempDir::new().unwrap();
+        copy_tree(src.path(), copy.path()).unwrap();
+        let before = snapshot(copy.path()).unwrap();
+        assert_eq!(before, snapshot(copy.path()).unwrap());
+        assert_eq!(before.len(), 5);
+
+        fs::write(copy.path().join("edit.txt"), "longer content").unwrap();
+        fs::remove_file(copy.path().join("gone.txt")).unwrap();
+        fs::write(copy.path().join("sub/new.txt"), "new").unwrap();
+        let after = snapshot(copy.path()).unwrap();
+
+        assert_eq!(
+            diff_snapshots(&before, &after),
+            vec![
+                FileChange::Modified(PathBuf::from("edit.txt")),
+                FileChange::Deleted(PathBuf::from("gone.txt")),
+                FileChange::Created(PathBuf::from("sub/new.txt")),
+            ]
+        );
+        // The original directory is untouched
+        assert_eq!(
+            fs::read_to_string(src.path().join("edit.txt")).unwrap(),
+            "old"
+        );
+        assert!(src.path().join("gone.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_preview_leaves_working_directory_untouched() {
+        if detect_backend().await.is_err() {
+            // No bubblewrap or user namespaces here; nothing to exercise
+            return;
+        }
+        let cwd = TempDir::new().unwrap();
+        fs::write(cwd.path().join("a.txt"), "original").unwrap();
+
+        let report = preview_command(
+            "cat a.txt; echo changed > a.txt; touch b.txt",
+            &ExecutionShell::resolve(Some("sh")),
+            cwd.path(),
+            &CancellationToken::new(),
+        )
+        .await
+        .unwrap();
+
+        assert!(report.outcome.success(), "{:?}", report.outcome);
+        assert_eq!(report.outcome.stdout, "original");
+        assert_eq!(
+            report.changes,
+            vec![
+                FileChange::Modified(PathBuf::from("a.txt")),
+                FileChange::Created(PathBuf::from("b.txt")),
+            ]
+        );
+        assert_eq!(
+            fs::read_to_string(cwd.path().join("a.txt")).unwrap(),
+            "original"
+        );
+        assert!(!cwd.path().join("b.txt").exists());
+    }
+}
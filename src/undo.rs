@@ -0,0 +1,698 @@
+use crate::config::UndoConfig;
+use crate::validation::{ShellParser, ShellToken};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+const MANIFEST_FILE: &str = "manifest.json";
+const MB: u64 = 1024 * 1024;
+
+/// Commands that run another command given as their arguments
+const WRAPPERS: &[&str] = &["sudo", "doas", "command", "nohup", "time", "env", "nice"];
+
+/// Files saved before a command ran, restorable with `cliai undo`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub id: String,
+    pub command: String,
+    pub cwd: PathBuf,
+    pub created_at: DateTime<Local>,
+    /// Total size of the saved files in bytes
+    pub bytes: u64,
+    pub entries: Vec<SnapshotEntry>,
+}
+
+/// One saved path; `stored` is its copy's name inside the snapshot directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    pub original: PathBuf,
+    pub stored: String,
+}
+
+/// Paths that a command may overwrite, truncate, move or delete, resolved against `cwd`
+/// with globs and `~` expanded. Only existing paths are returned. Fails with a reason when
+/// the command changes files but its targets can't be worked out before it runs.
+pub fn affected_paths(command: &str, cwd: &Path) -> std::result::Result<Vec<PathBuf>, String> {
+    let tokens = ShellParser::new()
+        .parse(command)
+        .map_err(|e| format!("could not parse the command: {}", e))?;
+
+    let mut operands: Vec<Word> = Vec::new();
+    let mut segment: Vec<Word> = Vec::new();
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        match token {
+            ShellToken::Operator(op) if op == ">" || op == ">>" => {
+                if let Some(ShellToken::Operator(_)) | None = tokens.peek() {
+                    // `>&2` and similar duplicate a descriptor instead of naming a file
+                    continue;
+                }
+                if let Some(target) = tokens.next() {
+                    operands.push(Word::from_token(&target));
+                }
+            }
+            ShellToken::Operator(op) if op == "<" || op == "<<" => {
+                tokens.next();
+            }
+            ShellToken::Operator(_) => {
+                operands.extend(command_targets(&segment, cwd)?);
+                segment.clear();
+            }
+            token => segment.push(Word::from_token(&token)),
+        }
+    }
+    operands.extend(command_targets(&segment, cwd)?);
+
+    let mut paths: Vec<PathBuf> = Vec::new();
+    for word in operands {
+        if word.text.contains("$(") || word.text.contains('`') {
+            return Err(format!("`{}` uses command substitution", word.text));
+        }
+        if word.text.contains('$') && !word.single_quoted {
+            return Err(format!("`{}` depends on a shell variable", word.text));
+        }
+        for path in word.expand(cwd) {
+            if is_virtual_path(&path) || fs::symlink_metadata(&path).is_err() {
+                continue;
+            }
+            paths.push(path);
+        }
+    }
+
+    // Saving a directory already covers everything below it
+    paths.sort();
+    paths.dedup();
+    let roots: Vec<PathBuf> = paths
+        .iter()
+        .filter(|path| {
+            !paths
+                .iter()
+                .any(|other| other != *path && path.starts_with(other))
+        })
+        .cloned()
+        .collect();
+    Ok(roots)
+}
+
+/// A shell word and how it was quoted
+#[derive(Debug, Clone)]
+struct Word {
+    text: String,
+    quoted: bool,
+    single_quoted: bool,
+}
+
+impl Word {
+    fn from_token(token: &ShellToken) -> Self {
+        Self {
+            text: token.content().replace('\\', ""),
+            quoted: token.is_quoted(),
+            single_quoted: matches!(token, ShellToken::SingleQuoted(_)),
+        }
+    }
+
+    fn is_option(&self) -> bool {
+        !self.quoted && self.text.starts_with('-') && self.text.len() > 1
+    }
+
+    /// Resolve to absolute paths, expanding `~` and globs the way the shell would
+    fn expand(&self, cwd: &Path) -> Vec<PathBuf> {
+        let mut text = self.text.clone();
+        if !self.quoted && (text == "~" || text.starts_with("~/")) {
+            if let Some(home) = dirs::home_dir() {
+                text = format!("{}{}", home.display(), &text[1..]);
+            }
+        }
+        let path = cwd.join(&text);
+        if self.quoted || !text.contains(['*', '?', '[']) {
+            return vec![normalize(&path)];
+        }
+        let matches = expand_glob(&path);
+        if matches.is_empty() {
+            // An unmatched glob is passed on literally
+            vec![normalize(&path)]
+        } else {
+            matches
+        }
+    }
+}
+
+/// Targets of one simple command (no operators)
+fn command_targets(words: &[Word], cwd: &Path) -> std::result::Result<Vec<Word>, String> {
+    // Skip variable assignments and wrappers such as sudo, with their options
+    let mut start = 0;
+    while let Some(word) = words.get(start) {
+        let is_assignment = !word.quoted && word.text.contains('=') && !word.text.starts_with('=');
+        if is_assignment || WRAPPERS.contains(&word.text.as_str()) || word.is_option() {
+            start += 1;
+        } else {
+            break;
+        }
+    }
+    let Some(program) = words.get(start) else {
+        return Ok(Vec::new());
+    };
+    let program_name = Path::new(&program.text)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default()
+        .to_string();
+    let args = &words[start + 1..];
+
+    let targets = match program_name.as_str() {
+        "rm" | "rmdir" | "unlink" | "shred" | "tee" => operands(args, &[]),
+        "truncate" => operands(args, &["-s", "--size", "-r", "--reference"]),
+        "mv" | "cp" => {
+            if args
+                .iter()
+                .any(|arg| arg.text == "-t" || arg.text.starts_with("--target-directory"))
+            {
+                return Err(format!("`{} -t` is not supported", program_name));
+            }
+            let mut operands = operands(args, &["-S", "--suffix"]);
+            if operands.len() < 2 {
+                return Ok(Vec::new());
+            }
+            let destination = operands.pop().unwrap_or_else(|| unreachable!());
+            let mut targets = if program_name == "mv" {
+                operands.clone()
+            } else {
+                Vec::new()
+            };
+            if destination.expand(cwd).iter().any(|path| path.is_dir()) {
+                // Sources land inside an existing destination directory
+                targets.extend(operands.iter().map(|source| Word {
+                    text: format!(
+                        "{}/{}",
+                        destination.text.trim_end_matches('/'),
+                        Path::new(&source.text)
+                            .file_name()
+                            .and_then(|name| name.to_str())
+                            .unwrap_or_default()
+                    ),
+                    ..destination.clone()
+                }));
+            } else {
+                targets.push(destination);
+            }
+            targets
+        }
+        "sed" => sed_in_place_files(args),
+        "dd" => args
+            .iter()
+            .filter_map(|arg| {
+                arg.text.strip_prefix("of=").map(|target| Word {
+                    text: target.to_string(),
+                    ..arg.clone()
+                })
+            })
+            .collect(),
+        "find"
+            if args
+                .iter()
+                .any(|arg| arg.text == "-delete" || arg.text == "-exec") =>
+        {
+            return Err("the files `find` deletes or changes depend on the search".to_string());
+        }
+        "xargs" => {
+            return Err("`xargs` takes its arguments from its input".to_string());
+        }
+        _ => Vec::new(),
+    };
+    Ok(targets)
+}
+
+/// Non-option arguments; options listed in `with_value` consume the next argument
+fn operands(args: &[Word], with_value: &[&str]) -> Vec<Word> {
+    let mut result = Vec::new();
+    let mut args = args.iter();
+    let mut options_done = false;
+    while let Some(arg) = args.next() {
+        if options_done || !arg.is_option() {
+            result.push(arg.clone());
+        } else if arg.text == "--" {
+            options_done = true;
+        } else if with_value.contains(&arg.text.as_str()) {
+            args.next();
+        }
+    }
+    result
+}
+
+/// Files edited by `sed -i`; empty when sed does not edit in place
+fn sed_in_place_files(args: &[Word]) -> Vec<Word> {
+    let mut in_place = false;
+    let mut script_given = false;
+    let mut files = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if !arg.is_option() {
+            files.push(arg.clone());
+            continue;
+        }
+        if arg.text.starts_with("--in-place") {
+            in_place = true;
+        } else if arg.text == "--expression" || arg.text == "--file" {
+            script_given = true;
+            args.next();
+        } else if arg.text.starts_with("--expression=") || arg.text.starts_with("--file=") {
+            script_given = true;
+        } else if !arg.text.starts_with("--") {
+            for (index, flag) in arg.text.char_indices().skip(1) {
+                match flag {
+                    // Anything after -i is the backup suffix
+                    'i' => {
+                        in_place = true;
+                        break;
+                    }
+                    'e' | 'f' => {
+                        script_given = true;
+                        if index + 1 == arg.text.len() {
+                            args.next();
+                        }
+                        break;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    if !in_place {
+        return Vec::new();
+    }
+    if !script_given && !files.is_empty() {
+        files.remove(0);
+    }
+    files
+}
+
+fn is_virtual_path(path: &Path) -> bool {
+    ["/dev", "/proc", "/sys"]
+        .iter()
+        .any(|prefix| path.starts_with(prefix))
+}
+
+/// Remove `.` and `..` components without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            other => result.push(other),
+        }
+    }
+    result
+}
+
+/// Expand `*`, `?` and `[...]` in each component of an absolute path. Hidden entries
+/// only match patterns that start with a dot, as in sh.
+fn expand_glob(path: &Path) -> Vec<PathBuf> {
+    let mut candidates = vec![PathBuf::new()];
+    for component in normalize(path).components() {
+        let part = component.as_os_str().to_string_lossy().to_string();
+        if !part.contains(['*', '?', '[']) {
+            for candidate in &mut candidates {
+                candidate.push(component);
+            }
+            continue;
+        }
+        let pattern: Vec<char> = part.chars().collect();
+        let mut next = Vec::new();
+        for candidate in &candidates {
+            let Ok(entries) = fs::read_dir(candidate) else {
+                continue;
+            };
+            let mut names: Vec<String> = entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().to_string())
+                .filter(|name| !name.starts_with('.') || part.starts_with('.'))
+                .filter(|name| glob_match(&pattern, &name.chars().collect::<Vec<_>>()))
+                .collect();
+            names.sort();
+            next.extend(names.into_iter().map(|name| candidate.join(name)));
+        }
+        candidates = next;
+    }
+    candidates.retain(|path| fs::symlink_metadata(path).is_ok());
+    candidates
+}
+
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| glob_match(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && glob_match(&pattern[1..], &name[1..]),
+        Some('[') => {
+            let Some(end) = pattern.iter().skip(2).position(|&c| c == ']') else {
+                return name.first() == Some(&'[') && glob_match(&pattern[1..], &name[1..]);
+            };
+            let class = &pattern[1..end + 2];
+            let (negated, class) = match class.first() {
+                Some('!') | Some('^') => (true, &class[1..]),
+                _ => (false, class),
+            };
+            let Some(&ch) = name.first() else {
+                return false;
+            };
+            let mut matched = false;
+            let mut index = 0;
+            while index < class.len() {
+                if index + 2 < class.len() && class[index + 1] == '-' {
+                    matched |= class[index] <= ch && ch <= class[index + 2];
+                    index += 3;
+                } else {
+                    matched |= class[index] == ch;
+                    index += 1;
+                }
+            }
+            matched != negated && glob_match(&pattern[end + 3..], &name[1..])
+        }
+        Some(&literal) => name.first() == Some(&literal) && glob_match(&pattern[1..], &name[1..]),
+    }
+}
+
+/// Directory of undo snapshots, newest last by id
+pub struct UndoJournal {
+    dir: PathBuf,
+    settings: UndoConfig,
+}
+
+#[allow(dead_code)]
+impl UndoJournal {
+    /// Journal in the data directory (~/.local/share/cliai/undo on Linux)
+    pub fn new(settings: &UndoConfig) -> Result<Self> {
+        let data_dir = dirs::data_dir()
+            .or_else(dirs::config_dir)
+            .ok_or_else(|| anyhow!("Could not find data directory"))?;
+        Ok(Self::at(data_dir.join("cliai").join("undo"), settings))
+    }
+
+    pub fn at(dir: PathBuf, settings: &UndoConfig) -> Self {
+        Self {
+            dir,
+            settings: settings.clone(),
+        }
+    }
+
+    /// Copy `paths` into a new snapshot for `command`. Fails without saving anything when
+    /// they exceed the per-snapshot size limit.
+    pub fn snapshot(&self, command: &str, cwd: &Path, paths: &[PathBuf]) -> Result<Snapshot> {
+        let bytes = paths
+            .iter()
+            .map(|path| disk_usage(path))
+            .sum::<Result<u64>>()?;
+        let limit = self.settings.max_snapshot_mb * MB;
+        if bytes > limit {
+            bail!(
+                "the affected files take {}, over the {} snapshot limit (undo.max_snapshot_mb)",
+                indicatif::HumanBytes(bytes),
+                indicatif::HumanBytes(limit)
+            );
+        }
+
+        let created_at = Local::now();
+        let mut id = created_at.format("%Y%m%d-%H%M%S%3f").to_string();
+        while self.dir.join(&id).exists() {
+            id.push('_');
+        }
+        let snapshot_dir = self.dir.join(&id);
+        fs::create_dir_all(&snapshot_dir)
+            .with_context(|| format!("Could not create {}", snapshot_dir.display()))?;
+
+        let mut entries = Vec::new();
+        for (index, path) in paths.iter().enumerate() {
+            let stored = index.to_string();
+            if let Err(e) = copy_recursive(path, &snapshot_dir.join(&stored)) {
+                let _ = fs::remove_dir_all(&snapshot_dir);
+                return Err(e).with_context(|| format!("Could not save {}", path.display()));
+            }
+            entries.push(SnapshotEntry {
+                original: path.clone(),
+                stored,
+            });
+        }
+
+        let snapshot = Snapshot {
+            id,
+            command: command.to_string(),
+            cwd: cwd.to_path_buf(),
+            created_at,
+            bytes,
+            entries,
+        };
+        fs::write(
+            snapshot_dir.join(MANIFEST_FILE),
+            serde_json::to_string_pretty(&snapshot)?,
+        )?;
+        self.prune()?;
+        Ok(snapshot)
+    }
+
+    /// Snapshots, newest first
+    pub fn list(&self) -> Result<Vec<Snapshot>> {
+        let mut snapshots = Vec::new();
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(snapshots),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let manifest = entry?.path().join(MANIFEST_FILE);
+            // Directories without a readable manifest are incomplete snapshots
+            if let Ok(content) = fs::read_to_string(&manifest) {
+                if let Ok(snapshot) = serde_json::from_str::<Snapshot>(&content) {
+                    snapshots.push(snapshot);
+                }
+            }
+        }
+        snapshots.sort_by(|a, b| b.id.cmp(&a.id));
+        Ok(snapshots)
+    }
+
+    /// The `n`th most recent snapshot (1 is the latest)
+    pub fn get(&self, n: usize) -> Result<Snapshot> {
+        let snapshots = self.list()?;
+        match n.checked_sub(1).and_then(|index| snapshots.get(index)) {
+            Some(snapshot) => Ok(snapshot.clone()),
+            None if snapshots.is_empty() => Err(anyhow!("Nothing to undo")),
+            None => Err(anyhow!(
+                "There are only {} undo snapshot(s)",
+                snapshots.len()
+            )),
+        }
+    }
+
+    /// Put a snapshot's files back in place, replacing whatever is there now, and drop
+    /// the snapshot
+    pub fn restore(&self, snapshot: &Snapshot) -> Result<()> {
+        let snapshot_dir = self.dir.join(&snapshot.id);
+        for entry in &snapshot.entries {
+            let stored = snapshot_dir.join(&entry.stored);
+            if let Ok(metadata) = fs::symlink_metadata(&entry.original) {
+                if metadata.is_dir() {
+                    fs::remove_dir_all(&entry.original)?;
+                } else {
+                    fs::remove_file(&entry.original)?;
+                }
+            }
+            if let Some(parent) = entry.original.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if fs::rename(&stored, &entry.original).is_err() {
+                // Different filesystem
+                copy_recursive(&stored, &entry.original)
+                    .with_context(|| format!("Could not restore {}", entry.original.display()))?;
+            }
+        }
+        fs::remove_dir_all(&snapshot_dir)?;
+        Ok(())
+    }
+
+    /// Drop snapshots older than `max_age_days`, then the oldest ones until the journal
+    /// fits in `max_total_mb`
+    pub fn prune(&self) -> Result<()> {
+        let max_age = chrono::Duration::days(i64::from(self.settings.max_age_days));
+        let now = Local::now();
+        let mut total = 0;
+        for snapshot in self.list()? {
+            total += snapshot.bytes;
+            if now - snapshot.created_at > max_age || total > self.settings.max_total_mb * MB {
+                fs::remove_dir_all(self.dir.join(&snapshot.id))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Bytes of regular files at or below `path`
+fn disk_usage(path: &Path) -> Result<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+    if !metadata.is_dir() {
+        return Ok(if metadata.is_file() {
+            metadata.len()
+        } else {
+            0
+        });
+    }
+    let mut total = 0;
+    for entry in fs::read_dir(path)? {
+        total += disk_usage(&entry?.path())?;
+    }
+    Ok(total)
+}
+
+/// Copy a file, symlink or directory tree, keeping permissions
+fn copy_recursive(src: &Path, dst: &Path) -> Result<()> {
+    let metadata = fs::symlink_metadata(src)?;
+    if metadata.file_type().is_symlink() {
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(fs::read_link(src)?, dst)?;
+    } else if metadata.is_dir() {
+        fs::create_dir(dst)?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &dst.join(entry.file_name()))?;
+        }
+        fs::set_permissions(dst, metadata.permissions())?;
+    } else if metadata.is_file() {
+        fs::copy(src, dst)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn settings() -> UndoConfig {
+        UndoConfig::default()
+    }
+
+    #[test]
+    fn test_affected_paths() {
+        let cwd = TempDir::new().unwrap();
+        let root = cwd.path();
+        for name in ["a.log", "b.log", "notes.txt", "config.ini"] {
+            fs::write(root.join(name), name).unwrap();
+        }
+        fs::create_dir(root.join("build")).unwrap();
+        fs::write(root.join("build/out.o"), "o").unwrap();
+
+        let paths = |command: &str| affected_paths(command, root).unwrap();
+
+        assert_eq!(
+            paths("rm -f *.log"),
+            vec![root.join("a.log"), root.join("b.log")]
+        );
+        assert_eq!(
+            paths("rm -rf build build/out.o missing"),
+            vec![root.join("build")]
+        );
+        assert_eq!(
+            paths("mv notes.txt config.ini"),
+            vec![root.join("config.ini"), root.join("notes.txt")]
+        );
+        // Copying into a directory only replaces the same name inside it
+        assert!(paths("cp notes.txt build").is_empty());
+        assert_eq!(
+            paths("cp -f a.log build/out.o"),
+            vec![root.join("build/out.o")]
+        );
+        assert_eq!(
+            paths("sed -i.bak 's/a/b/' config.ini"),
+            vec![root.join("config.ini")]
+        );
+        assert!(paths("sed 's/a/b/' config.ini").is_empty());
+        assert_eq!(
+            paths("sort notes.txt > config.ini && echo done >> /dev/null"),
+            vec![root.join("config.ini")]
+        );
+        assert_eq!(paths("sudo rm './notes.txt'"), vec![root.join("notes.txt")]);
+        assert!(paths("ls -la && cat notes.txt").is_empty());
+
+        assert!(affected_paths("rm $TARGET", root).is_err());
+        assert!(affected_paths("find . -name '*.o' -delete", root).is_err());
+        assert!(affected_paths("ls | xargs rm", root).is_err());
+    }
+
+    #[test]
+    fn test_glob_match() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert!(glob_match(&chars("*.log"), &chars("a.log")));
+        assert!(!glob_match(&chars("*.log"), &chars("a.txt")));
+        assert!(glob_match(&chars("file?.[0-9]"), &chars("file1.7")));
+        assert!(!glob_match(&chars("[!a]*"), &chars("abc")));
+    }
+
+    #[test]
+    fn test_snapshot_and_restore() {
+        let work = TempDir::new().unwrap();
+        let journal_dir = TempDir::new().unwrap();
+        let journal = UndoJournal::at(journal_dir.path().to_path_buf(), &settings());
+
+        let file = work.path().join("data.txt");
+        let dir = work.path().join("dir");
+        fs::write(&file, "precious").unwrap();
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join("inner"), "inner").unwrap();
+
+        let snapshot = journal
+            .snapshot(
+                "rm -r data.txt dir",
+                work.path(),
+                &[file.clone(), dir.clone()],
+            )
+            .unwrap();
+        assert_eq!(snapshot.bytes, 13);
+        fs::remove_file(&file).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        let latest = journal.get(1).unwrap();
+        assert_eq!(latest, snapshot);
+        assert!(journal.get(2).is_err());
+
+        journal.restore(&latest).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "precious");
+        assert_eq!(fs::read_to_string(dir.join("inner")).unwrap(), "inner");
+        assert!(journal.list().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_limits() {
+        let work = TempDir::new().unwrap();
+        let journal_dir = TempDir::new().unwrap();
+        let file = work.path().join("big.bin");
+        fs::write(&file, vec![0u8; 2 * MB as usize]).unwrap();
+
+        let tight = UndoConfig {
+            max_snapshot_mb: 1,
+            ..settings()
+        };
+        let journal = UndoJournal::at(journal_dir.path().to_path_buf(), &tight);
+        assert!(journal
+            .snapshot("rm big.bin", work.path(), std::slice::from_ref(&file))
+            .is_err());
+        assert!(journal.list().unwrap().is_empty());
+
+        // Only the newest snapshots that fit in the total budget are kept
+        let budget = UndoConfig {
+            max_snapshot_mb: 3,
+            max_total_mb: 3,
+            ..settings()
+        };
+        let journal = UndoJournal::at(journal_dir.path().to_path_buf(), &budget);
+        journal
+            .snapshot("first", work.path(), std::slice::from_ref(&file))
+            .unwrap();
+        let second = journal
+            .snapshot("second", work.path(), std::slice::from_ref(&file))
+            .unwrap();
+        assert_eq!(journal.list().unwrap(), vec![second]);
+    }
+}
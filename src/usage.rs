@@ -0,0 +1,467 @@
+use crate::providers::{ProviderResponse, ProviderType, TokenUsage};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+/// Price of a model in USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+/// List prices (USD per million input/output tokens) of common cloud models. Entries
+/// match model names by prefix, so dated snapshots such as `gpt-4o-2024-08-06` are
+/// covered; the config's `price_table` overrides or extends them.
+const BUILTIN_PRICES: &[(&str, f64, f64)] = &[
+    ("gpt-4o", 2.50, 10.00),
+    ("gpt-4o-mini", 0.15, 0.60),
+    ("gpt-4.1", 2.00, 8.00),
+    ("gpt-4.1-mini", 0.40, 1.60),
+    ("gpt-4.1-nano", 0.10, 0.40),
+    ("o3-mini", 1.10, 4.40),
+    ("claude-3-haiku", 0.25, 1.25),
+    ("claude-3-5-haiku", 0.80, 4.00),
+    ("claude-3-5-sonnet", 3.00, 15.00),
+    ("claude-3-7-sonnet", 3.00, 15.00),
+    ("claude-sonnet-4", 3.00, 15.00),
+    ("claude-opus-4", 15.00, 75.00),
+    ("gemini-1.5-flash", 0.075, 0.30),
+    ("gemini-1.5-pro", 1.25, 5.00),
+    ("gemini-2.0-flash", 0.10, 0.40),
+    ("gemini-2.0-flash-lite", 0.075, 0.30),
+    ("gemini-2.5-flash", 0.30, 2.50),
+    ("gemini-2.5-pro", 1.25, 10.00),
+    ("command-r", 0.15, 0.60),
+    ("command-r-plus", 2.50, 10.00),
+    ("command-r7b", 0.0375, 0.15),
+    ("command-a", 2.50, 10.00),
+];
+
+/// Model prices used to turn token counts into cost
+#[derive(Debug, Clone)]
+pub struct PriceTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+#[allow(dead_code)]
+impl PriceTable {
+    /// Built-in prices with the configured overrides applied on top
+    pub fn new(overrides: &HashMap<String, ModelPrice>) -> Self {
+        let mut prices: HashMap<String, ModelPrice> = BUILTIN_PRICES
+            .iter()
+            .map(|(model, input, output)| {
+                (
+                    model.to_string(),
+                    ModelPrice {
+                        input_per_mtok: *input,
+                        output_per_mtok: *output,
+                    },
+                )
+            })
+            .collect();
+        prices.extend(
+            overrides
+                .iter()
+                .map(|(model, price)| (model.clone(), *price)),
+        );
+        Self { prices }
+    }
+
+    /// Price of a model: an exact entry, otherwise the longest entry the name starts with
+    pub fn lookup(&self, model: &str) -> Option<ModelPrice> {
+        let model = model.strip_prefix("models/").unwrap_or(model);
+        if let Some(price) = self.prices.get(model) {
+            return Some(*price);
+        }
+        self.prices
+            .iter()
+            .filter(|(name, _)| model.starts_with(name.as_str()))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, price)| *price)
+    }
+
+    /// Cost of a request in USD. Local models are free; `None` means the model is not
+    /// in the price table.
+    pub fn cost(&self, provider: &ProviderType, model: &str, usage: &TokenUsage) -> Option<f64> {
+        if *provider == ProviderType::Local {
+            return Some(0.0);
+        }
+        let price = self.lookup(model)?;
+        Some(
+            (usage.prompt_tokens as f64 * price.input_per_mtok
+                + usage.completion_tokens as f64 * price.output_per_mtok)
+                / 1_000_000.0,
+        )
+    }
+}
+
+/// Daily usage of one provider, agent and model
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageRecord {
+    /// Local date, YYYY-MM-DD
+    pub date: String,
+    pub provider: String,
+    pub agent: String,
+    pub model: String,
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost_usd: f64,
+}
+
+impl UsageRecord {
+    /// Month of the record, YYYY-MM
+    pub fn month(&self) -> &str {
+        self.date.get(..7).unwrap_or(&self.date)
+    }
+}
+
+/// Summed usage of a group of records
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub cost_usd: f64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, record: &UsageRecord) {
+        self.requests += record.requests;
+        self.prompt_tokens += record.prompt_tokens;
+        self.completion_tokens += record.completion_tokens;
+        self.cost_usd += record.cost_usd;
+    }
+}
+
+/// Sum records by the key `group` returns, skipping records it maps to `None`.
+/// Groups are returned in key order.
+pub fn summarize<K, F>(records: &[UsageRecord], group: F) -> Vec<(K, UsageTotals)>
+where
+    K: Ord,
+    F: Fn(&UsageRecord) -> Option<K>,
+{
+    let mut totals: BTreeMap<K, UsageTotals> = BTreeMap::new();
+    for record in records {
+        if let Some(key) = group(record) {
+            totals.entry(key).or_default().add(record);
+        }
+    }
+    totals.into_iter().collect()
+}
+
+/// Per-day usage records, shared between CLI invocations through a JSON file in the
+/// data directory. Updates hold an exclusive file lock like the provider health store.
+#[derive(Debug, Clone)]
+pub struct UsageLedger {
+    path: PathBuf,
+}
+
+#[allow(dead_code)]
+impl UsageLedger {
+    /// Ledger at the default location in the data directory
+    pub fn new() -> Result<Self> {
+        Ok(Self::at(Self::get_usage_path()?))
+    }
+
+    /// Ledger at a specific file
+    pub fn at(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Load all records under a shared lock. A missing or unreadable file yields no records.
+    pub fn load(&self) -> Vec<UsageRecord> {
+        let Ok(mut file) = File::open(&self.path) else {
+            return Vec::new();
+        };
+        if file.lock_shared().is_err() {
+            return Vec::new();
+        }
+
+        let mut content = String::new();
+        let records = match file.read_to_string(&mut content) {
+            Ok(_) => serde_json::from_str(&content).unwrap_or_default(),
+            Err(_) => Vec::new(),
+        };
+        let _ = file.unlock();
+        records
+    }
+
+    /// Add one request to the day's record for its provider, agent and model
+    pub fn record(
+        &self,
+        date: &str,
+        provider: &str,
+        agent: &str,
+        model: &str,
+        usage: &TokenUsage,
+        cost_usd: f64,
+    ) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)?;
+        file.lock()
+            .map_err(|e| anyhow!("Failed to lock usage file: {}", e))?;
+
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        let mut records: Vec<UsageRecord> = serde_json::from_str(&content).unwrap_or_default();
+
+        let index = match records.iter().position(|r| {
+            r.date == date && r.provider == provider && r.agent == agent && r.model == model
+        }) {
+            Some(index) => index,
+            None => {
+                records.push(UsageRecord {
+                    date: date.to_string(),
+                    provider: provider.to_string(),
+                    agent: agent.to_string(),
+                    model: model.to_string(),
+                    ..Default::default()
+                });
+                records.len() - 1
+            }
+        };
+        let record = &mut records[index];
+        record.requests += 1;
+        record.prompt_tokens += usage.prompt_tokens;
+        record.completion_tokens += usage.completion_tokens;
+        record.cost_usd += cost_usd;
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(serde_json::to_string_pretty(&records)?.as_bytes())?;
+        file.flush()?;
+        file.unlock()?;
+
+        Ok(())
+    }
+
+    /// Total cost of a month (YYYY-MM) in USD
+    pub fn month_cost(&self, month: &str) -> f64 {
+        self.load()
+            .iter()
+            .filter(|record| record.month() == month)
+            .map(|record| record.cost_usd)
+            .sum()
+    }
+
+    /// Today's local date, YYYY-MM-DD
+    pub fn today() -> String {
+        chrono::Local::now().format("%Y-%m-%d").to_string()
+    }
+
+    /// Current local month, YYYY-MM
+    pub fn current_month() -> String {
+        chrono::Local::now().format("%Y-%m").to_string()
+    }
+
+    /// Get usage file path
+    pub fn get_usage_path() -> Result<PathBuf> {
+        let data_dir = dirs::data_dir()
+            .or_else(dirs::config_dir)
+            .ok_or_else(|| anyhow!("Could not find data directory"))?;
+        Ok(data_dir.join("cliai").join("usage.json"))
+    }
+}
+
+/// Prices requests and records them in the ledger, and enforces the monthly budget
+#[derive(Debug, Clone)]
+pub struct UsageTracker {
+    ledger: UsageLedger,
+    prices: PriceTable,
+    monthly_budget_usd: Option<f64>,
+}
+
+#[allow(dead_code)]
+impl UsageTracker {
+    pub fn new(ledger: UsageLedger, prices: PriceTable, monthly_budget_usd: Option<f64>) -> Self {
+        Self {
+            ledger,
+            prices,
+            monthly_budget_usd,
+        }
+    }
+
+    /// Record a completed request and return its cost (`None` if the model is unpriced)
+    pub fn record(&self, agent: &str, response: &ProviderResponse) -> Result<Option<f64>> {
+        let usage = response.usage.unwrap_or_default();
+        let cost = self
+            .prices
+            .cost(&response.provider, &response.model, &usage);
+        self.ledger.record(
+            &UsageLedger::today(),
+            &format!("{:?}", response.provider),
+            agent,
+            &response.model,
+            &usage,
+            cost.unwrap_or(0.0),
+        )?;
+        Ok(cost)
+    }
+
+    /// `(spent, budget)` when this month's spending has reached the budget
+    pub fn budget_exceeded(&self) -> Option<(f64, f64)> {
+        let budget = self.monthly_budget_usd?;
+        let spent = self.ledger.month_cost(&UsageLedger::current_month());
+        (spent >= budget).then_some((spent, budget))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(prompt_tokens: u64, completion_tokens: u64) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens,
+            completion_tokens,
+        }
+    }
+
+    #[test]
+    fn test_price_lookup_prefers_longest_prefix() {
+        let prices = PriceTable::new(&HashMap::new());
+        assert_eq!(
+            prices
+                .lookup("gpt-4o-mini-2024-07-18")
+                .unwrap()
+                .input_per_mtok,
+            0.15
+        );
+        assert_eq!(
+            prices.lookup("gpt-4o-2024-08-06").unwrap().input_per_mtok,
+            2.50
+        );
+        assert_eq!(
+            prices
+                .lookup("models/gemini-2.0-flash")
+                .unwrap()
+                .output_per_mtok,
+            0.40
+        );
+        assert!(prices.lookup("llama3").is_none());
+
+        let cost = prices
+            .cost(
+                &ProviderType::OpenAI,
+                "gpt-4o-mini",
+                &usage(1_000_000, 500_000),
+            )
+            .unwrap();
+        assert!((cost - 0.45).abs() < 1e-9);
+        // Local models are free even when unpriced
+        assert_eq!(
+            prices.cost(&ProviderType::Local, "llama3", &usage(10, 10)),
+            Some(0.0)
+        );
+        assert!(prices
+            .cost(&ProviderType::OpenAI, "llama3", &usage(10, 10))
+            .is_none());
+    }
+
+    #[test]
+    fn test_price_overrides() {
+        let mut overrides = HashMap::new();
+        overrides.insert(
+            "gpt-4o-mini".to_string(),
+            ModelPrice {
+                input_per_mtok: 1.0,
+                output_per_mtok: 2.0,
+            },
+        );
+        overrides.insert(
+            "my-model".to_string(),
+            ModelPrice {
+                input_per_mtok: 0.5,
+                output_per_mtok: 0.5,
+            },
+        );
+        let prices = PriceTable::new(&overrides);
+        assert_eq!(prices.lookup("gpt-4o-mini").unwrap().input_per_mtok, 1.0);
+        assert_eq!(prices.lookup("my-model-v2").unwrap().output_per_mtok, 0.5);
+    }
+
+    #[test]
+    fn test_ledger_aggregates_per_day() {
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = UsageLedger::at(dir.path().join("usage.json"));
+        assert!(ledger.load().is_empty());
+
+        ledger
+            .record(
+                "2026-01-31",
+                "OpenAI",
+                "CLIAI",
+                "gpt-4o",
+                &usage(100, 10),
+                0.5,
+            )
+            .unwrap();
+        ledger
+            .record(
+                "2026-01-31",
+                "OpenAI",
+                "CLIAI",
+                "gpt-4o",
+                &usage(50, 5),
+                0.25,
+            )
+            .unwrap();
+        ledger
+            .record(
+                "2026-02-01",
+                "Local",
+                "ShellExpert",
+                "mistral",
+                &usage(7, 3),
+                0.0,
+            )
+            .unwrap();
+
+        let records = ledger.load();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].requests, 2);
+        assert_eq!(records[0].prompt_tokens, 150);
+        assert!((ledger.month_cost("2026-01") - 0.75).abs() < 1e-9);
+        assert_eq!(ledger.month_cost("2026-02"), 0.0);
+
+        let by_month = summarize(&records, |r| Some(r.month().to_string()));
+        assert_eq!(by_month.len(), 2);
+        assert_eq!(by_month[0].0, "2026-01");
+        assert_eq!(by_month[1].1.completion_tokens, 3);
+    }
+
+    #[test]
+    fn test_budget_exceeded() {
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = UsageLedger::at(dir.path().join("usage.json"));
+        let tracker =
+            UsageTracker::new(ledger.clone(), PriceTable::new(&HashMap::new()), Some(0.01));
+        assert!(tracker.budget_exceeded().is_none());
+
+        let response = ProviderResponse::new(
+            "hi".to_string(),
+            Some(usage(10_000, 0)),
+            "gpt-4o",
+            ProviderType::OpenAI,
+        );
+        let cost = tracker.record("CLIAI", &response).unwrap().unwrap();
+        assert!((cost - 0.025).abs() < 1e-9);
+
+        let (spent, budget) = tracker.budget_exceeded().unwrap();
+        assert!(spent >= budget);
+        assert_eq!(ledger.load()[0].provider, "OpenAI");
+    }
+}
@@ -1,3 +1,4 @@
+use crate::os_context::Shell;
 use crate::quoting::QuotingCorrector;
 use anyhow::Result;
 use regex::Regex;
@@ -41,6 +42,8 @@ pub enum SecurityWarning {
     SystemModification(String),
     /// Command could be a fork bomb or similar
     DangerousPattern(String),
+    /// Command looks written for another shell and may do something else in this one
+    ShellMismatch(String),
 }
 
 /// Severity levels for safety warnings
@@ -488,6 +491,8 @@ pub struct DefaultCommandValidator {
     placeholder_patterns: Vec<Regex>,
     /// Quoting corrector for proper shell quoting
     quoting_corrector: QuotingCorrector,
+    /// Shell the command will run in; decides which syntax is accepted
+    shell: Shell,
 }
 
 #[allow(dead_code)]
@@ -549,9 +554,150 @@ impl DefaultCommandValidator {
             hallucinated_flags,
             placeholder_patterns,
             quoting_corrector: QuotingCorrector::new(),
+            shell: Shell::Bash,
         }
     }
 
+    /// Check commands against the syntax of `shell` instead of bash
+    pub fn with_shell(mut self, shell: Shell) -> Self {
+        self.shell = match shell {
+            Shell::Unknown => Shell::Sh,
+            shell => shell,
+        };
+        self
+    }
+
+    /// Check syntax that only works in some shells against the target shell. Variable
+    /// exports are translated; other mismatches are reported as syntax errors.
+    fn check_shell_syntax(
+        &self,
+        command: &str,
+    ) -> std::result::Result<(String, Vec<String>), Vec<ValidationError>> {
+        let Ok(tokens) = ShellParser::new().parse(command) else {
+            return Ok((command.to_string(), Vec::new()));
+        };
+        let fish = self.shell == Shell::Fish;
+        let shell_name = format!("{:?}", self.shell).to_lowercase();
+        let mut errors = Vec::new();
+        let mut error = |message: String| {
+            let error = ValidationError::SyntaxError(message);
+            if !errors.contains(&error) {
+                errors.push(error);
+            }
+        };
+
+        let mut command_position = true;
+        for token in &tokens {
+            match token {
+                ShellToken::Operator(op) => {
+                    if fish && op == "<<" {
+                        error("fish has no heredocs (<<); pipe from printf or echo".to_string());
+                    }
+                    command_position = !matches!(op.as_str(), ">" | ">>" | "<" | "<<");
+                    continue;
+                }
+                ShellToken::SingleQuoted(_) => {}
+                ShellToken::Unquoted(text) | ShellToken::DoubleQuoted(text) => {
+                    if fish && text.contains('`') {
+                        error("fish has no backtick substitution; use (command)".to_string());
+                    }
+                    if fish && text.contains("${") {
+                        error("fish expands variables as {$name}, not ${name}".to_string());
+                    }
+                }
+            }
+            if command_position && !token.is_quoted() {
+                let word = token.content();
+                match word {
+                    "then" | "fi" | "do" | "done" | "esac" if fish => error(format!(
+                        "`{}` is POSIX shell syntax; fish blocks close with `end`",
+                        word
+                    )),
+                    "[[" if fish || self.shell == Shell::Sh => {
+                        error(format!("{} has no [[ ]]; use [ ] or test", shell_name))
+                    }
+                    "function" if self.shell == Shell::Sh => {
+                        error("sh has no `function` keyword; use name() { ... }".to_string())
+                    }
+                    "and" | "or" | "not" | "end" if !fish => error(format!(
+                        "`{}` is fish syntax; {} uses {}",
+                        word,
+                        shell_name,
+                        match word {
+                            "and" => "&&",
+                            "or" => "||",
+                            "not" => "!",
+                            _ => "fi, done or }",
+                        }
+                    )),
+                    _ => {}
+                }
+            }
+            // Words after `then`, `do` etc. are commands again
+            command_position = matches!(
+                token.content(),
+                "if" | "while"
+                    | "until"
+                    | "then"
+                    | "else"
+                    | "do"
+                    | "and"
+                    | "or"
+                    | "not"
+                    | "!"
+                    | "{"
+                    | "sudo"
+            ) && !token.is_quoted();
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        // fish has no `export NAME=value`; its `set -x NAME value` does the same. The
+        // other direction is left to `shell_mismatch_warnings`, as `set -x` is valid there.
+        if fish {
+            let regex = Regex::new(
+                r#"(^|[;&|]\s*)export\s+([A-Za-z_][A-Za-z0-9_]*)=("[^"]*"|'[^']*'|[^\s;&|]+)"#,
+            )
+            .unwrap();
+            let rewritten = regex.replace_all(command, "${1}set -x $2 $3").to_string();
+            if rewritten != command {
+                return Ok((
+                    rewritten,
+                    vec!["Rewrote export as set -x for fish".to_string()],
+                ));
+            }
+        }
+        Ok((command.to_string(), Vec::new()))
+    }
+
+    /// Flag syntax that runs in this shell but most likely means what it does in fish:
+    /// `set -x NAME value` exports a variable there, and turns on command tracing and
+    /// sets the positional parameters in POSIX shells
+    fn shell_mismatch_warnings(&self, command: &str) -> Vec<SecurityWarning> {
+        if self.shell == Shell::Fish {
+            return Vec::new();
+        }
+        let regex = Regex::new(
+            r#"(?:^|[;&|]\s*)set\s+-(?:gx|xg|x)\s+([A-Za-z_][A-Za-z0-9_]*)\s+("[^"]*"|'[^']*'|[^\s;&|]+)\s*(?:$|[;&|])"#,
+        )
+        .unwrap();
+        regex
+            .captures_iter(command)
+            .map(|captures| {
+                SecurityWarning::ShellMismatch(format!(
+                    "`set -x {} {}` looks like fish syntax; in {} it turns on command tracing instead of setting {}. Use export {}={} to set the variable",
+                    &captures[1],
+                    &captures[2],
+                    format!("{:?}", self.shell).to_lowercase(),
+                    &captures[1],
+                    &captures[1],
+                    &captures[2]
+                ))
+            })
+            .collect()
+    }
+
     /// Check if command contains dangerous patterns using enhanced safety checker
     fn is_dangerous(&self, command: &str) -> Vec<SecurityWarning> {
         let safety_result = self.safety_checker.check_command(command);
@@ -882,13 +1028,28 @@ impl CommandValidator for DefaultCommandValidator {
             return ValidationResult::Valid(trimmed.to_string());
         }
 
+        // Check the syntax of the shell the command will run in
+        let (shell_checked, mut fixes) = match self.check_shell_syntax(trimmed) {
+            Ok(result) => result,
+            Err(errors) => return ValidationResult::Invalid(trimmed.to_string(), errors),
+        };
+        let trimmed = shell_checked.as_str();
+        let shell_warnings = self.shell_mismatch_warnings(trimmed);
+
         // Skip most validation for natural language queries
         if !self.looks_like_shell_command(trimmed) {
+            if !shell_warnings.is_empty() {
+                return ValidationResult::Sensitive(trimmed.to_string(), shell_warnings);
+            }
+            if !fixes.is_empty() {
+                return ValidationResult::Rewritten(trimmed.to_string(), fixes);
+            }
             return ValidationResult::Valid(trimmed.to_string());
         }
 
         // Check for dangerous patterns FIRST (highest priority)
-        let warnings = self.is_dangerous(trimmed);
+        let mut warnings = self.is_dangerous(trimmed);
+        warnings.extend(shell_warnings);
         if !warnings.is_empty() {
             return ValidationResult::Sensitive(trimmed.to_string(), warnings);
         }
@@ -905,7 +1066,6 @@ impl CommandValidator for DefaultCommandValidator {
 
         // Standardize file existence checks and check for improvements
         let standardized = self.standardize_file_existence_checks(trimmed);
-        let mut fixes = Vec::new();
 
         if standardized != trimmed {
             fixes.push("Standardized file existence check format".to_string());
@@ -1330,6 +1490,71 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_shell_specific_syntax() {
+        let fish = DefaultCommandValidator::new().with_shell(Shell::Fish);
+        match fish.validate("export EDITOR=vim && echo ok") {
+            ValidationResult::Rewritten(cmd, fixes) => {
+                assert_eq!(cmd, "set -x EDITOR vim && echo ok");
+                assert_eq!(fixes, vec!["Rewrote export as set -x for fish".to_string()]);
+            }
+            other => panic!("Expected Rewritten, got {:?}", other),
+        }
+        assert!(matches!(
+            fish.validate("for f in *.txt; do echo $f; done"),
+            ValidationResult::Invalid(_, _)
+        ));
+        assert!(matches!(
+            fish.validate("echo `date`"),
+            ValidationResult::Invalid(_, _)
+        ));
+        assert!(matches!(
+            fish.validate("make; and echo built"),
+            ValidationResult::Valid(_)
+        ));
+        // Keywords inside arguments are not commands
+        assert!(matches!(
+            fish.validate("echo done"),
+            ValidationResult::Valid(_)
+        ));
+
+        let bash = DefaultCommandValidator::new();
+        match bash.validate("make; and echo built") {
+            ValidationResult::Invalid(_, errors) => {
+                assert!(
+                    matches!(&errors[0], ValidationError::SyntaxError(msg) if msg.contains("&&"))
+                )
+            }
+            other => panic!("Expected Invalid, got {:?}", other),
+        }
+        // Valid xtrace in POSIX shells: flagged as a likely fish-ism, not rewritten
+        match bash.validate("set -x EDITOR vim") {
+            ValidationResult::Sensitive(cmd, warnings) => {
+                assert_eq!(cmd, "set -x EDITOR vim");
+                assert!(matches!(
+                    &warnings[..],
+                    [SecurityWarning::ShellMismatch(msg)] if msg.contains("export EDITOR=vim")
+                ));
+            }
+            other => panic!("Expected Sensitive, got {:?}", other),
+        }
+        assert!(matches!(
+            bash.validate("set -x"),
+            ValidationResult::Valid(cmd) if cmd == "set -x"
+        ));
+        assert!(!matches!(
+            bash.validate("for f in *.txt; do echo \"$f\"; done"),
+            ValidationResult::Invalid(_, errors)
+                if errors.iter().any(|e| matches!(e, ValidationError::SyntaxError(_)))
+        ));
+
+        let sh = DefaultCommandValidator::new().with_shell(Shell::Sh);
+        assert!(matches!(
+            sh.validate("[[ -f a.txt ]] && echo yes"),
+            ValidationResult::Invalid(_, _)
+        ));
+    }
+
     #[test]
     fn test_valid_command() {
         let validator = DefaultCommandValidator::new();
@@ -2222,6 +2447,8 @@ mod format_tests {
             api_token: None,
             use_cloud: false,
             backend_url: "https://api.cliai.com".to_string(),
+            cache_enabled: false,
+            ..Config::default()
         };
         let history = History { turns: vec![] };
 